@@ -0,0 +1,132 @@
+extern crate getopts;
+extern crate sick_lib;
+
+use getopts::Options;
+
+use sick_lib::disassembler::source::{disassemble, disassemble_bytes};
+use sick_lib::diagnostic::{self, Code, Diagnostic};
+use sick_lib::diagnostic::reporter::{Reporter, MessageFormat, EXIT_ERRORS, EXIT_USAGE};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process;
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] FILE", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn usage_error(program: &str, opts: Options, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    print_usage(program, opts);
+    process::exit(EXIT_USAGE);
+}
+
+/// Reads the hex digits of a text record body, whitespace is ignored
+fn hex_bytes(text: &str) -> Result<Vec<u8>, String> {
+    let digits = text.chars().filter(|c| !c.is_whitespace()).collect::<Vec<char>>();
+    if digits.len() % 2 != 0 {
+        return Err("odd number of hex digits".to_owned());
+    }
+    digits.chunks(2)
+        .map(|pair| {
+            let pair = pair.iter().collect::<String>();
+            u8::from_str_radix(&pair, 16).map_err(|_| format!("`{}` isn't a hex byte", pair))
+        })
+        .collect()
+}
+
+/// Program name of raw input, the file name cut to the 6 characters of an H record
+fn raw_name(file: &str) -> String {
+    let stem = Path::new(file).file_stem().and_then(|stem| stem.to_str()).unwrap_or("");
+    let name = stem.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(6)
+        .collect::<String>()
+        .to_uppercase();
+    match name.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => name,
+        _ => "RAW".to_owned(),
+    }
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optopt("o", "output", "write the source to a file instead of stdout", "name");
+    opts.optflag("", "raw", "the input is the hex bytes of a text record, not an object file");
+    opts.optopt("a", "address", "load address of raw input, in hex", "ADDR");
+    opts.optopt("", "message-format", "how diagnostics are printed", "human|json");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => usage_error(&program, opts, &f.to_string()),
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let format = match matches.opt_str("message-format").as_deref() {
+        None | Some("human") => MessageFormat::Human,
+        Some("json") => MessageFormat::Json,
+        Some(other) => {
+            usage_error(&program,
+                        opts,
+                        &format!("unknown message format `{}`, expected human or json", other))
+        }
+    };
+
+    let address = match matches.opt_str("address").map(|addr| u32::from_str_radix(&addr, 16)) {
+        None => 0,
+        Some(Ok(addr)) if addr <= 0xFFFFF => addr,
+        Some(_) => usage_error(&program, opts, "--address takes a hex address below 100000"),
+    };
+
+    let file = match matches.free.len() {
+        0 => usage_error(&program, opts, "no input file"),
+        1 => matches.free[0].clone(),
+        _ => usage_error(&program, opts, "only one input file is disassembled at a time"),
+    };
+
+    let mut reporter = Reporter::new(format, None, false);
+    let text = match fs::read_to_string(&file) {
+        Ok(text) => text,
+        Err(e) => {
+            reporter.report(&[Diagnostic::error(Code::Io,
+                                                format!("can't read `{}`: {}", file, e))]);
+            reporter.finish();
+            process::exit(EXIT_ERRORS);
+        }
+    };
+    reporter.sources.add_file(&file, text.lines().map(|line| line.to_owned()).collect());
+
+    let source = if matches.opt_present("raw") {
+        hex_bytes(&text).map(|bytes| disassemble_bytes(&raw_name(&file), address, &bytes))
+            .map_err(|e| vec![Diagnostic::error(Code::InvalidRecord, e)])
+    } else {
+        disassemble(&text)
+    };
+    let source = match source {
+        Ok(source) => source,
+        Err(mut errs) => {
+            diagnostic::set_file(&mut errs, &file);
+            reporter.report(&errs);
+            reporter.finish();
+            process::exit(EXIT_ERRORS);
+        }
+    };
+    reporter.finish();
+
+    match matches.opt_str("o") {
+        Some(output) => {
+            if let Err(e) = fs::write(&output, source) {
+                reporter.report(&[Diagnostic::error(Code::Io,
+                                                    format!("can't write `{}`: {}", output, e))]);
+                process::exit(EXIT_ERRORS);
+            }
+        }
+        None => print!("{}", source),
+    }
+}
@@ -0,0 +1,101 @@
+extern crate getopts;
+extern crate sick_lib;
+
+use getopts::Options;
+
+use sick_lib::linker::loader::link;
+use sick_lib::diagnostic::{Code, Diagnostic};
+use sick_lib::diagnostic::reporter::{Reporter, MessageFormat, EXIT_ERRORS, EXIT_USAGE};
+use std::env;
+use std::fs;
+use std::process;
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] FILE...", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn usage_error(program: &str, opts: Options, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    print_usage(program, opts);
+    process::exit(EXIT_USAGE);
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optopt("o", "output", "linked object file, a.objcode by default", "name");
+    opts.optopt("a", "progaddr", "load address of the first section, in hex", "ADDR");
+    opts.optflag("m", "map", "print the load map");
+    opts.optopt("", "message-format", "how diagnostics are printed", "human|json");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => usage_error(&program, opts, &f.to_string()),
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let format = match matches.opt_str("message-format").as_deref() {
+        None | Some("human") => MessageFormat::Human,
+        Some("json") => MessageFormat::Json,
+        Some(other) => {
+            usage_error(&program,
+                        opts,
+                        &format!("unknown message format `{}`, expected human or json", other))
+        }
+    };
+
+    let progaddr = match matches.opt_str("progaddr").map(|addr| u32::from_str_radix(&addr, 16)) {
+        None => 0,
+        Some(Ok(addr)) if addr <= 0xFFFFF => addr,
+        Some(_) => usage_error(&program, opts, "--progaddr takes a hex address below 100000"),
+    };
+
+    if matches.free.is_empty() {
+        usage_error(&program, opts, "no input files");
+    }
+    let output = matches.opt_str("o").unwrap_or_else(|| "a.objcode".to_owned());
+
+    let mut reporter = Reporter::new(format, None, false);
+    reporter.on_errors("no object file written");
+    let mut objects: Vec<(String, String)> = Vec::new();
+    for file in &matches.free {
+        match fs::read_to_string(file) {
+            Ok(text) => {
+                reporter.sources.add_file(file, text.lines().map(|line| line.to_owned()).collect());
+                objects.push((file.clone(), text));
+            }
+            Err(e) => {
+                reporter.report(&[Diagnostic::error(Code::Io,
+                                                    format!("can't read `{}`: {}", file, e))]);
+            }
+        }
+    }
+    if reporter.has_errors() {
+        reporter.finish();
+        process::exit(EXIT_ERRORS);
+    }
+
+    let linked = match link(&objects, progaddr) {
+        Ok(linked) => linked,
+        Err(errs) => {
+            reporter.report(&errs);
+            reporter.finish();
+            process::exit(EXIT_ERRORS);
+        }
+    };
+    reporter.finish();
+
+    if matches.opt_present("m") && format == MessageFormat::Human {
+        print!("{}", linked.load_map());
+    }
+
+    if let Err(e) = linked.output_to_file(&output) {
+        reporter.report(&[Diagnostic::error(Code::Io, format!("can't write `{}`: {}", output, e))]);
+        process::exit(EXIT_ERRORS);
+    }
+}
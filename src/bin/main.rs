@@ -13,14 +13,31 @@ use term::color;
 //use instruction::Instruction;
 //use operands::OperandType;
 use sick_lib::filehandler::FileHandler;
+use sick_lib::Session;
+use sick_lib::RawProgram;
+use sick_lib::assembler;
+use sick_lib::symbol::Symbol;
+use sick_lib::diagnostic::{Code, Diagnostic};
+use sick_lib::lexer::SourceFormat;
+use sick_lib::diagnostic::reporter::{paint, Reporter, MessageFormat, EXIT_ERRORS, EXIT_USAGE};
+use std::collections::HashSet;
 use std::env;
+use std::fs;
+use std::io::{self, BufReader, IsTerminal, Write};
+use std::path::Path;
+use std::process;
 fn print_usage(program: &str, opts: Options) {
-    let brief = format!("Usage: {} FILE [options] file", program);
+    let brief = format!("Usage: {} FILE [options] file, `-` reads stdin", program);
     print!("{}", opts.usage(&brief));
 }
 
+fn usage_error(program: &str, opts: Options, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    print_usage(program, opts);
+    process::exit(EXIT_USAGE);
+}
+
 fn main() {
-    let exit_on_error: bool = false;
     env_logger::init();
     // credits goes to here:-
     // https://doc.rust-lang.org/getopts/getopts/index.html
@@ -28,12 +45,20 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     let program = args[0].clone();
     let mut opts = Options::new();
-    opts.optopt("o", "output", "set output file name", "name");
+    opts.optopt("o", "output", "set output file name, `-` is stdout", "name");
     opts.optflag("c", "csect", "print control section details");
+    opts.optopt("", "message-format", "how diagnostics are printed", "human|json");
+    opts.optopt("", "max-errors", "stop after N errors", "N");
+    opts.optmulti("W", "", "-Werror turns warnings into errors", "error");
+    opts.optmulti("D", "", "set the variable &NAME, to 1 without a value", "NAME[=value]");
+    opts.optmulti("I", "", "look for included files in a directory", "DIR");
+    opts.optflag("", "case-sensitive-labels", "`loop` and `LOOP` are different labels");
+    opts.optflag("", "fixed-format", "read the fields in card columns, 1 10 17 and 36");
+    opts.optflag("", "free-format", "read the fields as words separated by spaces");
     opts.optflag("h", "help", "print this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => m,
-        Err(f) => panic!(f.to_string()),
+        Err(f) => usage_error(&program, opts, &f.to_string()),
     };
     if matches.opt_present("h") {
         print_usage(&program, opts);
@@ -45,46 +70,160 @@ fn main() {
     //     output = matches.opt_str("o").expect("missing file name after -o");
     // }
 
+    let format = match matches.opt_str("message-format").as_deref() {
+        None | Some("human") => MessageFormat::Human,
+        Some("json") => MessageFormat::Json,
+        Some(other) => {
+            usage_error(&program,
+                        opts,
+                        &format!("unknown message format `{}`, expected human or json", other))
+        }
+    };
+
+    let max_errors = match matches.opt_str("max-errors").map(|n| n.parse::<usize>()) {
+        None => None,
+        Some(Ok(n)) if n > 0 => Some(n),
+        Some(_) => usage_error(&program, opts, "--max-errors takes a positive number"),
+    };
+
+    let mut werror = false;
+    for flag in matches.opt_strs("W") {
+        match flag.as_str() {
+            "error" => werror = true,
+            other => usage_error(&program, opts, &format!("unknown warning option `-W{}`", other)),
+        }
+    }
+
+    let source_format = match (matches.opt_present("fixed-format"),
+                               matches.opt_present("free-format")) {
+        (true, true) => {
+            usage_error(&program, opts, "--fixed-format and --free-format can't both be given")
+        }
+        (true, false) => SourceFormat::Fixed,
+        (false, true) => SourceFormat::Free,
+        (false, false) => SourceFormat::Auto,
+    };
+
+    let mut defines: Vec<(String, String)> = Vec::new();
+    for define in matches.opt_strs("D") {
+        let (name, value) = match define.find('=') {
+            Some(at) => (define[..at].to_owned(), define[at + 1..].to_owned()),
+            None => (define.clone(), "1".to_owned()),
+        };
+        if !sick_lib::is_label(&name) {
+            usage_error(&program, opts, &format!("invalid variable name in `-D {}`", define));
+        }
+        defines.push((name, value));
+    }
+
     let input = if !matches.free.is_empty() {
         matches.free[0].clone()
     } else {
-        panic!("Error No input File selected");
+        usage_error(&program, opts, "no input file");
     };
+    // `-o -` writes the object program to stdout, the messages go to stderr
+    let output = matches.opt_str("o");
+    let to_stdout = output.as_deref() == Some("-");
 
-    let mut asm_file = FileHandler::new(input);
-    let result = asm_file.parse_file();
+    let mut reporter = Reporter::new(format, max_errors, werror);
+    reporter.on_errors("no object file written");
+    if to_stdout {
+        reporter.to_stderr();
+    }
+    let mut asm_file = if input == "-" {
+        FileHandler::from_reader("<stdin>".to_owned(), BufReader::new(io::stdin()))
+    } else {
+        match FileHandler::open(input) {
+            Ok(file) => file,
+            Err(e) => {
+                reporter.report(&[e]);
+                reporter.finish();
+                process::exit(EXIT_ERRORS);
+            }
+        }
+    };
+    for (name, value) in &defines {
+        asm_file.define(name, value);
+    }
+    for dir in matches.opt_strs("I") {
+        asm_file.include_dir(&dir);
+    }
+    asm_file.case_sensitive_labels(matches.opt_present("case-sensitive-labels"));
+    asm_file.source_format(source_format);
 
-    let mut t = term::stdout().unwrap();
-    print_errs(&asm_file.errs, exit_on_error);
+    let result = assembler::assemble(&mut asm_file);
+    for (file, lines) in asm_file.files() {
+        reporter.sources.add_file(file, lines.to_vec());
+    }
+    let assembly = match result {
+        Ok(assembly) => assembly,
+        Err(errs) => {
+            reporter.report(&errs);
+            reporter.finish();
+            process::exit(EXIT_ERRORS);
+        }
+    };
+    // -Werror can make the warnings reach --max-errors
+    let limited = reporter.report(&assembly.warnings);
+    reporter.finish();
 
-    let result = sick_lib::pass_one::pass_one::pass_one(result.unwrap());
-    let result = result.map_err(|e| print_error(&e, exit_on_error));
-    if result.is_err() {
-        return;
+    // Never leave an object file behind for a broken program
+    if limited || reporter.has_errors() {
+        process::exit(EXIT_ERRORS);
+    }
+
+    // Keep stdout parsable, the tables are for humans only
+    if format == MessageFormat::Human && !to_stdout {
+        print_listing(&matches, &assembly.session, &assembly.symbols, &assembly.program);
     }
 
-    let (sym_tab, mut raw_program): (_, _) = result.unwrap();
+    let raw_program = &assembly.program;
+    let file = output.unwrap_or_else(|| raw_program.program_name.clone() + ".objcode");
+    let written = if to_stdout {
+        let mut stdout = io::stdout();
+        writeln!(stdout, "{}", assembly.object_program()).and_then(|_| stdout.flush())
+    } else {
+        raw_program.output_to_path(&file)
+    };
+    if let Err(e) = written {
+        reporter.report(&[Diagnostic::error(Code::Io, format!("can't write `{}`: {}", file, e))]);
+        process::exit(EXIT_ERRORS);
+    }
 
-    t.fg(term::color::YELLOW).unwrap();
-    write!(
-        t,
-        "Prog name:{}, prog length:{:#X}, prog start addr:{:#X}\n",
-        raw_program.program_name, raw_program.program_length, raw_program.first_instruction_address
-    ).unwrap();
-    t.reset().unwrap();
+    // A pipeline leaves no files behind, there's no listing with `-o -`
+    if to_stdout {
+        return;
+    }
+    // The listing goes next to the object file, `x.obj` has `x.lst`
+    let file = Path::new(&file).with_extension("lst").to_string_lossy().into_owned();
+    if let Err(e) = fs::write(&file, &assembly.listing) {
+        reporter.report(&[Diagnostic::error(Code::Io, format!("can't write `{}`: {}", file, e))]);
+        process::exit(EXIT_ERRORS);
+    }
+}
 
-    let errs = sick_lib::pass_two::translator::pass_two(&mut raw_program);
+fn print_listing(matches: &getopts::Matches,
+                 session: &Session,
+                 sym_tab: &HashSet<Symbol>,
+                 raw_program: &RawProgram) {
+    let header = format!("Prog name:{}, prog length:{:#X}, prog start addr:{:#X}\n",
+                         raw_program.program_name,
+                         raw_program.program_length,
+                         raw_program.starting_address);
+    let painted = io::stdout().is_terminal() &&
+                  term::stdout().is_some_and(|mut t| paint(&mut *t, color::YELLOW, &header));
+    if !painted {
+        print!("{}", header);
+    }
 
     let mut sym_tab = sym_tab
-        .into_iter()
+        .iter()
         .map(|e| (e.get_name(), e.get_address(), e.get_control_section()))
         .collect::<Vec<(String, i32, String)>>();
 
-    print_errs(&errs, exit_on_error);
-
     // Print control sections info
     if matches.opt_present("c") {
-        print_csect_info();
+        print_csect_info(session);
     }
 
     // Sort by address
@@ -111,48 +250,17 @@ fn main() {
             Cell::new(&format!("{:04X}", instr.locctr))
                 .with_style(term::Attr::ForegroundColor(color::BRIGHT_BLUE)),
             Cell::new(&instr.label),
-            Cell::new(&instr.mnemonic),
+            Cell::new(instr.mnemonic().name()),
             Cell::new(format!("{:?}", &instr.get_format()).as_str()),
             Cell::new(&objcode).with_style(term::Attr::ForegroundColor(color::BRIGHT_YELLOW)),
         ]));
     }
     table.printstd();
-    raw_program.output_to_file();
 }
 
-fn print_csect_info() {
-    let csects_info = sick_lib::pass_one::pass_one::get_csects_info();
+fn print_csect_info(session: &Session) {
+    let csects_info = sick_lib::pass_one::pass_one::get_csects_info(session);
     for sect_info in csects_info {
         println!("{}", sect_info);
     }
 }
-
-fn print_error(err: &String, exit_on_error: bool) {
-    if err.len() == 0 {
-        return;
-    }
-    let mut t = term::stdout().unwrap();
-    t.fg(term::color::BRIGHT_RED).unwrap();
-    t.attr(term::Attr::Bold).unwrap();
-    println!("{}", err);
-    t.reset().unwrap();
-    if exit_on_error {
-        std::process::exit(-1);
-    }
-}
-
-fn print_errs(errs: &Vec<String>, exit_on_error: bool) {
-    if errs.len() == 0 {
-        return;
-    }
-    let mut t = term::stdout().unwrap();
-    t.fg(term::color::BRIGHT_RED).unwrap();
-    t.attr(term::Attr::Bold).unwrap();
-    for err in errs {
-        println!("{}", err);
-    }
-    t.reset().unwrap();
-    if exit_on_error {
-        std::process::exit(-1);
-    }
-}
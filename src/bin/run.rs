@@ -0,0 +1,206 @@
+extern crate getopts;
+extern crate sick_lib;
+
+use getopts::{Matches, Options};
+
+use sick_lib::htme::reader::read_sections;
+use sick_lib::linker::loader::link;
+use sick_lib::simulator::device::Device;
+use sick_lib::simulator::machine::Machine;
+use sick_lib::diagnostic::{self, Code, Diagnostic};
+use sick_lib::diagnostic::reporter::{Reporter, MessageFormat, EXIT_ERRORS, EXIT_USAGE};
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::process;
+
+const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+fn print_usage(program: &str, opts: Options) {
+    let brief = format!("Usage: {} [options] FILE...", program);
+    print!("{}", opts.usage(&brief));
+}
+
+fn usage_error(program: &str, opts: Options, message: &str) -> ! {
+    eprintln!("error: {}", message);
+    print_usage(program, opts);
+    process::exit(EXIT_USAGE);
+}
+
+/// Splits a `DEV=FILE` argument, DEV is the device number in hex
+fn device_arg(arg: &str) -> Option<(u8, String)> {
+    let mut parts = arg.splitn(2, '=');
+    let number = u8::from_str_radix(parts.next()?, 16).ok()?;
+    match parts.next() {
+        Some(file) if !file.is_empty() => Some((number, file.to_owned())),
+        _ => None,
+    }
+}
+
+/// The DEV=FILE arguments of an option
+fn device_args(matches: &Matches, name: &str) -> Result<Vec<(u8, String)>, String> {
+    matches.opt_strs(name)
+        .iter()
+        .map(|arg| {
+            device_arg(arg).ok_or_else(|| format!("--{} takes DEV=FILE, found `{}`", name, arg))
+        })
+        .collect()
+}
+
+/// Opens the host files given with --read and --write, `-` is stdin/stdout
+fn devices(reads: Vec<(u8, String)>,
+           writes: Vec<(u8, String)>)
+           -> Result<Vec<(u8, Device)>, String> {
+    let mut devices = Vec::new();
+    for (number, file) in reads {
+        let device = if file == "-" {
+            Device::Reader(Box::new(BufReader::new(io::stdin())))
+        } else {
+            let input = File::open(&file).map_err(|e| format!("can't read `{}`: {}", file, e))?;
+            Device::Reader(Box::new(BufReader::new(input)))
+        };
+        devices.push((number, device));
+    }
+    for (number, file) in writes {
+        let device = if file == "-" {
+            Device::Writer(Box::new(BufWriter::new(io::stdout())))
+        } else {
+            let output = File::create(&file)
+                .map_err(|e| format!("can't write `{}`: {}", file, e))?;
+            Device::Writer(Box::new(BufWriter::new(output)))
+        };
+        devices.push((number, device));
+    }
+    Ok(devices)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let program = args[0].clone();
+    let mut opts = Options::new();
+    opts.optopt("a",
+                "progaddr",
+                "load address of the first section, in hex, by default where the first \
+                 section starts",
+                "ADDR");
+    opts.optmulti("", "read", "connect an input device to a file, `-` is stdin", "DEV=FILE");
+    opts.optmulti("", "write", "connect an output device to a file, `-` is stdout", "DEV=FILE");
+    opts.optopt("",
+                "max-steps",
+                &format!("stop after N instructions, {} by default", DEFAULT_MAX_STEPS),
+                "N");
+    opts.optflag("r", "registers", "print the registers when the program stops");
+    opts.optopt("", "message-format", "how diagnostics are printed", "human|json");
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(&args[1..]) {
+        Ok(m) => m,
+        Err(f) => usage_error(&program, opts, &f.to_string()),
+    };
+    if matches.opt_present("h") {
+        print_usage(&program, opts);
+        return;
+    }
+
+    let format = match matches.opt_str("message-format").as_deref() {
+        None | Some("human") => MessageFormat::Human,
+        Some("json") => MessageFormat::Json,
+        Some(other) => {
+            usage_error(&program,
+                        opts,
+                        &format!("unknown message format `{}`, expected human or json", other))
+        }
+    };
+
+    let progaddr = match matches.opt_str("progaddr").map(|addr| u32::from_str_radix(&addr, 16)) {
+        None => None,
+        Some(Ok(addr)) if addr <= 0xFFFFF => Some(addr),
+        Some(_) => usage_error(&program, opts, "--progaddr takes a hex address below 100000"),
+    };
+
+    let max_steps = match matches.opt_str("max-steps").map(|steps| steps.parse::<u64>()) {
+        None => DEFAULT_MAX_STEPS,
+        Some(Ok(steps)) if steps > 0 => steps,
+        Some(_) => usage_error(&program, opts, "--max-steps takes a positive number"),
+    };
+
+    let (reads, writes) = match (device_args(&matches, "read"), device_args(&matches, "write")) {
+        (Ok(reads), Ok(writes)) => (reads, writes),
+        (Err(message), _) | (_, Err(message)) => usage_error(&program, opts, &message),
+    };
+
+    if matches.free.is_empty() {
+        usage_error(&program, opts, "no input files");
+    }
+
+    let mut reporter = Reporter::new(format, None, false);
+    reporter.on_errors("the program wasn't run");
+    let mut objects: Vec<(String, String)> = Vec::new();
+    for file in &matches.free {
+        match fs::read_to_string(file) {
+            Ok(text) => {
+                reporter.sources.add_file(file, text.lines().map(|line| line.to_owned()).collect());
+                objects.push((file.clone(), text));
+            }
+            Err(e) => {
+                reporter.report(&[Diagnostic::error(Code::Io,
+                                                    format!("can't read `{}`: {}", file, e))]);
+            }
+        }
+    }
+    if reporter.has_errors() {
+        reporter.finish();
+        process::exit(EXIT_ERRORS);
+    }
+
+    // An absolute program runs where it was assembled unless told otherwise
+    let progaddr = match progaddr {
+        Some(progaddr) => progaddr,
+        None => {
+            match read_sections(&objects[0].1) {
+                Ok(sections) => sections.first().map_or(0, |section| section.start),
+                Err(mut errs) => {
+                    diagnostic::set_file(&mut errs, &objects[0].0);
+                    reporter.report(&errs);
+                    reporter.finish();
+                    process::exit(EXIT_ERRORS);
+                }
+            }
+        }
+    };
+
+    let linked = match link(&objects, progaddr) {
+        Ok(linked) => linked,
+        Err(errs) => {
+            reporter.report(&errs);
+            reporter.finish();
+            process::exit(EXIT_ERRORS);
+        }
+    };
+    reporter.finish();
+
+    let mut machine = Machine::new();
+    if let Err(fault) = machine.load(&linked) {
+        eprintln!("error: {}", fault);
+        process::exit(EXIT_ERRORS);
+    }
+    match devices(reads, writes) {
+        Ok(devices) => {
+            for (number, device) in devices {
+                machine.connect(number, device);
+            }
+        }
+        Err(message) => {
+            eprintln!("error: {}", message);
+            process::exit(EXIT_ERRORS);
+        }
+    }
+
+    let result = machine.run(max_steps);
+    if matches.opt_present("r") {
+        eprintln!("{}", machine.dump());
+    }
+    if let Err(fault) = result {
+        eprintln!("error: {}", fault);
+        process::exit(EXIT_ERRORS);
+    }
+}
@@ -0,0 +1,319 @@
+/// The whole assembler in one call, from the source text to the object
+/// program and the listing, for tests and programs that embed it
+///
+///  let assembly = assemble_str(source, &Options::default())?;
+///  print!("{}", assembly.object_program());
+use std::collections::HashSet;
+use std::io::Cursor;
+use diagnostic::{self, Diagnostic};
+use filehandler::FileHandler;
+use lexer::SourceFormat;
+use htme::raw_program::RawProgram;
+use listing::listing;
+use pass_one::pass_one::pass_one;
+use pass_two::translator::pass_two;
+use session::Session;
+use symbol::Symbol;
+
+/// The name of a source given as a string, in the diagnostics
+pub const STRING_SOURCE: &str = "<input>";
+
+/// What the command line would set, variables, include directories, the
+/// case of the labels and the layout of the lines
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    /// SET variables, the names are without the `&`
+    pub defines: Vec<(String, String)>,
+    pub include_dirs: Vec<String>,
+    /// `loop` and `LOOP` are different labels
+    pub case_sensitive_labels: bool,
+    /// Card columns or words, by default each line is looked at
+    pub source_format: SourceFormat,
+}
+
+/// An assembled program and the tables that were built on the way
+pub struct Assembly {
+    pub session: Session,
+    pub program: RawProgram,
+    pub symbols: HashSet<Symbol>,
+    pub listing: String,
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Assembly {
+    /// The header, text, modification and end records
+    pub fn object_program(&self) -> String {
+        self.program.all_records()
+    }
+}
+
+/// Assembles a program given as a string, INCLUDEs are looked up from the
+/// current directory
+pub fn assemble_str(source: &str, options: &Options) -> Result<Assembly, Vec<Diagnostic>> {
+    let mut file = FileHandler::from_reader(STRING_SOURCE.to_owned(),
+                                            Cursor::new(source.to_owned()));
+    for (name, value) in &options.defines {
+        file.define(name, value);
+    }
+    for dir in &options.include_dirs {
+        file.include_dir(dir);
+    }
+    file.case_sensitive_labels(options.case_sensitive_labels);
+    file.source_format(options.source_format);
+    assemble(&mut file)
+}
+
+/// Assembles the program of a file, the errors come with the warnings found
+/// before them, sorted by their position
+pub fn assemble(file: &mut FileHandler) -> Result<Assembly, Vec<Diagnostic>> {
+    let path = file.path().to_owned();
+    let prog = match file.parse_file() {
+        Ok(prog) => prog,
+        Err(e) => {
+            let mut errs = file.errs.clone();
+            errs.push(e);
+            return Err(errs);
+        }
+    };
+    let mut errs = file.errs.clone();
+
+    let mut session = Session::new();
+    let result = pass_one(&mut session, prog);
+    let mut warnings = ::std::mem::take(&mut session.warnings);
+    diagnostic::set_file(&mut warnings, &path);
+    file.locate(&mut warnings);
+
+    let (symbols, mut program) = match result {
+        Ok(result) => result,
+        Err(mut e) => {
+            diagnostic::set_file(&mut e, &path);
+            file.locate(&mut e);
+            errs.append(&mut e);
+            errs.append(&mut warnings);
+            diagnostic::sort(&mut errs);
+            return Err(errs);
+        }
+    };
+
+    let mut e = pass_two(&mut session, &mut program);
+    diagnostic::set_file(&mut e, &path);
+    file.locate(&mut e);
+    errs.append(&mut e);
+    if errs.iter().any(|err| err.is_error()) {
+        errs.append(&mut warnings);
+        diagnostic::sort(&mut errs);
+        return Err(errs);
+    }
+
+    // Only warnings are left
+    warnings.append(&mut errs);
+    diagnostic::sort(&mut warnings);
+    let listing = listing(&session, &program, file.source_lines());
+    Ok(Assembly {
+        session,
+        program,
+        symbols,
+        listing,
+        warnings,
+    })
+}
+
+/// The object program of a source file, for the tests of the other modules
+#[cfg(test)]
+pub fn object_program_of(path: &str) -> String {
+    let mut file = FileHandler::open(path.to_owned()).unwrap();
+    assemble(&mut file).unwrap().object_program()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostic::Code;
+
+    const PROGRAM: &str = "SUM      START   1000
+.
+FIRST    LDA     ALPHA
+         ADD     #&STEP
+         STA     TOTAL
+         RSUB
+ALPHA    WORD    5
+TOTAL    RESW    1
+         END     FIRST
+";
+
+    fn options(step: &str) -> Options {
+        Options {
+            defines: vec![("STEP".to_owned(), step.to_owned())],
+            include_dirs: Vec::new(),
+            case_sensitive_labels: false,
+            source_format: SourceFormat::Auto,
+        }
+    }
+
+    #[test]
+    fn assembles_a_string() {
+        let assembly = assemble_str(PROGRAM, &options("3")).unwrap();
+        assert_eq!(assembly.object_program(),
+                   "HSUM   001000000012\n\
+                    T0010000F0320091900030F20064F0000000005\n\
+                    E001000");
+        assert!(assembly.warnings.is_empty());
+        assert!(assembly.listing.contains("    4  1003           ADD     #&STEP"));
+        assert_eq!(assembly.symbols.len(), 4);
+    }
+
+    #[test]
+    fn errors_name_the_string() {
+        let errs = assemble_str(PROGRAM, &Options::default()).err().unwrap();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].code, Code::UndefinedVariable);
+        assert_eq!((errs[0].file.as_str(), errs[0].line), (STRING_SOURCE, 4));
+
+        let errs = assemble_str("PROG START 0\n LDA MISSING\n END\n", &Options::default())
+            .err()
+            .unwrap();
+        assert_eq!(errs[0].code, Code::UndefinedSymbol);
+        assert_eq!((errs[0].file.as_str(), errs[0].line), (STRING_SOURCE, 2));
+    }
+
+    #[test]
+    fn character_constants() {
+        let source = "STR START 0\n BYTE C'A B'\n BYTE C'IT''S'\n LDA =C'.'\n END\n";
+        let assembly = assemble_str(source, &Options::default()).unwrap();
+        assert_eq!(assembly.object_program(),
+                   "HSTR   00000000000B\n\
+                    T0000000B412042495427530320002E\n\
+                    E000000");
+    }
+
+    #[test]
+    fn mistakes_that_used_to_panic() {
+        let codes = |source: &str| {
+            assemble_str(source, &Options::default())
+                .err()
+                .unwrap()
+                .iter()
+                .map(|e| e.code)
+                .collect::<Vec<Code>>()
+        };
+        assert_eq!(codes("P START 0\n RESB 1,2\n END\n"), vec![Code::InvalidOperands]);
+        assert_eq!(codes("P START 0\n RESW -1\n END\n"), vec![Code::InvalidOperand]);
+        assert_eq!(codes("P START 0\n EXTREF\n END\n"), vec![Code::InvalidOperand]);
+        assert_eq!(codes("P START 0\n BASE P\n NOBASE\n NOBASE\n END\n"),
+                   vec![Code::InvalidLine]);
+        assert_eq!(codes("P START 0\n RESB 2147483647\n END\n"),
+                   vec![Code::AddressOutOfRange]);
+        assert_eq!(codes(""), vec![Code::MissingStart]);
+    }
+
+    #[test]
+    fn overflowing_expressions() {
+        let source = "P        START   0\nA        EQU     2147483647*2\n         WORD    A\n\
+                      \x20        END\n";
+        let errs = assemble_str(source, &Options::default()).err().unwrap();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].code, Code::ValueOutOfRange);
+        assert_eq!((errs[0].line, errs[0].column()), (2, 18));
+    }
+
+    #[test]
+    fn literals_are_reused_after_ltorg() {
+        let source = "P START 0\n LDA =X'05'\n LTORG\n LDA =X'05'\n END\n";
+        let assembly = assemble_str(source, &Options::default()).unwrap();
+        assert_eq!(assembly.object_program(),
+                   "HP     000000000007\nT0000000703200005032FFC\nE000000");
+    }
+
+    #[test]
+    fn case_of_the_source() {
+        let lower = "copy     start   0\n\
+                     first    lda     =c'Eof'\n\
+                     Loop     tixr    t\n\
+                     \x20        jlt     loop\n\
+                     \x20        +stch   Buffer,x\n\
+                     buffer   resb    3\n\
+                     \x20        end     first\n";
+        let assembly = assemble_str(lower, &Options::default()).unwrap();
+        assert_eq!(assembly.object_program(),
+                   "HCOPY  000000000012\n\
+                    T0000000C03200CB8503B2FFB5790000C\n\
+                    T00000F03456F66\n\
+                    M00000905+COPY\n\
+                    E000000");
+
+        let options = Options { case_sensitive_labels: true, ..Options::default() };
+        let errs = assemble_str(lower, &options).err().unwrap();
+        let undefined = errs.iter()
+            .filter(|e| e.code == Code::UndefinedSymbol)
+            .map(|e| e.line)
+            .collect::<Vec<i32>>();
+        assert_eq!(undefined, vec![4, 5]);
+    }
+
+    #[test]
+    fn hex_constants_in_any_case() {
+        // The same literal in both cases is pooled once
+        let source = "p start 0\n lda =x'ab'\n lda =X'AB'\nc byte x'0f'\n end\n";
+        for &case_sensitive_labels in &[false, true] {
+            let options = Options { case_sensitive_labels, ..Options::default() };
+            let assembly = assemble_str(source, &options).unwrap();
+            assert!(assembly.object_program()
+                .contains("T000000080320040320010FAB\n"));
+        }
+    }
+
+    #[test]
+    fn card_columns() {
+        // A label named like a mnemonic and the object code of an old listing
+        let source = "COPY     START   0\r\n\
+                      RSUB     LDA     #5                010005\r\n\
+                      \x20        J       RSUB              3F2FFA\r\n\
+                      \x20        RSUB                      4F0000\r\n\
+                      \x20        END     RSUB\r\n";
+        let expected = "HCOPY  000000000009\nT00000009010005\
+                        3F2FFA4F0000\nE000000";
+        let assembly = assemble_str(source, &Options::default()).unwrap();
+        assert_eq!(assembly.object_program(), expected);
+        let options = Options { source_format: SourceFormat::Fixed, ..Options::default() };
+        assert_eq!(assemble_str(source, &options).unwrap().object_program(), expected);
+
+        let options = Options { source_format: SourceFormat::Free, ..Options::default() };
+        assert!(assemble_str(source, &options).is_err());
+    }
+
+    #[test]
+    fn archived_programs() {
+        // BUFFEND doesn't fit the 6 columns of the D and R records, the cards
+        // are assembled as they were punched otherwise
+        for path in &["src/tests/more-tests/copy.asm", "src/tests/more-tests/csect-copy.asm"] {
+            let source = ::std::fs::read_to_string(path).unwrap().replace("BUFFEND", "BUFEND");
+            let assembly = assemble_str(&source, &Options::default()).unwrap();
+            let object_program = assembly.object_program();
+            assert!(object_program.starts_with("HCOPY  000000001033\n\
+                                                DBUFFER000033BUFEND001033LENGTH00002D\n"));
+            assert!(object_program.contains("HRDREC 00000000002B\n"));
+            assert!(object_program.contains("HWRREC 00000000001C\n"));
+            assert!(object_program.contains("T00001D0E3B2FE9131000004F0000F1000000\n"));
+        }
+    }
+
+    #[test]
+    fn card_lines_mixed_with_free_ones() {
+        // Only the lines laid out in card columns are read in them
+        let source = "COPY     START   0\n\
+                      FIRST    LDA     #5                010005\n\
+                      LDA #1\n\
+                      RSUB\n\
+                      \x20        END     FIRST\n";
+        let expected = "HCOPY  000000000009\nT00000009010005\
+                        0100014F0000\nE000000";
+        let assembly = assemble_str(source, &Options::default()).unwrap();
+        assert_eq!(assembly.object_program(), expected);
+    }
+
+    #[test]
+    fn files_that_cannot_be_opened_are_errors() {
+        let err = FileHandler::open("src/tests/no such file.asm".to_owned()).err().unwrap();
+        assert_eq!(err.code, Code::Io);
+    }
+}
@@ -4,8 +4,11 @@ use formats::Format;
 use flags::Flags;
 use std::collections::HashSet;
 use operands::{OperandType, Value};
-use unit_or_pair::{UnitOrPair, unwrap_to_vec};
+use unit_or_pair::UnitOrPair;
 use register::Register;
+use instruction_set::Mnemonic;
+use statement::Statement;
+use diagnostic::LineSpans;
 use std::clone::Clone;
 use std::fmt;
 
@@ -42,11 +45,14 @@ pub struct Instruction {
     flags: HashSet<Flags>, // Set and Get through functoins
     format: Format,
     pub label: String,
-    pub mnemonic: String,
+    pub statement: Statement, // What it does, with the typed operands of directives
     pub csect: String,
+    pub block: String, // Program block of USE, empty for the default block
+    pub expanded: Option<String>, // Source text of a statement made by a macro
     pub operands: UnitOrPair<AsmOperand>, // Group oerands in one field
     pub locctr: i32, // Signed because it'll be subtracted from signed quantities
     pub src_line_num: i32,
+    pub spans: LineSpans, // Where the label, mnemonic and operands are in the source line
 }
 
 impl Instruction {
@@ -54,41 +60,40 @@ impl Instruction {
      * new A plain new instruction
      * use builder pattern? ( as it's transromed in phases and to make testing less verbose)
      */
-    pub fn new(label: String, mnemonic: String, operands: UnitOrPair<AsmOperand>) -> Instruction {
-        let mut inst = Instruction {
+    pub fn new(label: String, statement: Statement, operands: UnitOrPair<AsmOperand>) -> Instruction {
+        Instruction {
             label: label,
             format: Format::None,
-            mnemonic: mnemonic,
+            statement,
             csect: String::new(),
+            block: String::new(),
+            expanded: None,
             locctr: 0,
             src_line_num: 0,
+            spans: LineSpans::default(),
             // SIC/XE defaults ind. and imm. falgs to 1
             flags: HashSet::new(),
 
-            // Set the operands with the add_operand function to raise the flags
-            operands: UnitOrPair::None,
-        };
-
-        for op in unwrap_to_vec(&operands) {
-            if let Err(e) = inst.add_operand(op) {
-                panic!("{}", e);
-            }
+            // The flags are raised by set_format
+            operands,
         }
-        inst
     }
 
     /*
      *   Creates an instruction with only a mnemonic
      */
-    pub fn new_simple(mnemonic: String) -> Instruction {
+    pub fn new_simple(statement: Statement) -> Instruction {
         Instruction {
             label: String::new(),
             format: Format::None,
             csect: String::new(),
-            mnemonic: mnemonic,
+            block: String::new(),
+            expanded: None,
+            statement,
             flags: HashSet::new(),
             locctr: 0,
             src_line_num: 0,
+            spans: LineSpans::default(),
             operands: UnitOrPair::None,
         }
     }
@@ -152,6 +157,10 @@ impl Instruction {
         self.format
     }
 
+    pub fn mnemonic(&self) -> Mnemonic {
+        self.statement.mnemonic()
+    }
+
     pub fn add_label(&mut self, label: String) -> Result<(), &str> {
 
         if self.label.len() > 0 {
@@ -165,13 +174,13 @@ impl Instruction {
     /**
     *   Adds an operand to the instruction as appropriate
     **/
-    fn add_operand(&mut self, op: AsmOperand) -> Result<(), &str> {
+    fn add_operand(&mut self, op: AsmOperand) -> Result<(), &'static str> {
 
         // Match on a copy, modify the original
         match self.operands.clone() {
             UnitOrPair::Unit(asm) => Ok(self.operands = UnitOrPair::Pair(asm, op)),
             UnitOrPair::None => Ok(self.operands = UnitOrPair::Unit(op)),
-            _ => panic!("Invalid operands"),
+            UnitOrPair::Pair(..) => Err("An instruction has at most 2 operands"),
         }
     }
 
@@ -190,6 +199,10 @@ impl Instruction {
             warn!("Instruction {:?} format isnt specified", self);
             return Err("Instruction format wasn't specified".to_owned());
         }
+        // Formats 1 and 2 don't have flags, a wrong operand like #3 is reported elsewhere
+        if self.format == Format::One || self.format == Format::Two {
+            return Ok(0);
+        }
 
         // Decimal value resulting from decoding the flags
         let mut total_value: u32 = 0;
@@ -245,16 +258,18 @@ impl Instruction {
     /// padded with a zero, ex. TIXR T should be B850 but is calculated as B805
     /// adding the A register will pad it with 0
     pub fn add_reg_a(&mut self) {
-        self.add_operand(AsmOperand::new(OperandType::Register, Value::Register(Register::A)))
-            .unwrap();
-    }
-
-    pub fn get_first_operand(&self) -> AsmOperand {
-        unwrap_to_vec(&self.operands)[0].clone()
+        let register = AsmOperand::new(OperandType::Register, Value::Register(Register::A));
+        if let Err(e) = self.add_operand(register) {
+            warn!("Can't pad {:?} with the A register: {}", self, e);
+        }
     }
 
-    pub fn get_second_operand(&self) -> AsmOperand {
-        unwrap_to_vec(&self.operands)[1].clone()
+    /// The value of the first operand, Value::None without operands
+    pub fn first_value(&self) -> Value {
+        match self.operands {
+            UnitOrPair::Unit(ref op) | UnitOrPair::Pair(ref op, _) => op.val.clone(),
+            UnitOrPair::None => Value::None,
+        }
     }
 
     pub fn set_line_number(&mut self, line_num: i32) {
@@ -285,7 +300,7 @@ impl fmt::Debug for Instruction {
                self.locctr,
                self.format,
                self.label,
-               self.mnemonic,
+               self.mnemonic(),
                self.operands)
     }
 }
@@ -1,4 +1,5 @@
 use std::collections::{HashSet, HashMap};
+use std::fmt;
 use instruction::AsmOperand;
 use formats::{Format, get_bit_count};
 use operands::OperandType;
@@ -153,76 +154,240 @@ pub fn fetch_instruction<'a>(instr_mnemonic: &String) -> Result<AssemblyDef, &'a
     Ok(INSTRUCTION_SET.get(mnemonic).unwrap().clone())
 }
 
+/// Finds the instruction of an opcode, the n and i bits that share the
+/// byte with the opcode of format 3/4 instructions are ignored
+pub fn fetch_by_opcode(op_code: u8) -> Option<AssemblyDef> {
+    INSTRUCTION_SET.values()
+        .find(|def| match def.format {
+            UnitOrPair::Pair(_, _) => def.op_code == (op_code & 0xFC) as u32,
+            _ => def.op_code == op_code as u32,
+        })
+        .cloned()
+}
+
+/// Mnemonics are matched in any case, `lda` is `LDA`
 pub fn is_instruction(mnemonic: &str) -> bool {
-    INSTRUCTION_SET.contains_key(mnemonic)
+    INSTRUCTION_SET.contains_key(&mnemonic.to_uppercase())
 }
 
 lazy_static!{
-    static ref INSTRUCTION_SET: HashMap<String,AssemblyDef > = {
-            let isa :HashMap <String, AssemblyDef> = [
-                ("ADD".to_owned(),      AssemblyDef::new("ADD".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x18)),
-                ("ADDF".to_owned(),     AssemblyDef::new("ADDF".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x58)),
-                ("ADDR".to_owned(),     AssemblyDef::new("ADDR".to_owned(),     UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Register), 0x90)),
-                ("AND".to_owned(),      AssemblyDef::new("AND".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x40)),
-                ("CLEAR".to_owned(),    AssemblyDef::new("CLEAR".to_owned(),    UnitOrPair::Unit(Format::Two),                      UnitOrPair::Unit(OperandType::Register),                        0xB4)),
-                ("COMP".to_owned(),     AssemblyDef::new("COMP".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x28)),
-                ("COMPF".to_owned(),    AssemblyDef::new("COMPF".to_owned(),    UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x88)),
-                ("COMPR".to_owned(),    AssemblyDef::new("COMPR".to_owned(),    UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Register), 0xA0)),
-                ("DIV".to_owned(),      AssemblyDef::new("DIV".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x24)),
-                ("DIVF".to_owned(),     AssemblyDef::new("DIVF".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x64)),
-                ("DIVR".to_owned(),     AssemblyDef::new("DIVR".to_owned(),     UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Register), 0x9C)),
-                ("FIX".to_owned(),      AssemblyDef::new("FIX".to_owned(),      UnitOrPair::Unit(Format::One),                      UnitOrPair::None,                                               0xC4)),
-                ("FLOAT".to_owned(),    AssemblyDef::new("FLOAT".to_owned(),    UnitOrPair::Unit(Format::One),                      UnitOrPair::None,                                               0xC0)),
-                ("HIO".to_owned(),      AssemblyDef::new("HIO".to_owned(),      UnitOrPair::Unit(Format::One),                      UnitOrPair::None,                                               0xF4)),
-                ("J".to_owned(),        AssemblyDef::new("J".to_owned(),        UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x3C)),
-                ("JEQ".to_owned(),      AssemblyDef::new("JEQ".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x30)),
-                ("JGT".to_owned(),      AssemblyDef::new("JGT".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x34)),
-                ("JLT".to_owned(),      AssemblyDef::new("JLT".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x38)),
-                ("JSUB".to_owned(),     AssemblyDef::new("JSUB".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x48)),
-                ("LDA".to_owned(),      AssemblyDef::new("LDA".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x00)),
-                ("LDB".to_owned(),      AssemblyDef::new("LDB".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x68)),
-                ("LDCH".to_owned(),     AssemblyDef::new("LDHC".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x50)),
-                ("LDF".to_owned(),      AssemblyDef::new("LDF".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x70)),
-                ("LDL".to_owned(),      AssemblyDef::new("LDL".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x08)),
-                ("LDS".to_owned(),      AssemblyDef::new("LDS".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x6C)),
-                ("LDT".to_owned(),      AssemblyDef::new("LDT".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x74)),
-                ("LDX".to_owned(),      AssemblyDef::new("LDX".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x04)),
-                ("LPS".to_owned(),      AssemblyDef::new("LPS".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xD0)),
-                ("MUL".to_owned(),      AssemblyDef::new("MUL".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x20)),
-                ("MULF".to_owned(),     AssemblyDef::new("MULF".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x60)),
-                ("MULR".to_owned(),     AssemblyDef::new("MULR".to_owned(),     UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Register), 0x98)),
-                ("NORM".to_owned(),     AssemblyDef::new("NORM".to_owned(),     UnitOrPair::Unit(Format::One),                      UnitOrPair::None,                                               0xC8)),
-                ("OR".to_owned(),       AssemblyDef::new("OR".to_owned(),       UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x44)),
-                ("RD".to_owned(),       AssemblyDef::new("RD".to_owned(),       UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xD8)),
-                ("RMO".to_owned(),      AssemblyDef::new("RMO".to_owned(),      UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Register), 0xAC)),
-                ("RSUB".to_owned(),     AssemblyDef::new("RSUB".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::None,                                               0x4C)),
-                ("SHIFTL".to_owned(),   AssemblyDef::new("SHIFTL".to_owned(),   UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Immediate),0xA4)),
-                ("SHIFTR".to_owned(),   AssemblyDef::new("SHIRFT".to_owned(),   UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Immediate),0xA8)),
-                ("SIO".to_owned(),     AssemblyDef::new("SIO".to_owned(),       UnitOrPair::Unit(Format::One),                      UnitOrPair::None,                                               0xF0)),
-                ("SSK".to_owned(),      AssemblyDef::new("SSK".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xEC)),
-                ("STA".to_owned(),      AssemblyDef::new("STA".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x0C)),
-                ("STB".to_owned(),      AssemblyDef::new("STB".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x78)),
-                ("STCH".to_owned(),     AssemblyDef::new("STCH".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x54)),
-                ("STF".to_owned(),      AssemblyDef::new("STF".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x80)),
-                ("STI".to_owned(),      AssemblyDef::new("STI".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xD4)),
-                ("STL".to_owned(),      AssemblyDef::new("STL".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x14)),
-                ("STS".to_owned(),      AssemblyDef::new("STS".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x7C)),
-                ("STSW".to_owned(),     AssemblyDef::new("STSW".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xE8)),
-                ("STT".to_owned(),      AssemblyDef::new("STT".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x84)),
-                ("STX".to_owned(),      AssemblyDef::new("STX".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x10)),
-                ("SUB".to_owned(),      AssemblyDef::new("SUB".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x1C)),
-                ("SUBF".to_owned(),     AssemblyDef::new("SUBF".to_owned(),     UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x5C)),
-                ("SUBR".to_owned(),     AssemblyDef::new("SUBR".to_owned(),     UnitOrPair::Unit(Format::Two),                      UnitOrPair::Pair(OperandType::Register, OperandType::Register), 0x94)),
-                ("SVC".to_owned(),      AssemblyDef::new("SVC".to_owned(),      UnitOrPair::Unit(Format::Two),                      UnitOrPair::Unit(OperandType::Immediate),                       0xB0)),
-                ("TD".to_owned(),       AssemblyDef::new("TD".to_owned(),       UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xE0)),
-                ("TIO".to_owned(),      AssemblyDef::new("TIO".to_owned(),      UnitOrPair::Unit(Format::One),                      UnitOrPair::None,                                               0xF8)),
-                ("TIX".to_owned(),      AssemblyDef::new("TIX".to_owned(),      UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0x2C)),
-                ("TIXR".to_owned(),     AssemblyDef::new("TIXR".to_owned(),     UnitOrPair::Unit(Format::Two),                      UnitOrPair::Unit(OperandType::Register),                        0xB8)),
-                ("WD".to_owned(),       AssemblyDef::new("WD".to_owned(),       UnitOrPair::Pair(Format::Three, Format::Four),      UnitOrPair::Unit(OperandType::Immediate),                       0xDC))
-                    ].iter().cloned().collect();
-            return isa;
-        };
+    static ref INSTRUCTION_SET: HashMap<String, AssemblyDef> = Opcode::ALL.iter()
+        .map(|opcode| (opcode.name().to_owned(), opcode.definition()))
+        .collect();
+    static ref ASSEMBLER_DIRECTIVES: HashMap<String, AssemblyDef> = Directive::ALL.iter()
+        .map(|directive| (directive.name().to_owned(), directive.definition()))
+        .collect();
+}
+
+/// Declares an `Opcode` for every entry of the instruction set
+macro_rules! instruction_set {
+    ($(($opcode:ident, $name:expr, $formats:expr, $operands:expr, $code:expr),)*) => {
+        /// An instruction of the machine, ex. `Opcode::Lda`
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Opcode {
+            $($opcode,)*
+        }
+
+        impl Opcode {
+            /// Every instruction, in the order of the table
+            pub const ALL: &'static [Opcode] = &[$(Opcode::$opcode,)*];
+
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(Opcode::$opcode => $name,)*
+                }
+            }
+
+            pub fn definition(&self) -> AssemblyDef {
+                match *self {
+                    $(Opcode::$opcode => {
+                        AssemblyDef::new($name.to_owned(), $formats, $operands, $code)
+                    })*
+                }
+            }
+        }
+    };
+}
+
+/// Declares a `Directive` for every entry of the directive table, directives
+/// have no format and take at most one operand
+macro_rules! directives {
+    ($(($directive:ident, $name:expr, $operand:ident),)*) => {
+        /// An assembler directive, ex. `Directive::Resb`
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Directive {
+            $($directive,)*
+        }
+
+        impl Directive {
+            pub const ALL: &'static [Directive] = &[$(Directive::$directive,)*];
+
+            pub fn name(&self) -> &'static str {
+                match *self {
+                    $(Directive::$directive => $name,)*
+                }
+            }
+
+            pub fn definition(&self) -> AssemblyDef {
+                match *self {
+                    $(Directive::$directive => {
+                        AssemblyDef::new($name.to_owned(),
+                                         UnitOrPair::Unit(Format::None),
+                                         UnitOrPair::Unit(OperandType::$operand),
+                                         0xFF)
+                    })*
+                }
+            }
+        }
+    };
+}
+
+// The formats and operands of the instruction set table
+const F1: UnitOrPair<Format> = UnitOrPair::Unit(Format::One);
+const F2: UnitOrPair<Format> = UnitOrPair::Unit(Format::Two);
+const F34: UnitOrPair<Format> = UnitOrPair::Pair(Format::Three, Format::Four);
+const NONE: UnitOrPair<OperandType> = UnitOrPair::None;
+const MEMORY: UnitOrPair<OperandType> = UnitOrPair::Unit(OperandType::Immediate);
+const REGISTER: UnitOrPair<OperandType> = UnitOrPair::Unit(OperandType::Register);
+const REGISTERS: UnitOrPair<OperandType> = UnitOrPair::Pair(OperandType::Register,
+                                                            OperandType::Register);
+const REGISTER_NUMBER: UnitOrPair<OperandType> = UnitOrPair::Pair(OperandType::Register,
+                                                                  OperandType::Immediate);
+
+instruction_set! {
+    (Add,    "ADD",     F34, MEMORY,          0x18),
+    (Addf,   "ADDF",    F34, MEMORY,          0x58),
+    (Addr,   "ADDR",    F2,  REGISTERS,       0x90),
+    (And,    "AND",     F34, MEMORY,          0x40),
+    (Clear,  "CLEAR",   F2,  REGISTER,        0xB4),
+    (Comp,   "COMP",    F34, MEMORY,          0x28),
+    (Compf,  "COMPF",   F34, MEMORY,          0x88),
+    (Compr,  "COMPR",   F2,  REGISTERS,       0xA0),
+    (Div,    "DIV",     F34, MEMORY,          0x24),
+    (Divf,   "DIVF",    F34, MEMORY,          0x64),
+    (Divr,   "DIVR",    F2,  REGISTERS,       0x9C),
+    (Fix,    "FIX",     F1,  NONE,            0xC4),
+    (Float,  "FLOAT",   F1,  NONE,            0xC0),
+    (Hio,    "HIO",     F1,  NONE,            0xF4),
+    (J,      "J",       F34, MEMORY,          0x3C),
+    (Jeq,    "JEQ",     F34, MEMORY,          0x30),
+    (Jgt,    "JGT",     F34, MEMORY,          0x34),
+    (Jlt,    "JLT",     F34, MEMORY,          0x38),
+    (Jsub,   "JSUB",    F34, MEMORY,          0x48),
+    (Lda,    "LDA",     F34, MEMORY,          0x00),
+    (Ldb,    "LDB",     F34, MEMORY,          0x68),
+    (Ldch,   "LDCH",    F34, MEMORY,          0x50),
+    (Ldf,    "LDF",     F34, MEMORY,          0x70),
+    (Ldl,    "LDL",     F34, MEMORY,          0x08),
+    (Lds,    "LDS",     F34, MEMORY,          0x6C),
+    (Ldt,    "LDT",     F34, MEMORY,          0x74),
+    (Ldx,    "LDX",     F34, MEMORY,          0x04),
+    (Lps,    "LPS",     F34, MEMORY,          0xD0),
+    (Mul,    "MUL",     F34, MEMORY,          0x20),
+    (Mulf,   "MULF",    F34, MEMORY,          0x60),
+    (Mulr,   "MULR",    F2,  REGISTERS,       0x98),
+    (Norm,   "NORM",    F1,  NONE,            0xC8),
+    (Or,     "OR",      F34, MEMORY,          0x44),
+    (Rd,     "RD",      F34, MEMORY,          0xD8),
+    (Rmo,    "RMO",     F2,  REGISTERS,       0xAC),
+    (Rsub,   "RSUB",    F34, NONE,            0x4C),
+    (Shiftl, "SHIFTL",  F2,  REGISTER_NUMBER, 0xA4),
+    (Shiftr, "SHIFTR",  F2,  REGISTER_NUMBER, 0xA8),
+    (Sio,    "SIO",     F1,  NONE,            0xF0),
+    (Ssk,    "SSK",     F34, MEMORY,          0xEC),
+    (Sta,    "STA",     F34, MEMORY,          0x0C),
+    (Stb,    "STB",     F34, MEMORY,          0x78),
+    (Stch,   "STCH",    F34, MEMORY,          0x54),
+    (Stf,    "STF",     F34, MEMORY,          0x80),
+    (Sti,    "STI",     F34, MEMORY,          0xD4),
+    (Stl,    "STL",     F34, MEMORY,          0x14),
+    (Sts,    "STS",     F34, MEMORY,          0x7C),
+    (Stsw,   "STSW",    F34, MEMORY,          0xE8),
+    (Stt,    "STT",     F34, MEMORY,          0x84),
+    (Stx,    "STX",     F34, MEMORY,          0x10),
+    (Sub,    "SUB",     F34, MEMORY,          0x1C),
+    (Subf,   "SUBF",    F34, MEMORY,          0x5C),
+    (Subr,   "SUBR",    F2,  REGISTERS,       0x94),
+    (Svc,    "SVC",     F2,  MEMORY,          0xB0),
+    (Td,     "TD",      F34, MEMORY,          0xE0),
+    (Tio,    "TIO",     F1,  NONE,            0xF8),
+    (Tix,    "TIX",     F34, MEMORY,          0x2C),
+    (Tixr,   "TIXR",    F2,  REGISTER,        0xB8),
+    (Wd,     "WD",      F34, MEMORY,          0xDC),
+}
+
+directives! {
+    (Start,  "START",  Immediate),
+    (End,    "END",    Immediate),
+    (Byte,   "BYTE",   Bytes),
+    (Word,   "WORD",   Immediate),
+    (Resb,   "RESB",   Immediate),
+    (Resw,   "RESW",   Immediate),
+    (Base,   "BASE",   Label),
+    (Nobase, "NOBASE", None),
+    (Extref, "EXTREF", None),
+    (Extdef, "EXTDEF", None),
+    (Csect,  "CSECT",  None),
+    (Ltorg,  "LTORG",  None),
+    (Equ,    "EQU",    Immediate),
+    (Org,    "ORG",    Immediate),
+    (Use,    "USE",    Label),
+}
+
+/// An instruction or a directive, what a statement does
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mnemonic {
+    Instruction(Opcode),
+    Directive(Directive),
+}
+
+impl Mnemonic {
+    /// The mnemonic of a name in any case, the `+` of format 4 isn't part of it
+    pub fn parse(name: &str) -> Option<Mnemonic> {
+        let name = name.to_uppercase();
+        let opcode = Opcode::ALL.iter().find(|opcode| opcode.name() == name);
+        let directive = Directive::ALL.iter().find(|directive| directive.name() == name);
+        match (opcode, directive) {
+            (Some(&opcode), _) => Some(Mnemonic::Instruction(opcode)),
+            (None, Some(&directive)) => Some(Mnemonic::Directive(directive)),
+            (None, None) => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mnemonic::Instruction(opcode) => opcode.name(),
+            Mnemonic::Directive(directive) => directive.name(),
+        }
+    }
+
+    pub fn definition(&self) -> AssemblyDef {
+        match *self {
+            Mnemonic::Instruction(opcode) => opcode.definition(),
+            Mnemonic::Directive(directive) => directive.definition(),
+        }
+    }
+
+    pub fn opcode(&self) -> Option<Opcode> {
+        match *self {
+            Mnemonic::Instruction(opcode) => Some(opcode),
+            Mnemonic::Directive(_) => None,
+        }
+    }
+
+    pub fn directive(&self) -> Option<Directive> {
+        match *self {
+            Mnemonic::Instruction(_) => None,
+            Mnemonic::Directive(directive) => Some(directive),
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
     }
+}
 
 /// Checks if a provided directive exists in the Directive table and returns it or an error
 /// if the mnemonic doesn't exist
@@ -241,88 +406,9 @@ pub fn fetch_directive<'a>(instr_mnemonic: &str) -> Result<AssemblyDef, &'a str>
 }
 
 pub fn is_directive(mnemonic: &str) -> bool {
-    ASSEMBLER_DIRECTIVES.contains_key(mnemonic)
-}
-
-/// Assembler directives that will trigger a special action
-pub fn is_base_mode_directive(directive_mnemonic: &str) -> Option<String> {
-
-    let upper_cased = directive_mnemonic.to_uppercase();
-
-    match upper_cased.as_str() {
-        "BASE" | "NOBASE" => Some(upper_cased.to_owned()),
-        _ => None,
-    }
-}
-
-/// Indicates a directive that will generate object code
-pub fn is_decodable_directive(mnemonic: &str) -> bool {
-    match mnemonic.to_uppercase().as_str() {
-        "BYTE" | "WORD" => true,
-        _ => false,
-    }
+    ASSEMBLER_DIRECTIVES.contains_key(&mnemonic.to_uppercase())
 }
 
-lazy_static!{
-    static ref ASSEMBLER_DIRECTIVES: HashMap<String,AssemblyDef > = {
-            let assembler_directives :HashMap <String, AssemblyDef> = [
-             ("START".to_owned(),
-                  AssemblyDef::new("START".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Immediate),0xFF)),
-            ("END".to_owned(),
-                  AssemblyDef::new("END".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Immediate),0xFF)),
-            ("BYTE".to_owned(),
-                  AssemblyDef::new("BYTE".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Bytes),0xFF)),
-            ("WORD".to_owned(),
-                  AssemblyDef::new("WORD".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Immediate),0xFF)),
-            ("RESB".to_owned(),
-                  AssemblyDef::new("RESB".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Immediate),0xFF)),
-            ("RESW".to_owned(),
-                  AssemblyDef::new("RESW".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Immediate),0xFF)),
-            ("BASE".to_owned(),
-                  AssemblyDef::new("BASE".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Label),0xFF)),
-            ("NOBASE".to_owned(),
-                  AssemblyDef::new("NOBASE".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::None),0xFF)),
-            ("EXTREF".to_owned(),
-                  AssemblyDef::new("EXTREF".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::None),0xFF)),
-            ("EXTDEF".to_owned(),
-                  AssemblyDef::new("EXTDEF".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::None),0xFF)),
-            ("CSECT".to_owned(),
-                  AssemblyDef::new("CSECT".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::None),0xFF)),
-            ("LTORG".to_owned(),
-                  AssemblyDef::new("LTORG".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::None),0xFF)),
-            ("EQU".to_owned(),
-                  AssemblyDef::new("EQU".to_owned(),
-                  UnitOrPair::Unit(Format::None),
-                  UnitOrPair::Unit(OperandType::Immediate), 0xFF))
-                    ].iter().cloned().collect();
-            return assembler_directives;
-        };
-    }
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -332,6 +418,29 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn opcode_lookup() {
+        assert_eq!(fetch_by_opcode(0x03).unwrap().mnemonic, "LDA");
+        assert_eq!(fetch_by_opcode(0x4B).unwrap().mnemonic, "JSUB");
+        assert_eq!(fetch_by_opcode(0xB4).unwrap().mnemonic, "CLEAR");
+        assert!(fetch_by_opcode(0xFF).is_none());
+    }
+
+    #[test]
+    fn mnemonics() {
+        assert_eq!(Mnemonic::parse("lda"), Some(Mnemonic::Instruction(Opcode::Lda)));
+        assert_eq!(Mnemonic::parse("Resb"), Some(Mnemonic::Directive(Directive::Resb)));
+        assert_eq!(Mnemonic::parse("+LDA"), None);
+        assert_eq!(Mnemonic::parse("LOAD"), None);
+        assert_eq!(Mnemonic::Instruction(Opcode::Shiftl).to_string(), "SHIFTL");
+
+        // The tables are made from the enums
+        assert_eq!(Opcode::ALL.len(), 59);
+        assert_eq!(fetch_instruction(&"tixr".to_owned()).unwrap().op_code, 0xB8);
+        assert!(Directive::ALL.iter().all(|directive| is_directive(directive.name())));
+        assert!(is_instruction("tixr") && is_directive("Resw"));
+    }
+
     #[test]
     fn directive_table_check_negative() {
         let result: Result<AssemblyDef, &str> = fetch_directive(&"ended".to_owned());
@@ -1,70 +1,88 @@
 use std::collections::HashSet;
 use std::iter::Iterator;
-use parking_lot::RwLock;
-use std::ops::DerefMut;
 use pass_two::operand_translator::translate_literal;
 use literal::Literal;
 use regex::RegexSet;
 
 lazy_static!{
-    /// HashMap -> Keep the stored values unique
-    static ref LITERAL_TABLE: RwLock<HashSet<Literal>> = RwLock::new(HashSet::new());
-    static ref TEMP_LITERALS: RwLock<HashSet<String>> = RwLock::new(HashSet::new());    
-    static ref LIT_ID: RwLock<u32> = RwLock::new(0);
-    
-    static ref LIT_REGEX:RegexSet = RegexSet::new(&[r"^=C'[[:alnum:]]+'$",
+    static ref LIT_REGEX:RegexSet = RegexSet::new([r"^=C'([ -&(-~]|'')+'$",
                                                     r"^=X'[[:xdigit:]]+'$"]).unwrap();
 }
 
-pub fn insert_literal(literal: &String, address: u32) {
+/// Literals of a single assembly session
+#[derive(Debug, Default)]
+pub struct LiteralTable {
+    /// HashSet -> Keep the stored values unique
+    literals: HashSet<Literal>,
+    /// Literals waiting for the next LTORG, kept in the order of appearance
+    unresolved: Vec<String>,
+    next_id: u32,
+}
 
-    if get_literal(literal).is_some() {
-        return;
+impl LiteralTable {
+    pub fn new() -> LiteralTable {
+        LiteralTable::default()
     }
 
-    let mut temp = LIT_ID.write();
-    let mut lit_table = LITERAL_TABLE.write();
+    pub fn insert_literal(&mut self, literal: &str, address: u32) {
 
-    let mut literal_id: &mut u32 = temp.deref_mut();
-    let lit_val = translate_literal(&literal[1..]); // Don't translate using the = sign
+        if self.get_literal(literal).is_some() {
+            return;
+        }
 
-    let lit: Literal = Literal::new(("lit_".to_owned() + &literal_id.to_string()),
-                                    lit_val,
-                                    literal.clone(),
-                                    address);
+        let lit_val = translate_literal(&literal[1..]); // Don't translate using the = sign
 
-    *literal_id = *literal_id + 1;
+        let lit: Literal = Literal::new("lit_".to_owned() + &self.next_id.to_string(),
+                                        lit_val,
+                                        literal.to_owned(),
+                                        address);
 
+        self.next_id += 1;
 
-    // Ignore the error if insertion returns None,
-    // this means that the literal existed in the table
-    lit_table.insert(lit);
+        // Ignore the error if insertion returns None,
+        // this means that the literal existed in the table
+        self.literals.insert(lit);
+    }
 
-}
+    /// Insert a literal name to the temp literal table
+    pub fn insert_unresolved(&mut self, literal_name: &str) {
+        if !self.unresolved.iter().any(|lit| lit == literal_name) {
+            self.unresolved.push(literal_name.to_owned());
+        }
+    }
 
-/// Insert a literal name to the temp literal table
-pub fn insert_unresolved(literal_name: &str) {
-    TEMP_LITERALS.write().insert(literal_name.to_owned());
-}
+    /// Called when encountering LTORG or end of file
+    pub fn get_unresolved(&mut self) -> Vec<String> {
+        self.unresolved.drain(..).collect()
+    }
 
-/// Called when encountering LTORG or end of file
-pub fn get_unresolved() -> Vec<String> {
-    let ret: Vec<String> =
-        TEMP_LITERALS.read().iter().map(|li| li.to_owned()).collect::<Vec<String>>();
-    TEMP_LITERALS.write().clear();
-    ret
-}
+    pub fn get_literal(&self, name: &str) -> Option<Literal> {
+        let val: String = translate_literal(&name[1..]); // Remove the = sign
 
-pub fn get_literal(name: &str) -> Option<Literal> {
-    let val: String = translate_literal(&name[1..]); // Remove the = sign
-    let table = LITERAL_TABLE.read();
+        for lit in self.literals.iter() {
+            if *lit.value == val || lit.label == *name {
+                return Some(lit.clone());
+            }
+        }
+        None
+    }
 
-    for lit in table.iter() {
-        if *lit.value == val || lit.label == *name {
-            return Some(lit.clone());
+    /// Moves a placed literal, label is the one chosen by the assembler
+    pub fn move_literal(&mut self, label: &str, address: u32) {
+        let moved = self.literals.iter().find(|lit| lit.label == label).cloned();
+        if let Some(mut lit) = moved {
+            self.literals.remove(&lit);
+            lit.address = address;
+            self.literals.insert(lit);
         }
     }
-    None
+
+    /// All the placed literals ordered by address
+    pub fn get_all_literals(&self) -> Vec<Literal> {
+        let mut literals = self.literals.iter().cloned().collect::<Vec<Literal>>();
+        literals.sort_by_key(|lit| lit.address);
+        literals
+    }
 }
 
 pub fn is_literal(st: &str) -> bool {
@@ -73,12 +91,24 @@ pub fn is_literal(st: &str) -> bool {
 
 #[test]
 fn add_get_literal() {
-    insert_unresolved(&"=C'EOF'".to_owned());
+    let mut table = LiteralTable::new();
+    table.insert_unresolved("=C'EOF'");
 
-    for s in get_unresolved().iter() {
-        insert_literal(&s.clone(), 45);
+    for s in table.get_unresolved().iter() {
+        table.insert_literal(s, 45);
     }
 
-    assert!(get_literal(&"=C'EOF'".to_owned()).is_some());
-    assert!(get_literal(&"=X'454F46'".to_owned()).is_some());
+    assert!(table.get_literal("=C'EOF'").is_some());
+    assert!(table.get_literal("=X'454F46'").is_some());
+}
+
+#[test]
+fn unresolved_keep_their_order() {
+    let mut table = LiteralTable::new();
+    table.insert_unresolved("=X'05'");
+    table.insert_unresolved("=C'EOF'");
+    table.insert_unresolved("=X'05'");
+
+    assert_eq!(table.get_unresolved(), vec!["=X'05'", "=C'EOF'"]);
+    assert!(table.get_unresolved().is_empty());
 }
@@ -14,4 +14,5 @@ pub mod literal;
 pub mod base_table;
 pub mod symbol;
 pub mod symbol_tables;
+pub mod statement;
 mod tests;
@@ -1,6 +1,7 @@
 #![allow(dead_code)]
 
 use register::Register;
+use expression::Expr;
 use std::fmt;
 /**
 *  Instruction operand
@@ -44,7 +45,7 @@ pub enum Value {
     Label(String),
     Bytes(String),
     VarArgs(Vec<String>),
-    Expression(Vec<String>),
+    Expression(Expr),
     None,
 }
 
@@ -0,0 +1,314 @@
+/// What a line of code does, the operands of directives are checked and
+/// typed once by the parser so the passes match on them, ex. `RESB 10` is
+/// `Statement::Resb(10)`
+use instruction::AsmOperand;
+use instruction_set::{Directive, Mnemonic, Opcode};
+use operands::Value;
+use unit_or_pair::{UnitOrPair, unwrap_to_vec};
+use expression::Expr;
+use diagnostic::{Diagnostic, Code};
+use super::super::{char_constant, is_ascii_or_word_operand};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    /// A machine instruction, its operands depend on its format and are
+    /// checked against the instruction set in pass two
+    Instruction(Opcode),
+    Start(u32),
+    /// The first instruction to run, the start of the program without one
+    End(Option<Expr>),
+    Byte(Vec<u8>),
+    Word(Expr),
+    Resb(i32),
+    Resw(i32),
+    Base(String),
+    Nobase,
+    Extref(Vec<String>),
+    Extdef(Vec<String>),
+    Csect,
+    Ltorg,
+    Equ(Expr),
+    /// A bare ORG returns to where the location counter was
+    Org(Option<Expr>),
+    /// The program block, empty for the default block
+    Use(String),
+}
+
+/// A WORD is 24 bits, decimals take a sign
+const WORD_BITS: u32 = 23;
+
+impl Statement {
+    /// Types the operands of a mnemonic, the operands were split and parsed
+    /// by `parse_operands` of the file handler
+    pub fn parse(mnemonic: Mnemonic,
+                 operands: &UnitOrPair<AsmOperand>)
+                 -> Result<Statement, Diagnostic> {
+        let directive = match mnemonic {
+            Mnemonic::Instruction(opcode) => return Ok(Statement::Instruction(opcode)),
+            Mnemonic::Directive(directive) => directive,
+        };
+        let operands = unwrap_to_vec(operands);
+        let value = operands.into_iter().next().map(|operand| operand.val);
+
+        let statement = match (directive, value) {
+            (Directive::Start, None) => Statement::Start(0),
+            (Directive::Start, Some(Value::Raw(address))) => Statement::Start(address),
+            (Directive::End, None) => Statement::End(None),
+            (Directive::End, Some(Value::Raw(address))) => {
+                Statement::End(Some(Expr::Number(address as i32)))
+            }
+            (Directive::End, Some(Value::Label(label))) => {
+                Statement::End(Some(Expr::Symbol(label)))
+            }
+            (Directive::End, Some(_)) => {
+                return Err(Diagnostic::error(Code::InvalidOperand,
+                                             "invalid END operand".to_owned())
+                    .with_help("END takes the address or the label of the first instruction"
+                        .to_owned()))
+            }
+            (Directive::Byte, Some(Value::Bytes(constant))) => {
+                match constant_bytes(&constant) {
+                    Some(bytes) => Statement::Byte(bytes),
+                    None => return Err(invalid_operand(directive)),
+                }
+            }
+            (Directive::Word, Some(Value::SignedInt(x))) => {
+                if x >= 1 << WORD_BITS {
+                    return Err(Diagnostic::error(Code::ValueOutOfRange,
+                                                 format!("immediate value {} is out of the \
+                                                          {}-bit range",
+                                                         x,
+                                                         WORD_BITS)));
+                }
+                Statement::Word(Expr::Number(x))
+            }
+            (Directive::Word, Some(Value::Bytes(constant))) => {
+                match constant_bytes(&constant) {
+                    Some(ref bytes) if bytes.len() <= 3 => {
+                        let word = bytes.iter().fold(0, |word, &byte| word << 8 | byte as i32);
+                        Statement::Word(Expr::Number(word))
+                    }
+                    Some(bytes) => {
+                        return Err(Diagnostic::error(Code::InvalidOperand,
+                                                     format!("`{}` is {} bytes, a word holds 3",
+                                                             constant,
+                                                             bytes.len()))
+                            .with_help("use BYTE for longer constants".to_owned()))
+                    }
+                    None => return Err(invalid_operand(directive)),
+                }
+            }
+            (Directive::Word, Some(Value::Label(label))) => Statement::Word(Expr::Symbol(label)),
+            (Directive::Word, Some(Value::Expression(expr))) => Statement::Word(expr),
+            (Directive::Resb, Some(Value::SignedInt(count))) if count >= 0 => {
+                Statement::Resb(count)
+            }
+            (Directive::Resw, Some(Value::SignedInt(count))) if count >= 0 &&
+                                                               count.checked_mul(3).is_some() => {
+                Statement::Resw(count)
+            }
+            (Directive::Base, Some(Value::Label(label))) => Statement::Base(label),
+            (Directive::Nobase, _) => Statement::Nobase,
+            (Directive::Extref, Some(Value::VarArgs(names))) => Statement::Extref(names),
+            (Directive::Extdef, Some(Value::VarArgs(names))) => Statement::Extdef(names),
+            (Directive::Extref, _) |
+            (Directive::Extdef, _) => return Err(missing_external_names(directive)),
+            (Directive::Csect, _) => Statement::Csect,
+            (Directive::Ltorg, _) => Statement::Ltorg,
+            (Directive::Equ, Some(Value::Raw(x))) => Statement::Equ(Expr::Number(x as i32)),
+            (Directive::Equ, Some(Value::SignedInt(x))) => Statement::Equ(Expr::Number(x)),
+            (Directive::Equ, Some(Value::Label(label))) => Statement::Equ(Expr::Symbol(label)),
+            (Directive::Equ, Some(Value::Expression(expr))) => Statement::Equ(expr),
+            (Directive::Equ, Some(Value::Bytes(ref text))) if text == "*" => {
+                Statement::Equ(Expr::Locctr)
+            }
+            (Directive::Equ, Some(Value::Bytes(ref text))) if text.starts_with("X'") => {
+                match i32::from_str_radix(&text[2..text.len() - 1], 16) {
+                    Ok(x) => Statement::Equ(Expr::Number(x)),
+                    Err(e) => return Err(Diagnostic::error(Code::InvalidOperand, e.to_string())),
+                }
+            }
+            (Directive::Equ, _) => return Err(invalid_equ_operand()),
+            (Directive::Org, None) => Statement::Org(None),
+            (Directive::Org, Some(Value::Label(label))) => Statement::Org(Some(Expr::Symbol(label))),
+            (Directive::Org, Some(Value::Expression(expr))) => Statement::Org(Some(expr)),
+            (Directive::Org, Some(_)) => return Err(invalid_org_operand()),
+            (Directive::Use, None) => Statement::Use(String::new()),
+            (Directive::Use, Some(Value::Label(name))) => Statement::Use(name),
+            (_, None) => {
+                return Err(Diagnostic::error(Code::InvalidOperands,
+                                             format!("`{}` expects 1 operand", directive.name())))
+            }
+            (_, Some(_)) => return Err(invalid_operand(directive)),
+        };
+        Ok(statement)
+    }
+
+    /// The statement of a directive whose operand was reported as invalid,
+    /// it's assembled as if it had no operand and takes no space, a BASE
+    /// without a label sets no base
+    pub fn unparsed(mnemonic: Mnemonic) -> Statement {
+        match mnemonic {
+            Mnemonic::Instruction(opcode) => Statement::Instruction(opcode),
+            Mnemonic::Directive(directive) => {
+                match directive {
+                    Directive::Start => Statement::Start(0),
+                    Directive::End => Statement::End(None),
+                    Directive::Byte => Statement::Byte(Vec::new()),
+                    Directive::Word => Statement::Word(Expr::Number(0)),
+                    Directive::Resb => Statement::Resb(0),
+                    Directive::Resw => Statement::Resw(0),
+                    Directive::Base => Statement::Base(String::new()),
+                    Directive::Nobase => Statement::Nobase,
+                    Directive::Extref => Statement::Extref(Vec::new()),
+                    Directive::Extdef => Statement::Extdef(Vec::new()),
+                    Directive::Csect => Statement::Csect,
+                    Directive::Ltorg => Statement::Ltorg,
+                    Directive::Equ => Statement::Equ(Expr::Locctr),
+                    Directive::Org => Statement::Org(None),
+                    Directive::Use => Statement::Use(String::new()),
+                }
+            }
+        }
+    }
+
+    pub fn mnemonic(&self) -> Mnemonic {
+        let directive = match *self {
+            Statement::Instruction(opcode) => return Mnemonic::Instruction(opcode),
+            Statement::Start(_) => Directive::Start,
+            Statement::End(_) => Directive::End,
+            Statement::Byte(_) => Directive::Byte,
+            Statement::Word(_) => Directive::Word,
+            Statement::Resb(_) => Directive::Resb,
+            Statement::Resw(_) => Directive::Resw,
+            Statement::Base(_) => Directive::Base,
+            Statement::Nobase => Directive::Nobase,
+            Statement::Extref(_) => Directive::Extref,
+            Statement::Extdef(_) => Directive::Extdef,
+            Statement::Csect => Directive::Csect,
+            Statement::Ltorg => Directive::Ltorg,
+            Statement::Equ(_) => Directive::Equ,
+            Statement::Org(_) => Directive::Org,
+            Statement::Use(_) => Directive::Use,
+        };
+        Mnemonic::Directive(directive)
+    }
+
+    /// The bytes of a statement that isn't an instruction, instructions
+    /// take the bytes of their format
+    pub fn size(&self) -> i32 {
+        match *self {
+            Statement::Byte(ref bytes) => bytes.len() as i32,
+            Statement::Word(_) => 3,
+            Statement::Resb(count) => count,
+            Statement::Resw(count) => count * 3,
+            _ => 0,
+        }
+    }
+}
+
+/// The bytes of a C'...' or X'...' constant, an odd number of hex digits
+/// is padded with a leading 0
+pub fn constant_bytes(constant: &str) -> Option<Vec<u8>> {
+    if !is_ascii_or_word_operand(constant) {
+        return None;
+    }
+    let text = &constant[2..constant.len() - 1];
+    if constant.starts_with('C') || constant.starts_with('c') {
+        return Some(char_constant(constant).bytes().collect());
+    }
+    let padded = if text.len() % 2 == 1 {
+        format!("0{}", text)
+    } else {
+        text.to_owned()
+    };
+    (0..padded.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&padded[i..i + 2], 16).ok())
+        .collect()
+}
+
+fn invalid_operand(directive: Directive) -> Diagnostic {
+    Diagnostic::error(Code::InvalidOperand,
+                      format!("invalid operand for `{}`", directive.name()))
+        .with_help(format!("`{}` expects {}", directive.name(), expected_operand(directive)))
+}
+
+fn expected_operand(directive: Directive) -> &'static str {
+    match directive {
+        Directive::Start => "the hexadecimal address of the program",
+        Directive::Byte => "C'text' or X'hex'",
+        Directive::Resb | Directive::Resw => "a count that isn't negative",
+        Directive::Word => "a number, a C'...'/X'...' constant, a label or an expression",
+        Directive::Base => "a label",
+        Directive::Use => "a block name, or nothing for the default block",
+        _ => "no operands",
+    }
+}
+
+fn missing_external_names(directive: Directive) -> Diagnostic {
+    Diagnostic::error(Code::InvalidOperand,
+                      format!("{} expects a list of symbols", directive.name()))
+        .with_help(format!("ex. `{} BUFFER,LENGTH`", directive.name()))
+}
+
+pub fn invalid_org_operand() -> Diagnostic {
+    Diagnostic::error(Code::InvalidOperand, "invalid ORG operand".to_owned())
+        .with_help("ORG takes an address of the section, ex. `ORG TABLE+3`".to_owned())
+}
+
+fn invalid_equ_operand() -> Diagnostic {
+    Diagnostic::error(Code::InvalidOperand, "invalid EQU operand".to_owned())
+        .with_help("EQU takes a number, a label, an expression or `*`".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use operands::OperandType;
+
+    fn statement(mnemonic: &str, operand: Option<Value>) -> Result<Statement, Diagnostic> {
+        let operands = match operand {
+            Some(val) => UnitOrPair::Unit(AsmOperand::new(OperandType::None, val)),
+            None => UnitOrPair::None,
+        };
+        Statement::parse(Mnemonic::parse(mnemonic).unwrap(), &operands)
+    }
+
+    #[test]
+    fn directive_operands_are_typed() {
+        assert_eq!(statement("RESB", Some(Value::SignedInt(10))).unwrap(), Statement::Resb(10));
+        assert_eq!(statement("BYTE", Some(Value::Bytes("X'F1'".to_owned()))).unwrap(),
+                   Statement::Byte(vec![0xF1]));
+        assert_eq!(statement("BYTE", Some(Value::Bytes("C'EOF'".to_owned()))).unwrap(),
+                   Statement::Byte(b"EOF".to_vec()));
+        assert_eq!(statement("WORD", Some(Value::Bytes("C'EOF'".to_owned()))).unwrap(),
+                   Statement::Word(Expr::Number(0x454F46)));
+        assert_eq!(statement("EQU", Some(Value::Bytes("*".to_owned()))).unwrap(),
+                   Statement::Equ(Expr::Locctr));
+        assert_eq!(statement("USE", None).unwrap(), Statement::Use(String::new()));
+        assert_eq!(statement("LDA", None).unwrap(), Statement::Instruction(Opcode::Lda));
+        assert_eq!(statement("RESW", Some(Value::SignedInt(3))).unwrap().size(), 9);
+    }
+
+    #[test]
+    fn invalid_directive_operands() {
+        let code = |mnemonic, operand| statement(mnemonic, operand).unwrap_err().code;
+        assert_eq!(code("RESB", Some(Value::SignedInt(-1))), Code::InvalidOperand);
+        assert_eq!(code("RESW", None), Code::InvalidOperands);
+        assert_eq!(code("BYTE", Some(Value::Label("EOF".to_owned()))), Code::InvalidOperand);
+        assert_eq!(code("WORD", Some(Value::SignedInt(1 << 23))), Code::ValueOutOfRange);
+        assert_eq!(code("WORD", Some(Value::Bytes("C'LONG'".to_owned()))),
+                   Code::InvalidOperand);
+        assert_eq!(code("EXTREF", None), Code::InvalidOperand);
+        assert_eq!(code("ORG", Some(Value::SignedInt(3))), Code::InvalidOperand);
+    }
+
+    #[test]
+    fn odd_hex_constants_are_padded() {
+        assert_eq!(constant_bytes("X'F'"), Some(vec![0x0F]));
+        assert_eq!(constant_bytes("X'ABC'"), Some(vec![0x0A, 0xBC]));
+        assert_eq!(constant_bytes("C'IT''S'"), Some(b"IT'S".to_vec()));
+        assert_eq!(constant_bytes("=C'EOF'"), None);
+    }
+}
@@ -1,5 +1,6 @@
 use std::hash::{Hash, Hasher};
 use std::cmp::{PartialEq, Eq};
+use diagnostic::Span;
 
 #[derive(Debug,PartialEq,Eq)]
 pub enum SymbolType {
@@ -13,7 +14,10 @@ pub struct Symbol {
     name: String,
     address: i32,
     control_section: String,
+    block: String, // Program block of a relative symbol, empty for the default block
     is_relative: bool,
+    line: i32, // Where the symbol was defined, 0 for symbols made by the assembler
+    span: Option<Span>,
 }
 
 impl Symbol {
@@ -26,14 +30,47 @@ impl Symbol {
             name: name.to_owned(),
             address: addr,
             control_section: csect,
+            block: String::new(),
             is_relative: false,
+            line: 0,
+            span: None,
         }
     }
 
+    /// Records where the symbol was defined in the source
+    pub fn defined_at(mut self, line: i32, span: Option<Span>) -> Symbol {
+        self.line = line;
+        self.span = span;
+        self
+    }
+
+    /// Puts the symbol in a program block of USE
+    pub fn in_block(mut self, block: &str) -> Symbol {
+        self.block = block.to_owned();
+        self
+    }
+
+    pub fn get_block(&self) -> String {
+        self.block.clone()
+    }
+
+    pub fn get_line(&self) -> i32 {
+        self.line
+    }
+
+    pub fn get_span(&self) -> Option<Span> {
+        self.span
+    }
+
     pub fn mark_relative(&mut self) {
         self.is_relative = true
     }
 
+    /// Relative symbols are addresses, they move with their control section
+    pub fn is_relative(&self) -> bool {
+        self.is_relative
+    }
+
     pub fn set_address(&mut self, addr: i32) {
         self.address = addr
     }
@@ -54,7 +91,11 @@ impl Symbol {
 
 impl Clone for Symbol {
     fn clone(&self) -> Symbol {
-        Symbol::new(&self.name, self.address, &self.control_section)
+        let mut sym = Symbol::new(&self.name, self.address, &self.control_section)
+            .defined_at(self.line, self.span)
+            .in_block(&self.block);
+        sym.is_relative = self.is_relative;
+        sym
     }
 }
 
@@ -1,21 +1,11 @@
-use parking_lot::Mutex;
 use symbol::{Symbol, SymbolType};
+use diagnostic::{Diagnostic, Code};
 use std::collections::{HashSet, HashMap};
-use std::time::Duration;
 use std::borrow::Borrow;
 use std::string::ToString;
 
-const LOCK_DURATION_MILLIS: u64 = 50;
-
 // TODO: print all symtab import and export tables
 
-lazy_static!{    
-    // The master table contains all the control sections' symbol tables, it's the main
-    // data structure in this module
-    static ref MASTER_TABLE:Mutex<MasterTable> = Mutex::new(MasterTable::new());
-    static ref LOCK_DURATION: Duration = {Duration::from_millis(LOCK_DURATION_MILLIS)};
-}
-
 #[derive(Debug)]
 pub struct TableResult {
     pub symbol: Symbol,
@@ -47,24 +37,33 @@ impl TableResult {
     }
 }
 
+/// The master table contains all the control sections' symbol tables, it's the main
+/// data structure in this module, each assembly session owns one
 #[derive(Debug)]
 pub struct MasterTable {
     mapping: HashMap<String, Box<CsectSymTab>>,
 }
 
+impl Default for MasterTable {
+    fn default() -> MasterTable {
+        MasterTable::new()
+    }
+}
+
 impl MasterTable {
-    fn new() -> MasterTable {
+    pub fn new() -> MasterTable {
         let mut table = MasterTable { mapping: HashMap::new() };
-        table.define_csect(&String::new()).unwrap(); // Define the default section
-        assert!(table.has_csect(&String::new()));
+        table.define_control_section("").unwrap(); // Define the default section
+        assert!(table.has_csect(""));
         table
     }
 
     /// Called when a CSECT is encountered
-    fn define_csect(&mut self, csect: &str) -> Result<(), String> {
+    pub fn define_control_section(&mut self, csect: &str) -> Result<(), Diagnostic> {
 
         if self.has_csect(csect) {
-            return Err(format!("Redefinition of control section {{ {} }} ", csect));
+            return Err(Diagnostic::error(Code::CsectRedefinition,
+                                         format!("redefinition of control section `{}`", csect)));
         }
 
         let table = Box::new(CsectSymTab::new(csect));
@@ -75,29 +74,59 @@ impl MasterTable {
     }
 
     /// Called when a label is declared in the current code section
-    fn define_local_symbol(&mut self,
-                           sym_name: &str,
-                           addr: i32,
-                           csect: &str)
-                           -> Result<(), String> {
-
-        let csect_tab: &mut CsectSymTab = self.get_csect_table_write(csect);
-        if csect_tab.has_local(sym_name) {
-            return Err(format!("Redefinition of label {{ {} }}", sym_name));
+    pub fn define_local_symbol(&mut self,
+                               sym_name: &str,
+                               addr: i32,
+                               csect: &str)
+                               -> Result<(), Diagnostic> {
+        self.define_symbol(Symbol::new(sym_name, addr, csect))
+    }
+
+    /// Adds a symbol to the table of its control section, the symbol
+    /// remembers where it was defined to point at it on redefinition
+    pub fn define_symbol(&mut self, sym: Symbol) -> Result<(), Diagnostic> {
+        let csect_tab: &mut CsectSymTab = self.get_csect_table_write(&sym.get_control_section());
+        if let Ok(old) = csect_tab.find_local(&sym.get_name()) {
+            let mut err = Diagnostic::error(Code::LabelRedefinition,
+                                            format!("redefinition of label `{}`",
+                                                    sym.get_name()));
+            if old.get_line() > 0 {
+                err = err.with_note_at("label first defined here".to_owned(),
+                                       old.get_line(),
+                                       old.get_span());
+            }
+            return Err(err);
         }
 
-        let sym: Symbol = Symbol::new(sym_name, addr, csect);
         csect_tab.insert_local_symbol(sym);
 
         Ok(())
     }
 
+    /// Moves the relative symbols of a program block of the section by
+    /// offset, used when the blocks are laid out after pass one
+    pub fn move_block(&mut self, csect: &str, block: &str, offset: i32) {
+        let table = self.get_csect_table_write(csect);
+        for sym in table.local_symbols.values_mut() {
+            if sym.is_relative() && sym.get_block() == block {
+                let address = sym.get_address() + offset;
+                sym.set_address(address);
+            }
+        }
+    }
+
     /// Called when an EXT REF symbol is encountered
-    fn define_import_symbol(&mut self, sym_name: &str, csect: &str) -> Result<(), String> {
+    pub fn define_imported_symbol(&mut self,
+                                  sym_name: &str,
+                                  csect: &str)
+                                  -> Result<(), Diagnostic> {
         let csect_tab: &mut CsectSymTab = self.get_csect_table_write(csect);
 
         if csect_tab.imports(csect) {
-            return Err(format!("Multiple imports of {{ {} }} in {{ {} }}", sym_name, csect));
+            return Err(Diagnostic::error(Code::DuplicateImport,
+                                         format!("multiple imports of `{}` in `{}`",
+                                                 sym_name,
+                                                 csect)));
         }
 
         csect_tab.insert_import_symbol(sym_name);
@@ -106,11 +135,17 @@ impl MasterTable {
     }
 
     /// Called when an EXT DEF symbol is encountered
-    fn define_export_symbol(&mut self, sym_name: &str, csect: &str) -> Result<(), String> {
+    pub fn define_exported_symbol(&mut self,
+                                  sym_name: &str,
+                                  csect: &str)
+                                  -> Result<(), Diagnostic> {
         let csect_tab: &mut CsectSymTab = self.get_csect_table_write(csect);
 
         if csect_tab.exports(sym_name) {
-            return Err(format!("Multiple exports of {{ {} }} in {{ {} }}", sym_name, csect));
+            return Err(Diagnostic::error(Code::DuplicateExport,
+                                         format!("multiple exports of `{}` in `{}`",
+                                                 sym_name,
+                                                 csect)));
         }
 
         csect_tab.insert_export_symbol(sym_name);
@@ -118,23 +153,46 @@ impl MasterTable {
         Ok(())
     }
 
+    pub fn define_exported_symbols(&mut self,
+                                   symbols: &[String],
+                                   csect: &str)
+                                   -> Result<(), Vec<Diagnostic>> {
+        let errs: Vec<Diagnostic> = symbols.iter()
+            .filter_map(|item| self.define_exported_symbol(item, csect).err())
+            .collect();
+        if errs.is_empty() { Ok(()) } else { Err(errs) }
+    }
+
+    pub fn define_imported_symbols(&mut self,
+                                   symbols: &[String],
+                                   csect: &str)
+                                   -> Result<(), Vec<Diagnostic>> {
+        let errs: Vec<Diagnostic> = symbols.iter()
+            .filter_map(|item| self.define_imported_symbol(item, csect).err())
+            .collect();
+        if errs.is_empty() { Ok(()) } else { Err(errs) }
+    }
+
     /// This function will be called in pass2, so we can detect errors here
-    fn resolve_label(&mut self, sym_name: &str, csect: &str) -> Result<TableResult, String> {
+    pub fn get_symbol(&self, sym_name: &str, csect: &str) -> Result<TableResult, Diagnostic> {
         // Try to find in the csect local
-        let mut errs: Vec<String> = Vec::new();
         let csect_table = self.get_csect_table_read(csect);
 
-        match csect_table.find_local(sym_name) {
-            Ok(sym) => return Ok(TableResult::new(sym.clone(), SymbolType::Local)),
-            Err(e) => errs.push(e),
-        };
+        if let Ok(sym) = csect_table.find_local(sym_name) {
+            return Ok(TableResult::new(sym.clone(), SymbolType::Local));
+        }
 
         match self.resolve_imported(sym_name, csect) {
-            Ok(sym) => return Ok(TableResult::new(sym.clone(), SymbolType::Imported)),
-            Err(e) => errs.push(e),
+            Ok(sym) => Ok(TableResult::new(sym.clone(), SymbolType::Imported)),
+            Err(e) => {
+                let message = if csect.is_empty() {
+                    format!("undefined symbol `{}`", sym_name)
+                } else {
+                    format!("undefined symbol `{}` in control section `{}`", sym_name, csect)
+                };
+                Err(Diagnostic::error(Code::UndefinedSymbol, message).with_note(e))
+            }
         }
-
-        Err(errs.join("\n"))
     }
 
     /// Finds the parent section of the given symbol name
@@ -146,13 +204,13 @@ impl MasterTable {
             }
         }
 
-        Err(format!("Symbol isn't exported anywhere"))
+        Err(format!("`{}` is imported but isn't defined in any control section", sym_name))
     }
 
     /// Resolves an externally defined symbol for a given control section
     fn resolve_imported(&self, sym_name: &str, csect: &str) -> Result<&Symbol, String> {
         if !self.get_csect_table_read(csect).imports(sym_name) {
-            return Err(format!("{{ {} }} isn't imported", sym_name));
+            return Err(format!("`{}` isn't a local symbol nor is it imported", sym_name));
         }
 
         match self.resolve_exported(sym_name) {
@@ -161,14 +219,10 @@ impl MasterTable {
         }
     }
 
-    fn get_all_symbols(&self) -> HashSet<Symbol> {
+    pub fn get_all_symbols(&self) -> HashSet<Symbol> {
         let mut result: HashSet<Symbol> = HashSet::new();
-        for (_, table) in &self.mapping {
-            let table: &CsectSymTab = table;
-            table.local_symbols
-                .iter()
-                .map(|(_, sym)| result.insert(sym.clone()))
-                .collect::<Vec<bool>>();
+        for table in self.mapping.values() {
+            result.extend(table.local_symbols.values().cloned());
         }
         result
     }
@@ -191,7 +245,7 @@ impl MasterTable {
         return table;
     }
 
-    fn get_csect_tables(&self) -> Vec<String> {
+    pub fn get_all_section_tables(&self) -> Vec<String> {
         let mut result: Vec<String> = Vec::new();
 
         for b in self.mapping.values() {
@@ -266,145 +320,96 @@ impl CsectSymTab {
     }
 }
 
-pub fn define_control_section(csect: &str) -> Result<(), String> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    master_table.define_csect(csect)
-}
-
-pub fn define_local_symbol(sym_name: &str, addr: i32, csect: &str) -> Result<(), String> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    master_table.define_local_symbol(sym_name, addr, csect)
-}
-
-
-pub fn define_exported_symbols(symbols: &Vec<String>, csect: &str) -> Result<(), String> {
-    let mut errs: Vec<String> = Vec::new();
-
-    let result = symbols.iter()
-        .map(|item| {
-            define_exported_symbol(&item, csect).or_else(|e| {
-                errs.push(e);
-                Err(())
-            })
-        })
-        .all(|r| r.is_ok());
-    if result { Ok(()) } else { Err(errs.join("\t")) }
-}
-
-
-pub fn define_exported_symbol(sym_name: &str, csect: &str) -> Result<(), String> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    master_table.define_export_symbol(sym_name, csect)
-}
-
-pub fn define_imported_symbols(symbols: &Vec<String>, csect: &str) -> Result<(), String> {
-    let mut errs: Vec<String> = Vec::new();
-
-    let result = symbols.iter()
-        .map(|item| {
-            define_imported_symbol(&item, csect).or_else(|e| {
-                errs.push(e);
-                Err(())
-            })
-        })
-        .all(|r| r.is_ok());
-    if result { Ok(()) } else { Err(errs.join("\t")) }
-}
-
-
-pub fn define_imported_symbol(sym_name: &str, csect: &str) -> Result<(), String> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    master_table.define_import_symbol(sym_name, csect)
-}
-
-pub fn get_symbol(sym_name: &str, csect: &str) -> Result<TableResult, String> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    match master_table.resolve_label(sym_name, csect) {
-        Ok(table_res) => Ok(table_res),
-        Err(e) => Err(e),
-    }
-}
-
-pub fn get_all_symbols() -> HashSet<Symbol> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    master_table.get_all_symbols()
-}
-
-pub fn get_all_section_tables() -> Vec<String> {
-    let ref mut master_table: MasterTable = *MASTER_TABLE.try_lock_for(*LOCK_DURATION).unwrap();
-    master_table.get_csect_tables()
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     const DEFAULT_CONTROL_SECTION: &str = "";
     #[test]
     fn simple_local_symbol() {
-        let (name, csect) = create_local_variable("X1", DEFAULT_CONTROL_SECTION);
-        let sym = get_symbol(name, csect).unwrap();
+        let mut table = MasterTable::new();
+        let (name, csect) = create_local_variable(&mut table, "X1", DEFAULT_CONTROL_SECTION);
+        let sym = table.get_symbol(name, csect).unwrap();
         check_var(name, csect, sym);
     }
 
     #[test]
     fn simple_export() {
-        let (name, csect) = create_local_variable("X2-0", DEFAULT_CONTROL_SECTION);
-        define_exported_symbol(name, csect).unwrap();
+        let mut table = MasterTable::new();
+        let (name, csect) = create_local_variable(&mut table, "X2", DEFAULT_CONTROL_SECTION);
+        table.define_exported_symbol(name, csect).unwrap();
 
-        let ext_csect = "csect2-0"; // csect2 is defined below, the sym tab is static
-        define_control_section(ext_csect).unwrap();
-        define_imported_symbol(name, ext_csect).unwrap();
+        let ext_csect = "csect2";
+        table.define_control_section(ext_csect).unwrap();
+        table.define_imported_symbol(name, ext_csect).unwrap();
 
-        let sym = get_symbol(name, ext_csect).unwrap();
+        let sym = table.get_symbol(name, ext_csect).unwrap();
         check_var(&name, &csect, sym);
     }
 
     #[test]
     #[should_panic]
     fn simple_failing_export() {
+        let mut table = MasterTable::new();
         let (name, csect) = ("X2", DEFAULT_CONTROL_SECTION); // Undecalred variable
-        define_exported_symbol(name, csect).unwrap();
+        table.define_exported_symbol(name, csect).unwrap();
 
         let ext_csect = "csect2";
-        define_control_section(ext_csect).unwrap();
-        define_imported_symbol(name, ext_csect).unwrap();
+        table.define_control_section(ext_csect).unwrap();
+        table.define_imported_symbol(name, ext_csect).unwrap();
 
-        let sym = get_symbol(name, ext_csect).unwrap();
+        let sym = table.get_symbol(name, ext_csect).unwrap();
         check_var(&name, &csect, sym);
     }
 
     #[test]
     fn simple_export_again() {
-        let (name, csect) = create_local_variable("X2-1", "TROL_SECTION");
+        let mut table = MasterTable::new();
+        let (name, csect) = create_local_variable(&mut table, "X2", "TROL_SECTION");
 
-        define_exported_symbol(name, csect).unwrap();
+        table.define_exported_symbol(name, csect).unwrap();
 
-        let ext_csect = "csect2-1";
-        define_control_section(ext_csect).unwrap();
-        define_imported_symbol(name, ext_csect).unwrap();
+        let ext_csect = "csect2";
+        table.define_control_section(ext_csect).unwrap();
+        table.define_imported_symbol(name, ext_csect).unwrap();
 
-        let sym = get_symbol(name, ext_csect).unwrap();
+        let sym = table.get_symbol(name, ext_csect).unwrap();
         check_var(&name, &csect, sym);
     }
 
+    #[test]
+    fn redefinition_points_at_first_definition() {
+        let mut table = MasterTable::new();
+        table.define_symbol(Symbol::new("LOOP", 3, "").defined_at(2, None)).unwrap();
+
+        let err = table.define_symbol(Symbol::new("LOOP", 9, "").defined_at(7, None))
+            .unwrap_err();
+        assert_eq!(err.code, Code::LabelRedefinition);
+        assert_eq!(err.notes[0].line, Some(2));
+    }
+
+    #[test]
+    fn tables_are_independent() {
+        let mut first = MasterTable::new();
+        let second = MasterTable::new();
+        create_local_variable(&mut first, "X1", DEFAULT_CONTROL_SECTION);
+
+        assert!(first.get_symbol("X1", DEFAULT_CONTROL_SECTION).is_ok());
+        assert!(second.get_symbol("X1", DEFAULT_CONTROL_SECTION).is_err());
+    }
+
     fn check_var(expected_name: &str, expected_csect: &str, found: TableResult) {
         assert!(expected_csect == found.get_control_section());
         assert!(expected_name == found.get_name());
     }
 
-    fn create_local_variable<'a>(name: &'a str, csect: &'a str) -> (&'a str, &'a str) {
+    fn create_local_variable<'a>(table: &mut MasterTable,
+                                 name: &'a str,
+                                 csect: &'a str)
+                                 -> (&'a str, &'a str) {
         if !csect.is_empty() {
-            define_control_section(csect).unwrap();
+            table.define_control_section(csect).unwrap();
         }
-        define_local_symbol(name, 0, csect).unwrap();
+        table.define_local_symbol(name, 0, csect).unwrap();
         (name, csect)
     }
-
-    fn create_local_variable_with_addr(name: &str, addr: i32, csect: &str) -> (String, String) {
-        if !csect.is_empty() {
-            define_control_section(csect).unwrap();
-        }
-        define_local_symbol(name, addr, csect).unwrap();
-        (name.to_owned(), csect.to_owned())
-    }
 }
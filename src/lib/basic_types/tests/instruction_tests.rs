@@ -3,12 +3,14 @@
 mod instuction_tests {
 
     use instruction::Instruction;
+    use instruction_set::Opcode;
+    use statement::Statement;
 
     use formats::Format;
 
     #[test]
     fn format_3_base_relative() {
-        let mut instr: Instruction = Instruction::new_simple("load".to_owned());
+        let mut instr: Instruction = Instruction::new_simple(Statement::Instruction(Opcode::Lda));
 
         instr.set_format(Format::Three);
         instr.set_base_relative();
@@ -22,7 +24,7 @@ mod instuction_tests {
 
     #[test]
     fn format_4() {
-        let mut instr: Instruction = Instruction::new_simple("load".to_owned());
+        let mut instr: Instruction = Instruction::new_simple(Statement::Instruction(Opcode::Lda));
 
         // Not setting the E flag on a format four instruction is an error
         instr.set_format(Format::Four);
@@ -35,7 +37,7 @@ mod instuction_tests {
 
     #[test]
     fn format_3_pc_relative() {
-        let mut instr: Instruction = Instruction::new_simple("format 4 rel - PC".to_owned());
+        let mut instr: Instruction = Instruction::new_simple(Statement::Instruction(Opcode::Lda));
 
         instr.set_pc_relative();
         match instr.get_flags_value() {
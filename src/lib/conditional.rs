@@ -0,0 +1,225 @@
+/// Conditional assembly, SET variables are written `&NAME` and hold a text
+/// that's put in their place in the statements that follow, ex.
+///
+///  &SIZE    SET     4*1024
+///           IF      (&DEV EQ 'F1')
+///           ...
+///           ELSE
+///           ...
+///           ENDIF
+///
+/// A SET operand is a quoted text or an expression of numbers, its value is
+/// kept in decimal. Conditions compare two operands with EQ, NE, LT, LE, GT
+/// or GE, as numbers when both are numbers and as texts otherwise.
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use diagnostic::{Diagnostic, Code};
+use expression;
+
+/// How many times a WHILE loop repeats before it's taken as endless
+pub const MAX_ITERATIONS: usize = 4096;
+
+/// The SET variables defined so far, and the ones given on the command line
+#[derive(Debug, Default)]
+pub struct Variables {
+    values: HashMap<String, String>,
+}
+
+impl Variables {
+    pub fn new() -> Variables {
+        Variables::default()
+    }
+
+    /// The name is without the `&`
+    pub fn set(&mut self, name: &str, value: String) {
+        self.values.insert(name.to_owned(), value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|value| value.as_str())
+    }
+
+    /// Puts the values of the variables in place of their `&NAME`, an
+    /// undefined variable is an error unless it's allowed to be empty
+    pub fn substitute(&self, text: &str, undefined_empty: bool) -> Result<String, Diagnostic> {
+        let chars = text.chars().collect::<Vec<char>>();
+        let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let mut out = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] != '&' || !chars.get(i + 1).is_some_and(|&next| is_name(next)) {
+                out.push(chars[i]);
+                i += 1;
+                continue;
+            }
+
+            let end = (i + 1..chars.len()).find(|&j| !is_name(chars[j])).unwrap_or(chars.len());
+            let name = chars[i + 1..end].iter().collect::<String>();
+            match self.get(&name) {
+                Some(value) => out.push_str(value),
+                None if undefined_empty => (),
+                None => {
+                    return Err(Diagnostic::error(Code::UndefinedVariable,
+                                                 format!("`&{}` isn't defined", name))
+                        .with_help(format!("define it with `&{} SET value` or `-D {}=value`",
+                                           name,
+                                           name)));
+                }
+            }
+            i = end;
+        }
+        Ok(out)
+    }
+}
+
+/// The value of a SET operand, the text between the quotes or the value of
+/// the expression
+pub fn set_value(operand: &str) -> Result<String, Diagnostic> {
+    let operand = operand.trim();
+    if let Some(text) = unquote(operand) {
+        return Ok(text.to_owned());
+    }
+    match number(operand) {
+        Some(Ok(value)) => Ok(value.to_string()),
+        Some(Err(e)) => Err(e),
+        None => {
+            Err(Diagnostic::error(Code::InvalidExpression,
+                                  format!("invalid SET operand `{}`", operand))
+                .with_help("SET takes an expression of numbers or a quoted text, ex. `4*1024` \
+                            or `'F1'`"
+                    .to_owned()))
+        }
+    }
+}
+
+/// Evaluates the condition of IF or WHILE, ex. `(&SIZE GT 100)`
+pub fn condition(text: &str) -> Result<bool, Diagnostic> {
+    let invalid = || {
+        Diagnostic::error(Code::InvalidConditional,
+                          format!("invalid condition `{}`", text.trim()))
+            .with_help("conditions are written `(operand OP operand)`, OP is EQ, NE, LT, LE, \
+                        GT or GE"
+                .to_owned())
+    };
+
+    let mut inner = text.trim();
+    if inner.starts_with('(') && inner.ends_with(')') {
+        inner = &inner[1..inner.len() - 1];
+    }
+    let words = inner.split_whitespace().collect::<Vec<&str>>();
+    let operators = words.iter()
+        .enumerate()
+        .filter(|(_, word)| relation(word).is_some())
+        .map(|(i, _)| i)
+        .collect::<Vec<usize>>();
+    if operators.len() != 1 {
+        return Err(invalid());
+    }
+
+    let at = operators[0];
+    let accepts = relation(words[at]).unwrap();
+    let left = operand(&words[..at].join(" "))?;
+    let right = operand(&words[at + 1..].join(" "))?;
+    let ordering = match (left.number, right.number) {
+        (Some(a), Some(b)) => a.cmp(&b),
+        _ => left.text.cmp(&right.text),
+    };
+    Ok(accepts(ordering))
+}
+
+/// An operand of a condition, its text is compared when either operand
+/// isn't a number
+struct Operand {
+    text: String,
+    number: Option<i32>,
+}
+
+fn operand(text: &str) -> Result<Operand, Diagnostic> {
+    if let Some(text) = unquote(text) {
+        return Ok(Operand {
+            text: text.to_owned(),
+            number: None,
+        });
+    }
+    Ok(Operand {
+        text: text.to_owned(),
+        number: number(text).transpose()?,
+    })
+}
+
+/// Whether an ordering satisfies a relational operator
+fn relation(word: &str) -> Option<fn(Ordering) -> bool> {
+    let accepts: fn(Ordering) -> bool = match word.to_uppercase().as_str() {
+        "EQ" => |o| o == Ordering::Equal,
+        "NE" => |o| o != Ordering::Equal,
+        "LT" => |o| o == Ordering::Less,
+        "LE" => |o| o != Ordering::Greater,
+        "GT" => |o| o == Ordering::Greater,
+        "GE" => |o| o != Ordering::Less,
+        _ => return None,
+    };
+    Some(accepts)
+}
+
+/// The value of an expression without symbols, None when it isn't one
+fn number(text: &str) -> Option<Result<i32, Diagnostic>> {
+    let expr = expression::parse(text).ok()?;
+    if !expr.symbols().is_empty() || expr.uses_locctr() {
+        return None;
+    }
+    Some(expr.evaluate(0, &|_: &str| unreachable!()).map(|result| result.value))
+}
+
+fn unquote(text: &str) -> Option<&str> {
+    if text.len() >= 2 && text.starts_with('\'') && text.ends_with('\'') {
+        Some(&text[1..text.len() - 1])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn substitution() {
+        let mut variables = Variables::new();
+        variables.set("DEV", "F1".to_owned());
+        variables.set("N", "3".to_owned());
+
+        assert_eq!(variables.substitute("TD =X'&DEV'", false).unwrap(), "TD =X'F1'");
+        assert_eq!(variables.substitute("WORD &N*&N", false).unwrap(), "WORD 3*3");
+        assert_eq!(variables.substitute("X & Y", false).unwrap(), "X & Y");
+        assert_eq!(variables.substitute("LDA &SIZE", false).unwrap_err().code,
+                   Code::UndefinedVariable);
+        assert_eq!(variables.substitute("&SIZE EQ ''", true).unwrap(), " EQ ''");
+    }
+
+    #[test]
+    fn set_values() {
+        assert_eq!(set_value("4*1024").unwrap(), "4096");
+        assert_eq!(set_value("(7+2)/3-5").unwrap(), "-2");
+        assert_eq!(set_value("'F1'").unwrap(), "F1");
+        assert_eq!(set_value("''").unwrap(), "");
+        assert_eq!(set_value("BUFFER+1").unwrap_err().code, Code::InvalidExpression);
+    }
+
+    #[test]
+    fn conditions() {
+        assert!(condition("(3 EQ 1+2)").unwrap());
+        assert!(condition("(10 gt 9)").unwrap());
+        assert!(!condition("(10 LT 9)").unwrap());
+        assert!(condition("(F1 EQ 'F1')").unwrap());
+        assert!(condition("(A LT B)").unwrap());
+        // An empty variable against an empty text
+        assert!(condition("( EQ '')").unwrap());
+        assert!(condition("(05 NE '')").unwrap());
+        assert!(condition("(05 EQ '05')").unwrap());
+        assert!(condition("(05 EQ 5)").unwrap());
+
+        assert_eq!(condition("(1 2)").unwrap_err().code, Code::InvalidConditional);
+        assert_eq!(condition("(1 EQ 1 EQ 1)").unwrap_err().code, Code::InvalidConditional);
+    }
+}
@@ -0,0 +1,108 @@
+/// Renders diagnostics as JSON objects, one per line, for tools that
+/// need to parse the assembler output (editors, CI annotations)
+///
+/// {"type":"diagnostic","file":"copy.asm","line":17,"column":1,"severity":"error",
+///  "code":"E0020","message":"redefinition of label `RLOOP`","notes":[],"help":null}
+/// {"type":"summary","errors":1,"warnings":0}
+use super::{Diagnostic, Severity};
+
+pub fn to_json(diag: &Diagnostic) -> String {
+    let notes = diag.notes
+        .iter()
+        .map(|note| {
+            format!("{{\"message\":{},\"line\":{},\"column\":{}}}",
+                    string(&note.message),
+                    number(note.line.unwrap_or(0) as usize),
+                    number(note.span.map_or(0, |span| span.start + 1)))
+        })
+        .collect::<Vec<String>>();
+
+    format!("{{\"type\":\"diagnostic\",\"file\":{},\"line\":{},\"column\":{},\
+             \"severity\":{},\"code\":{},\"message\":{},\"notes\":[{}],\"help\":{}}}",
+            string(&diag.file),
+            number(diag.line.max(0) as usize),
+            number(diag.column()),
+            string(&diag.severity.to_string()),
+            string(diag.code.as_str()),
+            string(&diag.message),
+            notes.join(","),
+            diag.help.as_ref().map_or("null".to_owned(), |help| string(help)))
+}
+
+/// The last object printed, counts the diagnostics by severity
+pub fn summary(diagnostics: &[Diagnostic]) -> String {
+    let count = |severity| diagnostics.iter().filter(|d| d.severity == severity).count();
+    format!("{{\"type\":\"summary\",\"errors\":{},\"warnings\":{}}}",
+            count(Severity::Error),
+            count(Severity::Warning))
+}
+
+/// Unknown lines and columns are 0 internally, null in the output
+fn number(value: usize) -> String {
+    if value == 0 {
+        "null".to_owned()
+    } else {
+        value.to_string()
+    }
+}
+
+fn string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostic::{Code, Span};
+
+    #[test]
+    fn diagnostic_to_json() {
+        let diag = Diagnostic::error(Code::LabelRedefinition,
+                                     "redefinition of label `LOOP`".to_owned())
+            .in_file("copy.asm")
+            .at_line(3)
+            .with_span(Some(Span::new(0, 4)))
+            .with_note_at("label first defined here".to_owned(), 2, Some(Span::new(0, 4)));
+
+        assert_eq!(to_json(&diag),
+                   "{\"type\":\"diagnostic\",\"file\":\"copy.asm\",\"line\":3,\"column\":1,\
+                    \"severity\":\"error\",\"code\":\"E0020\",\
+                    \"message\":\"redefinition of label `LOOP`\",\
+                    \"notes\":[{\"message\":\"label first defined here\",\"line\":2,\"column\":1}],\
+                    \"help\":null}");
+    }
+
+    #[test]
+    fn unknown_position_and_escaping() {
+        let diag = Diagnostic::error(Code::Io, "can't open \"a\\b.asm\"".to_owned())
+            .with_help("check the path\n".to_owned());
+
+        assert_eq!(to_json(&diag),
+                   "{\"type\":\"diagnostic\",\"file\":\"\",\"line\":null,\"column\":null,\
+                    \"severity\":\"error\",\"code\":\"E0001\",\
+                    \"message\":\"can't open \\\"a\\\\b.asm\\\"\",\"notes\":[],\
+                    \"help\":\"check the path\\n\"}");
+    }
+
+    #[test]
+    fn summary_counts_severities() {
+        let diags = vec![Diagnostic::error(Code::MissingEnd, String::new()),
+                         Diagnostic::warning(Code::InvalidLine, String::new()),
+                         Diagnostic::error(Code::MissingStart, String::new())];
+        assert_eq!(summary(&diags), "{\"type\":\"summary\",\"errors\":2,\"warnings\":1}");
+    }
+}
@@ -0,0 +1,347 @@
+/// Problems found while assembling a program, every stage reports its
+/// errors as diagnostics so they can be filtered, sorted and rendered
+/// the same way regardless of where they came from
+use std::fmt;
+use std::collections::HashMap;
+
+pub mod json;
+pub mod render;
+pub mod reporter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+/// Stable diagnostic codes, the numbers must never be reused for
+/// another kind of problem as tools match on them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    Io,
+    InvalidLine,
+    InvalidLabel,
+    UnknownMnemonic,
+    InvalidOperand,
+    MissingStart,
+    DuplicateStart,
+    MissingProgramName,
+    MissingEnd,
+    LabelRedefinition,
+    CsectRedefinition,
+    DuplicateImport,
+    DuplicateExport,
+    UndefinedSymbol,
+    ImportedSymbolMisuse,
+    InvalidExpression,
+    IllegalRelative,
+    InvalidOperands,
+    InvalidFormat,
+    AddressOutOfRange,
+    ValueOutOfRange,
+    InvalidLiteral,
+    StatementAfterEnd,
+    UndefinedExternal,
+    DuplicateExternal,
+    InvalidRecord,
+    InvalidMacro,
+    InvalidMacroCall,
+    InvalidConditional,
+    UndefinedVariable,
+    InvalidInclude,
+}
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Code::Io => "E0001",
+            Code::InvalidLine => "E0002",
+            Code::InvalidLabel => "E0003",
+            Code::UnknownMnemonic => "E0004",
+            Code::InvalidOperand => "E0005",
+            Code::MissingStart => "E0010",
+            Code::DuplicateStart => "E0011",
+            Code::MissingProgramName => "E0012",
+            Code::MissingEnd => "E0013",
+            Code::LabelRedefinition => "E0020",
+            Code::CsectRedefinition => "E0021",
+            Code::DuplicateImport => "E0022",
+            Code::DuplicateExport => "E0023",
+            Code::UndefinedSymbol => "E0024",
+            Code::ImportedSymbolMisuse => "E0025",
+            Code::InvalidExpression => "E0030",
+            Code::IllegalRelative => "E0031",
+            Code::InvalidOperands => "E0040",
+            Code::InvalidFormat => "E0041",
+            Code::AddressOutOfRange => "E0050",
+            Code::ValueOutOfRange => "E0051",
+            Code::InvalidLiteral => "E0060",
+            Code::UndefinedExternal => "E0070",
+            Code::DuplicateExternal => "E0071",
+            Code::InvalidRecord => "E0080",
+            Code::InvalidMacro => "E0090",
+            Code::InvalidMacroCall => "E0091",
+            Code::InvalidConditional => "E0092",
+            Code::UndefinedVariable => "E0093",
+            Code::InvalidInclude => "E0094",
+            Code::StatementAfterEnd => "W0001",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Byte columns of a piece of a source line, the end is exclusive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start,
+            end,
+        }
+    }
+
+    /// The smallest span covering both spans
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+}
+
+/// Where the parts of a statement were found in its source line
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LineSpans {
+    pub label: Option<Span>,
+    pub mnemonic: Option<Span>,
+    pub operands: Option<Span>,
+}
+
+/// Extra information attached to a diagnostic, it may point at
+/// another place in the source, ex. where a label was first defined
+#[derive(Debug, Clone, PartialEq)]
+pub struct Note {
+    pub message: String,
+    pub line: Option<i32>,
+    pub span: Option<Span>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub file: String,
+    /// 1-based source line, 0 when the problem isn't tied to a line
+    pub line: i32,
+    pub span: Option<Span>,
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub notes: Vec<Note>,
+    /// Boxed to keep diagnostics small, every stage returns them as errors
+    pub help: Option<Box<str>>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, code: Code, message: String) -> Diagnostic {
+        Diagnostic {
+            file: String::new(),
+            line: 0,
+            span: None,
+            severity,
+            code,
+            message,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn error(code: Code, message: String) -> Diagnostic {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    pub fn warning(code: Code, message: String) -> Diagnostic {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    pub fn in_file(mut self, file: &str) -> Diagnostic {
+        self.file = file.to_owned();
+        self
+    }
+
+    pub fn at_line(mut self, line: i32) -> Diagnostic {
+        self.line = line;
+        self
+    }
+
+    pub fn with_span(mut self, span: Option<Span>) -> Diagnostic {
+        self.span = span;
+        self
+    }
+
+    /// Points the diagnostic at the given span unless it already points somewhere
+    pub fn or_span(mut self, span: Option<Span>) -> Diagnostic {
+        if self.span.is_none() {
+            self.span = span;
+        }
+        self
+    }
+
+    pub fn with_note(mut self, message: String) -> Diagnostic {
+        self.notes.push(Note {
+            message,
+            line: None,
+            span: None,
+        });
+        self
+    }
+
+    pub fn with_note_at(mut self, message: String, line: i32, span: Option<Span>) -> Diagnostic {
+        self.notes.push(Note {
+            message,
+            line: Some(line),
+            span,
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: String) -> Diagnostic {
+        self.help = Some(help.into_boxed_str());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// 1-based column of the start of the span, 0 if there's no span
+    pub fn column(&self) -> usize {
+        self.span.map_or(0, |span| span.start + 1)
+    }
+
+    /// file:line:column, omitting the unknown parts
+    pub fn location(&self) -> String {
+        let mut location = self.file.clone();
+        if self.line > 0 {
+            if !location.is_empty() {
+                location.push(':');
+            }
+            location.push_str(&self.line.to_string());
+            if self.span.is_some() {
+                location.push_str(&format!(":{}", self.column()));
+            }
+        }
+        location
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let location = self.location();
+        if !location.is_empty() {
+            write!(f, "{}: ", location)?;
+        }
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)?;
+        for note in &self.notes {
+            match note.line {
+                Some(line) => write!(f, "\n  note: line {}: {}", line, note.message)?,
+                None => write!(f, "\n  note: {}", note.message)?,
+            }
+        }
+        if let Some(ref help) = self.help {
+            write!(f, "\n  help: {}", help)?;
+        }
+        Ok(())
+    }
+}
+
+/// The source lines of the assembled files, used to show the code
+/// a diagnostic points at
+#[derive(Debug, Default)]
+pub struct SourceMap {
+    files: HashMap<String, Vec<String>>,
+}
+
+impl SourceMap {
+    pub fn new() -> SourceMap {
+        SourceMap::default()
+    }
+
+    pub fn add_file(&mut self, file: &str, lines: Vec<String>) {
+        self.files.insert(file.to_owned(), lines);
+    }
+
+    /// The 1-based line of the given file, if it's known
+    pub fn get_line(&self, file: &str, line: i32) -> Option<&str> {
+        if line < 1 {
+            return None;
+        }
+        self.files
+            .get(file)
+            .and_then(|lines| lines.get(line as usize - 1))
+            .map(|line| line.as_str())
+    }
+}
+
+/// Names the file of the diagnostics that were produced without knowing it
+pub fn set_file(diagnostics: &mut [Diagnostic], file: &str) {
+    for diagnostic in diagnostics.iter_mut().filter(|d| d.file.is_empty()) {
+        diagnostic.file = file.to_owned();
+    }
+}
+
+/// Orders diagnostics by their position in the source
+pub fn sort(diagnostics: &mut [Diagnostic]) {
+    diagnostics.sort_by(|a, b| {
+        (&a.file, a.line, a.span, a.severity).cmp(&(&b.file, b.line, b.span, b.severity))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_with_location() {
+        let diag = Diagnostic::error(Code::LabelRedefinition,
+                                     "redefinition of label `LOOP`".to_owned())
+            .in_file("copy.asm")
+            .at_line(12)
+            .with_span(Some(Span::new(0, 4)))
+            .with_note_at("label first defined here".to_owned(), 3, None);
+
+        assert_eq!(diag.to_string(),
+                   "copy.asm:12:1: error[E0020]: redefinition of label `LOOP`\n  note: line 3: \
+                    label first defined here");
+    }
+
+    #[test]
+    fn display_without_location() {
+        let diag = Diagnostic::error(Code::MissingEnd, "missing END directive".to_owned());
+        assert_eq!(diag.to_string(), "error[E0013]: missing END directive");
+    }
+
+    #[test]
+    fn sort_by_position() {
+        let mut diags = vec![Diagnostic::error(Code::InvalidLine, "b".to_owned()).at_line(7),
+                             Diagnostic::error(Code::InvalidLine, "a".to_owned()).at_line(2)];
+        sort(&mut diags);
+        assert_eq!(diags[0].message, "a");
+    }
+}
@@ -0,0 +1,176 @@
+/// Renders diagnostics the way rustc does, with the offending source
+/// line and a caret under the part of the line the diagnostic is about
+///
+/// error[E0020]: redefinition of label `RLOOP`
+///   --> copy.asm:17:1
+///    |
+/// 17 | RLOOP   TD      INPUT
+///    | ^^^^^
+///    |
+/// note: label first defined here
+///   --> copy.asm:9:1
+///    |
+///  9 | RLOOP   TD      INPUT
+///    | -----
+use super::{Diagnostic, SourceMap, Span};
+
+const TAB_WIDTH: usize = 4;
+
+pub fn render(diag: &Diagnostic, sources: &SourceMap) -> String {
+    let gutter = gutter_width(diag);
+    let mut out = format!("{}[{}]: {}\n", diag.severity, diag.code, diag.message);
+
+    render_snippet(&mut out, gutter, &diag.file, diag.line, diag.span, '^', sources);
+
+    for note in &diag.notes {
+        match note.line {
+            Some(line) => {
+                out.push_str(&format!("note: {}\n", note.message));
+                render_snippet(&mut out, gutter, &diag.file, line, note.span, '-', sources);
+            }
+            None => out.push_str(&format!("{} = note: {}\n", pad(gutter), note.message)),
+        }
+    }
+
+    if let Some(ref help) = diag.help {
+        out.push_str(&format!("{} = help: {}\n", pad(gutter), help));
+    }
+
+    out
+}
+
+/// Renders a location arrow followed by the source line and the underline
+fn render_snippet(out: &mut String,
+                  gutter: usize,
+                  file: &str,
+                  line: i32,
+                  span: Option<Span>,
+                  underline: char,
+                  sources: &SourceMap) {
+    if line < 1 {
+        if !file.is_empty() {
+            out.push_str(&format!("{}--> {}\n", pad(gutter), file));
+        }
+        return;
+    }
+
+    let column = span.map_or(String::new(), |span| format!(":{}", span.start + 1));
+    out.push_str(&format!("{}--> {}:{}{}\n", pad(gutter), file, line, column));
+
+    let source = match sources.get_line(file, line) {
+        Some(source) => source,
+        None => return,
+    };
+
+    out.push_str(&format!("{} |\n", pad(gutter)));
+    out.push_str(&format!("{:>width$} | {}\n",
+                          line,
+                          expand_tabs(source),
+                          width = gutter));
+
+    if let Some(span) = span {
+        let start = span.start.min(source.len());
+        let end = span.end.min(source.len()).max(start);
+        let offset = display_width(&source[..start]);
+        let len = display_width(&source[start..end]).max(1);
+        out.push_str(&format!("{} | {}{}\n",
+                              pad(gutter),
+                              pad(offset),
+                              underline.to_string().repeat(len)));
+    }
+}
+
+/// Width of the line numbers column, wide enough for every line shown
+fn gutter_width(diag: &Diagnostic) -> usize {
+    let widest = diag.notes
+        .iter()
+        .filter_map(|note| note.line)
+        .chain(Some(diag.line))
+        .max()
+        .unwrap_or(0);
+    widest.to_string().len()
+}
+
+fn pad(width: usize) -> String {
+    " ".repeat(width)
+}
+
+fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::new();
+    for c in text.chars() {
+        if c == '\t' {
+            expanded.push_str(&pad(TAB_WIDTH));
+        } else {
+            expanded.push(c);
+        }
+    }
+    expanded
+}
+
+fn display_width(text: &str) -> usize {
+    text.chars().map(|c| if c == '\t' { TAB_WIDTH } else { 1 }).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostic::Code;
+
+    fn sources() -> SourceMap {
+        let mut sources = SourceMap::new();
+        sources.add_file("copy.asm",
+                         vec!["COPY START 0".to_owned(),
+                              "LOOP\tLDA ZERO".to_owned(),
+                              "LOOP\tJ LOOP".to_owned()]);
+        sources
+    }
+
+    #[test]
+    fn render_with_caret_and_note() {
+        let diag = Diagnostic::error(Code::LabelRedefinition,
+                                     "redefinition of label `LOOP`".to_owned())
+            .in_file("copy.asm")
+            .at_line(3)
+            .with_span(Some(Span::new(0, 4)))
+            .with_note_at("label first defined here".to_owned(), 2, Some(Span::new(0, 4)));
+
+        assert_eq!(render(&diag, &sources()),
+                   "error[E0020]: redefinition of label `LOOP`
+ --> copy.asm:3:1
+  |
+3 | LOOP    J LOOP
+  | ^^^^
+note: label first defined here
+ --> copy.asm:2:1
+  |
+2 | LOOP    LDA ZERO
+  | ----
+");
+    }
+
+    #[test]
+    fn render_expands_tabs_before_the_caret() {
+        let diag = Diagnostic::error(Code::UndefinedSymbol, "undefined symbol `ZERO`".to_owned())
+            .in_file("copy.asm")
+            .at_line(2)
+            .with_span(Some(Span::new(9, 13)))
+            .with_help("define it".to_owned());
+
+        assert_eq!(render(&diag, &sources()),
+                   "error[E0024]: undefined symbol `ZERO`
+ --> copy.asm:2:10
+  |
+2 | LOOP    LDA ZERO
+  |             ^^^^
+  = help: define it
+");
+    }
+
+    #[test]
+    fn render_without_source() {
+        let diag = Diagnostic::error(Code::MissingEnd, "missing END directive".to_owned())
+            .in_file("copy.asm");
+        assert_eq!(render(&diag, &sources()),
+                   "error[E0013]: missing END directive\n --> copy.asm\n");
+    }
+}
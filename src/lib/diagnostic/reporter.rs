@@ -0,0 +1,172 @@
+/// Prints the diagnostics of every stage in the format picked on the
+/// command line and applies the error policy (-Werror, --max-errors),
+/// shared by the binaries so they report problems the same way
+use std::io::{self, IsTerminal};
+use term::{self, color};
+use super::{Diagnostic, Severity, SourceMap};
+use super::render::render;
+use super::json;
+
+/// The input has errors, no output file was written
+pub const EXIT_ERRORS: i32 = 1;
+/// The command line is invalid
+pub const EXIT_USAGE: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+pub struct Reporter {
+    format: MessageFormat,
+    max_errors: Option<usize>,
+    werror: bool,
+    pub sources: SourceMap,
+    reported: Vec<Diagnostic>,
+    stderr: bool,
+    consequence: Option<&'static str>,
+}
+
+impl Reporter {
+    pub fn new(format: MessageFormat, max_errors: Option<usize>, werror: bool) -> Reporter {
+        Reporter {
+            format,
+            max_errors,
+            werror,
+            sources: SourceMap::new(),
+            reported: Vec::new(),
+            stderr: false,
+            consequence: None,
+        }
+    }
+
+    /// Prints to stderr instead of stdout, when stdout is the output
+    pub fn to_stderr(&mut self) {
+        self.stderr = true;
+    }
+
+    /// What the errors prevented, added to the summary, ex. "no object file written"
+    pub fn on_errors(&mut self, consequence: &'static str) {
+        self.consequence = Some(consequence);
+    }
+
+    pub fn format(&self) -> MessageFormat {
+        self.format
+    }
+
+    /// Prints the diagnostics, true when `--max-errors` was reached and the
+    /// rest were left out, the caller then finishes and stops
+    pub fn report(&mut self, errs: &[Diagnostic]) -> bool {
+        for err in errs {
+            let mut err = err.clone();
+            if self.werror && err.severity == Severity::Warning {
+                err.severity = Severity::Error;
+                err = err.with_note("warnings are treated as errors because of `-Werror`"
+                    .to_owned());
+            }
+
+            match self.format {
+                MessageFormat::Human => self.print_err(&err),
+                MessageFormat::Json => self.print(&format!("{}\n", json::to_json(&err))),
+            }
+            self.reported.push(err);
+
+            if self.max_errors.is_some_and(|max| self.error_count() >= max) {
+                return true;
+            }
+        }
+        false
+    }
+
+    fn error_count(&self) -> usize {
+        self.reported.iter().filter(|err| err.is_error()).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    /// Prints the closing summary
+    pub fn finish(&self) {
+        match self.format {
+            MessageFormat::Json => self.print(&format!("{}\n", json::summary(&self.reported))),
+            MessageFormat::Human if self.has_errors() => {
+                self.print_colored(color::BRIGHT_RED, "error");
+                self.print(&format!(": aborting due to {} error(s)", self.error_count()));
+                if let Some(consequence) = self.consequence {
+                    self.print(&format!(", {}", consequence));
+                }
+                self.print("\n");
+            }
+            MessageFormat::Human => {}
+        }
+    }
+
+    fn print_err(&self, err: &Diagnostic) {
+        // Only the header line gets colored, the snippet is printed as is
+        let rendered = render(err, &self.sources);
+        let (header, snippet) = rendered.split_at(rendered.find('\n').unwrap_or(rendered.len()));
+        let color = match err.severity {
+            Severity::Error => color::BRIGHT_RED,
+            Severity::Warning => color::BRIGHT_YELLOW,
+            Severity::Note => color::BRIGHT_GREEN,
+        };
+        self.print_colored(color, header);
+        self.print(snippet);
+    }
+
+    fn print(&self, text: &str) {
+        if self.stderr {
+            eprint!("{}", text);
+        } else {
+            print!("{}", text);
+        }
+    }
+
+    /// Falls back to plain text without a terminal that has colors, ex. in
+    /// a pipe or with TERM unset or `dumb`
+    fn print_colored(&self, color: color::Color, text: &str) {
+        let painted = if self.stderr {
+            io::stderr().is_terminal() &&
+            term::stderr().is_some_and(|mut t| paint(&mut *t, color, text))
+        } else {
+            io::stdout().is_terminal() &&
+            term::stdout().is_some_and(|mut t| paint(&mut *t, color, text))
+        };
+        if !painted {
+            self.print(text);
+        }
+    }
+}
+
+/// Prints the text in bold color, false when nothing was printed
+pub fn paint<T: term::Terminal + ?Sized>(t: &mut T, color: color::Color, text: &str) -> bool {
+    if t.fg(color).is_err() {
+        return false;
+    }
+    // Bold is a nicety, the text is colored without it
+    let _ = t.attr(term::Attr::Bold);
+    let written = write!(t, "{}", text).is_ok();
+    let _ = t.reset();
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diagnostic::Code;
+
+    #[test]
+    fn max_errors_is_left_to_the_caller() {
+        let errs = vec![Diagnostic::error(Code::InvalidLine, "first".to_owned()),
+                        Diagnostic::error(Code::InvalidLine, "second".to_owned())];
+        let mut reporter = Reporter::new(MessageFormat::Json, Some(1), false);
+        assert!(reporter.report(&errs));
+        assert_eq!(reporter.error_count(), 1);
+
+        let mut reporter = Reporter::new(MessageFormat::Json, None, false);
+        assert!(!reporter.report(&errs));
+        assert_eq!(reporter.error_count(), 2);
+    }
+}
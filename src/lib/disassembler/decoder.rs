@@ -0,0 +1,252 @@
+/// Decodes a single SIC/XE instruction from object code, the format comes
+/// from the instruction set for 1 and 2 byte instructions and from the `e`
+/// bit for format 3/4
+use instruction_set::fetch_by_opcode;
+use formats::Format;
+use operands::OperandType;
+use unit_or_pair::UnitOrPair;
+
+/// Names of the register numbers of format 2 instructions, 7 isn't used
+const REGISTERS: [&str; 10] = ["A", "X", "L", "B", "S", "T", "F", "", "PC", "SW"];
+
+/// How the operand of a format 3/4 instruction is addressed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    /// n=0, i=0, the 15 bit address of the SIC machine
+    Sic,
+    Simple,
+    Immediate,
+    Indirect,
+}
+
+/// Where the address field of a format 3/4 instruction points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Target {
+    /// The field is the address (or the value of an immediate)
+    Absolute(u32),
+    /// PC relative, already added to the address of the next instruction
+    Relative(u32),
+    /// Displacement from the base register
+    Base(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operands {
+    None,
+    /// Format 2 operands as written in the source, ex. `A,S` or `T`
+    Registers(String),
+    Memory {
+        mode: Mode,
+        target: Target,
+        indexed: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decoded {
+    pub address: u32,
+    pub mnemonic: String,
+    pub format: Format,
+    pub operands: Operands,
+}
+
+impl Decoded {
+    pub fn length(&self) -> u32 {
+        self.format as u32
+    }
+
+    /// The address the operand refers to, base relative operands need the
+    /// value of the base register
+    pub fn target(&self, base: Option<u32>) -> Option<u32> {
+        match self.operands {
+            Operands::Memory { target: Target::Absolute(address), .. } |
+            Operands::Memory { target: Target::Relative(address), .. } => Some(address),
+            Operands::Memory { target: Target::Base(disp), .. } => {
+                base.map(|base| (base + disp) & 0xFFFFF)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn mode(&self) -> Option<Mode> {
+        match self.operands {
+            Operands::Memory { mode, .. } => Some(mode),
+            _ => None,
+        }
+    }
+
+    /// Jumps and subroutine calls, their target is code
+    pub fn is_jump(&self) -> bool {
+        matches!(self.mnemonic.as_str(), "J" | "JEQ" | "JGT" | "JLT" | "JSUB")
+    }
+
+    /// The next instruction isn't reached from this one
+    pub fn ends_flow(&self) -> bool {
+        self.mnemonic == "J" || self.mnemonic == "RSUB"
+    }
+}
+
+/// Decodes the instruction at the start of `bytes`, `address` is where the
+/// instruction is in memory. Returns None when the bytes aren't a valid
+/// instruction or the instruction doesn't fit in them.
+pub fn decode(bytes: &[u8], address: u32) -> Option<Decoded> {
+    let opcode = *bytes.first()?;
+    let def = fetch_by_opcode(opcode)?;
+
+    let (format, operands) = match def.format {
+        UnitOrPair::Unit(Format::One) => (Format::One, Operands::None),
+        UnitOrPair::Unit(Format::Two) => {
+            let registers = *bytes.get(1)?;
+            let operands = format_two(&def.operands, registers >> 4, registers & 0xF)?;
+            (Format::Two, Operands::Registers(operands))
+        }
+        _ => format_three(bytes, address)?,
+    };
+
+    // Operand-less instructions ignore their address field
+    let operands = match def.operands {
+        UnitOrPair::None => Operands::None,
+        _ => operands,
+    };
+
+    Some(Decoded {
+        address,
+        mnemonic: def.mnemonic,
+        format,
+        operands,
+    })
+}
+
+fn format_two(operands: &UnitOrPair<OperandType>, r1: u8, r2: u8) -> Option<String> {
+    let register = |number: u8| match REGISTERS.get(number as usize) {
+        Some(name) if !name.is_empty() => Some(*name),
+        _ => None,
+    };
+    match *operands {
+        UnitOrPair::Unit(OperandType::Register) if r2 == 0 => Some(register(r1)?.to_owned()),
+        // SVC n
+        UnitOrPair::Unit(_) if r2 == 0 => Some(r1.to_string()),
+        UnitOrPair::Pair(OperandType::Register, OperandType::Register) => {
+            Some(format!("{},{}", register(r1)?, register(r2)?))
+        }
+        // SHIFTL r1,n keeps n-1 in r2
+        UnitOrPair::Pair(OperandType::Register, _) => {
+            Some(format!("{},{}", register(r1)?, r2 + 1))
+        }
+        _ => None,
+    }
+}
+
+fn format_three(bytes: &[u8], address: u32) -> Option<(Format, Operands)> {
+    let (first, second, third) = (bytes[0], *bytes.get(1)?, *bytes.get(2)?);
+    let indexed = second & 0x80 != 0;
+    let mode = match first & 0x03 {
+        0 => Mode::Sic,
+        1 => Mode::Immediate,
+        2 => Mode::Indirect,
+        _ => Mode::Simple,
+    };
+
+    if mode == Mode::Sic {
+        let field = ((second as u32 & 0x7F) << 8) | third as u32;
+        let operands = Operands::Memory {
+            mode,
+            target: Target::Absolute(field),
+            indexed,
+        };
+        return Some((Format::Three, operands));
+    }
+
+    let (base, relative, extended) = (second & 0x40 != 0, second & 0x20 != 0, second & 0x10 != 0);
+    if extended {
+        if base || relative {
+            return None;
+        }
+        let field = ((second as u32 & 0x0F) << 16) | ((third as u32) << 8) |
+                    *bytes.get(3)? as u32;
+        let operands = Operands::Memory {
+            mode,
+            target: Target::Absolute(field),
+            indexed,
+        };
+        return Some((Format::Four, operands));
+    }
+
+    let disp = ((second as u32 & 0x0F) << 8) | third as u32;
+    let target = match (base, relative) {
+        (false, false) => Target::Absolute(disp),
+        (true, false) => Target::Base(disp),
+        (false, true) => {
+            // The displacement is a 12 bit two's complement number
+            let disp = ((disp << 20) as i32 >> 20) as u32;
+            Target::Relative(address.wrapping_add(3).wrapping_add(disp) & 0xFFFFF)
+        }
+        (true, true) => return None,
+    };
+    let operands = Operands::Memory {
+        mode,
+        target,
+        indexed,
+    };
+    Some((Format::Three, operands))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_formats() {
+        // STL RETADR, pc relative from 0
+        let stl = decode(&[0x17, 0x20, 0x2D], 0).unwrap();
+        assert_eq!(stl.mnemonic, "STL");
+        assert_eq!(stl.target(None), Some(0x30));
+
+        // +JSUB 1036
+        let jsub = decode(&[0x4B, 0x10, 0x10, 0x36], 6).unwrap();
+        assert_eq!(jsub.format, Format::Four);
+        assert_eq!(jsub.target(None), Some(0x1036));
+        assert!(jsub.is_jump());
+
+        // J CLOOP, backwards
+        let jump = decode(&[0x3F, 0x2F, 0xEC], 0x17).unwrap();
+        assert_eq!(jump.target(None), Some(0x06));
+        assert!(jump.ends_flow());
+
+        // STCH BUFFER,X with base 0x33
+        let stch = decode(&[0x57, 0xC0, 0x03], 0x104E).unwrap();
+        assert_eq!(stch.target(None), None);
+        assert_eq!(stch.target(Some(0x33)), Some(0x36));
+        assert_eq!(stch.operands,
+                   Operands::Memory {
+                       mode: Mode::Simple,
+                       target: Target::Base(3),
+                       indexed: true,
+                   });
+
+        // LDA #3, @RETADR
+        assert_eq!(decode(&[0x01, 0x00, 0x03], 0).unwrap().mode(), Some(Mode::Immediate));
+        assert_eq!(decode(&[0x3E, 0x20, 0x03], 0).unwrap().mode(), Some(Mode::Indirect));
+
+        assert_eq!(decode(&[0xA0, 0x04], 0).unwrap().operands,
+                   Operands::Registers("A,S".to_owned()));
+        assert_eq!(decode(&[0xB8, 0x50], 0).unwrap().operands,
+                   Operands::Registers("T".to_owned()));
+        assert_eq!(decode(&[0xA4, 0x03], 0).unwrap().operands,
+                   Operands::Registers("A,4".to_owned()));
+        assert_eq!(decode(&[0x4F, 0x00, 0x00], 0).unwrap().operands, Operands::None);
+        assert_eq!(decode(&[0xC4], 0).unwrap().format, Format::One);
+    }
+
+    #[test]
+    fn invalid_code() {
+        assert!(decode(&[], 0).is_none());
+        assert!(decode(&[0xFF, 0x00, 0x00], 0).is_none());
+        // Cut short
+        assert!(decode(&[0x4B, 0x10, 0x10], 0).is_none());
+        assert!(decode(&[0x90], 0).is_none());
+        // b and p together, register 7
+        assert!(decode(&[0x03, 0x60, 0x00], 0).is_none());
+        assert!(decode(&[0x90, 0x70], 0).is_none());
+    }
+}
@@ -0,0 +1,476 @@
+/// Rebuilds the source of an object program, the code is found by following
+/// the jumps from the entry point and whatever isn't reached is kept as
+/// BYTE data, the gaps between text records become RESB
+///
+/// Jump and operand targets get synthesized labels (`L0033`) unless an
+/// EXTDEF names them, the BASE directive follows every `LDB #...` so the
+/// output assembles back to the same object code for the usual programs.
+use std::collections::{BTreeMap, HashMap};
+use diagnostic::Diagnostic;
+use formats::Format;
+use htme::modification::Modification;
+use htme::reader::{read_sections, ObjectSection, Text};
+use disassembler::decoder::{decode, Decoded, Mode, Operands, Target};
+
+/// Most bytes in a single BYTE directive
+const DATA_BYTES: usize = 16;
+
+/// Disassembles every control section of an object program
+pub fn disassemble(text: &str) -> Result<String, Vec<Diagnostic>> {
+    let sections = read_sections(text)?;
+    let mut source = String::new();
+    let mut entry = String::new();
+    for (i, section) in sections.iter().enumerate() {
+        let disassembly = Disassembly::new(section);
+        disassembly.write(&mut source, i == 0);
+        if let Some(label) = section.entry.and_then(|address| disassembly.labels.get(&address)) {
+            entry = label.clone();
+        }
+    }
+    source.push_str(&line("", "END", &entry, None));
+    Ok(source)
+}
+
+/// Disassembles the bytes of a text record (or a memory dump) loaded at
+/// `start`, the code starts at the first byte
+pub fn disassemble_bytes(name: &str, start: u32, bytes: &[u8]) -> String {
+    let section = ObjectSection {
+        name: name.to_owned(),
+        start,
+        length: bytes.len() as u32,
+        defines: Vec::new(),
+        refers: Vec::new(),
+        texts: vec![Text {
+                        start,
+                        bytes: bytes.to_vec(),
+                    }],
+        modifications: Vec::new(),
+        entry: Some(start),
+        line: 0,
+    };
+    let disassembly = Disassembly::new(&section);
+    let mut source = String::new();
+    disassembly.write(&mut source, true);
+    let entry = disassembly.labels.get(&start).cloned().unwrap_or_default();
+    source.push_str(&line("", "END", &entry, None));
+    source
+}
+
+fn line(label: &str, operation: &str, operand: &str, comment: Option<String>) -> String {
+    let mut line = format!("{:<8} {:<7} {}", label, operation, operand);
+    if let Some(comment) = comment {
+        line = format!("{:<28} . {}", line, comment);
+    }
+    format!("{}\n", line.trim_end())
+}
+
+/// A relocated or external WORD, ex. `BUFEND-BUFFER`
+struct Word {
+    /// Address the word holds when it's relocated by the section
+    local: Option<u32>,
+    /// The external symbols added to it, with their signs
+    externals: String,
+    offset: i32,
+}
+
+struct Disassembly<'a> {
+    section: &'a ObjectSection,
+    memory: BTreeMap<u32, u8>,
+    code: BTreeMap<u32, Decoded>,
+    /// Addresses the operands refer to, by the address of the instruction
+    targets: HashMap<u32, u32>,
+    /// Value of the base register after an `LDB #...`
+    bases: HashMap<u32, u32>,
+    words: BTreeMap<u32, Word>,
+    labels: BTreeMap<u32, String>,
+}
+
+impl<'a> Disassembly<'a> {
+    fn new(section: &'a ObjectSection) -> Disassembly<'a> {
+        let mut memory = BTreeMap::new();
+        for text in &section.texts {
+            for (i, byte) in text.bytes.iter().enumerate() {
+                memory.insert(text.start + i as u32, *byte);
+            }
+        }
+        let mut disassembly = Disassembly {
+            section,
+            memory,
+            code: BTreeMap::new(),
+            targets: HashMap::new(),
+            bases: HashMap::new(),
+            words: BTreeMap::new(),
+            labels: BTreeMap::new(),
+        };
+
+        // Base relative jumps are only known once the LDBs before them are
+        let mut roots = vec![section.entry.unwrap_or(section.start)];
+        while !roots.is_empty() {
+            disassembly.trace(roots);
+            disassembly.resolve();
+            roots = disassembly.code
+                .values()
+                .filter(|decoded| follows(decoded))
+                .filter_map(|decoded| disassembly.targets.get(&decoded.address))
+                .filter(|target| !disassembly.code.contains_key(target))
+                .filter(|&&target| disassembly.decodable(target))
+                .cloned()
+                .collect();
+        }
+
+        disassembly.find_words();
+        disassembly.name_labels();
+        disassembly
+    }
+
+    /// Data words with modification records, they're written as WORD so
+    /// the records come back when the source is assembled
+    fn find_words(&mut self) {
+        let section = self.section;
+        for modification in &section.modifications {
+            let address = modification.address;
+            let bytes = (address..address + 3)
+                .map(|at| self.memory.get(&at).cloned())
+                .collect::<Option<Vec<u8>>>();
+            let value = match bytes {
+                Some(ref bytes) if modification.half_bytes == 6 &&
+                                   !self.words.contains_key(&address) &&
+                                   (address..address + 3).all(|at| !self.covered(at)) => {
+                    bytes.iter().fold(0, |word, &byte| word << 8 | byte as u32)
+                }
+                _ => continue,
+            };
+
+            let mut externals = String::new();
+            let mut locals = 0;
+            let terms = section.modifications
+                .iter()
+                .filter(|term| term.address == address && term.half_bytes == 6);
+            for term in terms {
+                if term.symbol == section.name {
+                    locals += if term.negative { -1 } else { 1 };
+                } else {
+                    externals.push(if term.negative { '-' } else { '+' });
+                    externals.push_str(&term.symbol);
+                }
+            }
+            let word = match locals {
+                // The value is the address of a local symbol
+                1 => Word { local: Some(value), externals, offset: 0 },
+                0 => Word { local: None, externals, offset: ((value << 8) as i32) >> 8 },
+                _ => continue,
+            };
+            self.words.insert(address, word);
+        }
+    }
+
+    /// Decodes the instructions reached from the roots
+    fn trace(&mut self, mut work: Vec<u32>) {
+        while let Some(address) = work.pop() {
+            let decoded = match self.decode_at(address) {
+                Some(decoded) => decoded,
+                None => continue,
+            };
+            let next = address + decoded.length();
+            if !decoded.ends_flow() {
+                work.push(next);
+            }
+            if follows(&decoded) && self.external(&decoded).is_none() {
+                if let Some(target) = decoded.target(None) {
+                    work.push(target);
+                }
+            }
+            self.code.insert(address, decoded);
+        }
+    }
+
+    fn decodable(&self, address: u32) -> bool {
+        self.decode_at(address).is_some()
+    }
+
+    /// Decodes an instruction that doesn't overlap the ones already found
+    fn decode_at(&self, address: u32) -> Option<Decoded> {
+        if self.covered(address) {
+            return None;
+        }
+        let bytes = (address..address + 4)
+            .map_while(|address| self.memory.get(&address).cloned())
+            .collect::<Vec<u8>>();
+        let decoded = decode(&bytes, address)?;
+        match self.code.range(address..address + decoded.length()).next() {
+            Some(_) => None,
+            None => Some(decoded),
+        }
+    }
+
+    /// The address is part of a decoded instruction
+    fn covered(&self, address: u32) -> bool {
+        self.code
+            .range(..=address)
+            .next_back()
+            .is_some_and(|(start, decoded)| address < start + decoded.length())
+    }
+
+    /// Finds the operand targets, following the base register through the code
+    fn resolve(&mut self) {
+        let mut base: Option<u32> = None;
+        self.targets.clear();
+        self.bases.clear();
+        for decoded in self.code.values() {
+            let target = if self.external(decoded).is_some() || self.is_constant(decoded) {
+                None
+            } else {
+                decoded.target(base)
+            };
+            if let Some(target) = target {
+                self.targets.insert(decoded.address, target);
+            }
+
+            if decoded.mnemonic == "LDB" {
+                base = match decoded.mode() {
+                    Some(Mode::Immediate) => target,
+                    _ => None,
+                };
+                if let Some(base) = base {
+                    self.bases.insert(decoded.address, base);
+                }
+            }
+        }
+    }
+
+    /// The external symbol a format 4 address field is modified with
+    fn external(&self, decoded: &Decoded) -> Option<&'a Modification> {
+        self.modification(decoded).filter(|modification| modification.symbol != self.section.name)
+    }
+
+    fn modification(&self, decoded: &Decoded) -> Option<&'a Modification> {
+        if decoded.format != Format::Four {
+            return None;
+        }
+        self.section
+            .modifications
+            .iter()
+            .find(|modification| {
+                modification.address == decoded.address + 1 && modification.half_bytes == 5
+            })
+    }
+
+    /// Immediate operands are numbers unless they're relative or relocated
+    fn is_constant(&self, decoded: &Decoded) -> bool {
+        match decoded.operands {
+            Operands::Memory { mode: Mode::Immediate, target: Target::Absolute(_), .. } => {
+                self.modification(decoded).is_none()
+            }
+            _ => false,
+        }
+    }
+
+    fn name_labels(&mut self) {
+        for &(ref name, address) in &self.section.defines {
+            self.labels.insert(address, name.clone());
+        }
+        let mut addresses = self.targets.values().cloned().collect::<Vec<u32>>();
+        addresses.extend(self.section.entry);
+        addresses.extend(self.words.values().filter_map(|word| word.local));
+        for address in addresses {
+            self.labels.entry(address).or_insert_with(|| format!("L{:04X}", address));
+        }
+    }
+
+    fn write(&self, source: &mut String, first: bool) {
+        let section = self.section;
+        if first {
+            source.push_str(&line(&section.name, "START", &format!("{:X}", section.start), None));
+        } else {
+            source.push_str(&line(&section.name, "CSECT", "", None));
+        }
+        if !section.defines.is_empty() {
+            let names = section.defines.iter().map(|define| define.0.clone()).collect::<Vec<_>>();
+            source.push_str(&line("", "EXTDEF", &names.join(","), None));
+        }
+        if !section.refers.is_empty() {
+            source.push_str(&line("", "EXTREF", &section.refers.join(","), None));
+        }
+
+        let end = section.start + section.length;
+        let mut placed: Vec<u32> = Vec::new();
+        let mut address = section.start;
+        while address < end {
+            let label = match self.labels.get(&address) {
+                Some(label) => {
+                    placed.push(address);
+                    label.as_str()
+                }
+                None => "",
+            };
+            let comment = Some(format!("{:05X}", address));
+
+            if let Some(decoded) = self.code.get(&address) {
+                source.push_str(&self.instruction(label, decoded));
+                if let Some(base) = self.bases.get(&address) {
+                    source.push_str(&line("", "BASE", &self.labels[base], None));
+                }
+                address += decoded.length();
+                continue;
+            }
+            if let Some(word) = self.words.get(&address) {
+                source.push_str(&line(label, "WORD", &self.word_operand(word), comment));
+                address += 3;
+                continue;
+            }
+
+            // Data and gaps stop at the next label or word so it can be placed
+            let known = self.memory.contains_key(&address);
+            let run = (address..end)
+                .enumerate()
+                .take_while(|&(i, at)| {
+                    i == 0 ||
+                    !(self.labels.contains_key(&at) || self.code.contains_key(&at) ||
+                      self.words.contains_key(&at) || self.memory.contains_key(&at) != known)
+                })
+                .count() as u32;
+            if known {
+                let run = run.min(DATA_BYTES as u32);
+                let bytes = (address..address + run).map(|at| self.memory[&at]).collect::<Vec<_>>();
+                source.push_str(&line(label, "BYTE", &format!("X'{}'", hex(&bytes)), comment));
+                address += run;
+            } else {
+                source.push_str(&line(label, "RESB", &run.to_string(), comment));
+                address += run;
+            }
+        }
+
+        for (address, label) in &self.labels {
+            if placed.contains(address) {
+                continue;
+            }
+            if *address == end {
+                source.push_str(&line(label, "EQU", "*", None));
+            } else {
+                // Outside of the section or inside an instruction
+                source.push_str(&line(label, "EQU", &format!("X'{:X}'", address), None));
+            }
+        }
+    }
+
+    fn word_operand(&self, word: &Word) -> String {
+        let mut operand = match word.local {
+            Some(address) => self.labels[&address].clone(),
+            None => String::new(),
+        };
+        operand.push_str(&word.externals);
+        if word.offset != 0 || operand.is_empty() {
+            operand.push_str(&format!("{:+}", word.offset));
+        }
+        operand.trim_start_matches('+').to_owned()
+    }
+
+    fn instruction(&self, label: &str, decoded: &Decoded) -> String {
+        let address = decoded.address;
+        let bytes = (address..address + decoded.length())
+            .map(|at| self.memory[&at])
+            .collect::<Vec<u8>>();
+        let mut comment = format!("{:05X} {}", address, hex(&bytes));
+        let mnemonic = match decoded.format {
+            Format::Four => format!("+{}", decoded.mnemonic),
+            _ => decoded.mnemonic.clone(),
+        };
+
+        let operand = match decoded.operands {
+            Operands::None => String::new(),
+            Operands::Registers(ref registers) => registers.clone(),
+            Operands::Memory { mode, target, indexed } => {
+                let prefix = match mode {
+                    Mode::Immediate => "#",
+                    Mode::Indirect => "@",
+                    _ => "",
+                };
+                let field = if let Some(external) = self.external(decoded) {
+                    external.symbol.clone()
+                } else if let Some(target) = self.targets.get(&address) {
+                    self.labels[target].clone()
+                } else {
+                    match target {
+                        Target::Absolute(value) | Target::Relative(value) => value.to_string(),
+                        Target::Base(disp) => {
+                            comment.push_str(" base unknown");
+                            disp.to_string()
+                        }
+                    }
+                };
+                format!("{}{}{}", prefix, field, if indexed { ",X" } else { "" })
+            }
+        };
+        line(label, &mnemonic, &operand, Some(comment))
+    }
+}
+
+/// Jumps followed to find more code, indirect jumps go through data
+fn follows(decoded: &Decoded) -> bool {
+    decoded.is_jump() && matches!(decoded.mode(), Some(Mode::Simple) | Some(Mode::Sic))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02X}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::object_program_of;
+    use std::env;
+    use std::fs;
+
+    /// Disassembles the object code of a source file and assembles it again
+    fn round_trip(path: &str, name: &str) -> (String, String) {
+        let records = object_program_of(path);
+        let source = disassemble(&records).unwrap();
+        let path = env::temp_dir().join(format!("{}-{}.asm", name, ::std::process::id()));
+        fs::write(&path, &source).unwrap();
+        let again = object_program_of(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        (records, again)
+    }
+
+    #[test]
+    fn round_trip_copy() {
+        let (records, again) = round_trip("src/tests/test2.asm", "copy");
+        assert_eq!(records, again);
+    }
+
+    #[test]
+    fn round_trip_control_sections() {
+        let (records, again) = round_trip("src/tests/csect.asm", "csect");
+        assert_eq!(records, again);
+    }
+
+    #[test]
+    fn external_references() {
+        let source = disassemble(&object_program_of("src/tests/csect.asm")).unwrap();
+        let lines = source.lines().map(|line| line.trim_end()).collect::<Vec<&str>>();
+        assert!(lines.contains(&"         EXTREF  RDREC,WRREC"));
+        assert!(lines.contains(&"RDREC    CSECT"));
+        assert!(lines.contains(&"L0003    +JSUB   RDREC       . 00003 4B100000"));
+        assert!(lines.contains(&"         +STCH   BUFFER,X    . 00017 57900000"));
+        assert!(lines.contains(&"L0028    WORD    BUFEND-BUFFER . 00028"));
+        assert!(lines.contains(&"BUFEND   EQU     *"));
+    }
+
+    #[test]
+    fn text_bytes() {
+        // LDB #L000C, BASE, LDA L000C (base relative), J L0003, then data
+        let source = disassemble_bytes("RAW",
+                                       0x100,
+                                       &[0x69, 0x20, 0x09, 0x03, 0x40, 0x00, 0x3F, 0x2F, 0xFA,
+                                         0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x2A]);
+        assert_eq!(source,
+                   "RAW      START   100\n\
+                    L0100    LDB     #L010C      . 00100 692009\n\
+                    \x20        BASE    L010C\n\
+                    L0103    LDA     L010C       . 00103 034000\n\
+                    \x20        J       L0103       . 00106 3F2FFA\n\
+                    \x20        BYTE    X'FFFFFF'   . 00109\n\
+                    L010C    BYTE    X'00002A'   . 0010C\n\
+                    \x20        END     L0100\n");
+    }
+}
@@ -0,0 +1,448 @@
+/// Operand expressions, ex. `BUFEND-BUFFER`, `(MAXLEN+2)/3`, `C'EOF'` or `*-1`
+///
+/// The result is absolute or relative by the SIC/XE rules: the relative
+/// terms have to pair up, `BUFEND-BUFFER` is absolute, a single positive
+/// relative term left over makes the result relative and relative terms
+/// can't be multiplied or divided. EXTREF symbols aren't known until the
+/// program is loaded, they're kept apart for the modification records.
+use diagnostic::{Diagnostic, Code};
+use session::Session;
+use symbol::SymbolType;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(i32),
+    Symbol(String),
+    /// `*`, the location counter of the statement
+    Locctr,
+    Negate(Box<Expr>),
+    Binary(Operator, Box<Expr>, Box<Expr>),
+}
+
+/// What a symbol of an expression stands for
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Term {
+    Absolute(i32),
+    Relative(i32),
+    External,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluated {
+    /// The value without the external symbols
+    pub value: i32,
+    /// The value is an address in the control section
+    pub relative: bool,
+    /// EXTREF symbols, each with whether it's subtracted
+    pub externals: Vec<(bool, String)>,
+}
+
+/// A value during the evaluation, `relative` counts the relative terms
+/// that didn't cancel out
+struct Partial {
+    value: i32,
+    relative: i32,
+    externals: Vec<(bool, String)>,
+}
+
+impl Partial {
+    fn absolute(value: i32) -> Partial {
+        Partial {
+            value,
+            relative: 0,
+            externals: Vec::new(),
+        }
+    }
+
+    fn is_absolute(&self) -> bool {
+        self.relative == 0 && self.externals.is_empty()
+    }
+
+    fn negate(mut self) -> Result<Partial, Diagnostic> {
+        self.value = self.value.checked_neg().ok_or_else(overflow)?;
+        self.relative = -self.relative;
+        for external in &mut self.externals {
+            external.0 = !external.0;
+        }
+        Ok(self)
+    }
+}
+
+/// The value of an expression doesn't fit in 32 bits
+fn overflow() -> Diagnostic {
+    Diagnostic::error(Code::ValueOutOfRange,
+                      "the expression overflows, its value doesn't fit in 32 bits".to_owned())
+}
+
+impl Expr {
+    /// Evaluates the expression, `lookup` tells what the symbols are
+    pub fn evaluate<F>(&self, locctr: i32, lookup: &F) -> Result<Evaluated, Diagnostic>
+        where F: Fn(&str) -> Result<Term, Diagnostic>
+    {
+        let result = self.partial(locctr, lookup)?;
+        match result.relative {
+            0 | 1 => {
+                Ok(Evaluated {
+                    value: result.value,
+                    relative: result.relative == 1,
+                    externals: result.externals,
+                })
+            }
+            n if n > 1 => {
+                Err(Diagnostic::error(Code::IllegalRelative,
+                                      "relative terms are added together".to_owned())
+                    .with_help("a relative term has to be paired with a subtracted one, ex. \
+                                `BUFEND-BUFFER`"
+                        .to_owned()))
+            }
+            _ => {
+                Err(Diagnostic::error(Code::IllegalRelative,
+                                      "a relative term is subtracted from an absolute value"
+                                          .to_owned())
+                    .with_help("a relative term has to be paired with a subtracted one, ex. \
+                                `BUFEND-BUFFER`"
+                        .to_owned()))
+            }
+        }
+    }
+
+    /// The symbols the expression refers to, `*` isn't one of them
+    pub fn symbols(&self) -> Vec<&str> {
+        match *self {
+            Expr::Number(_) | Expr::Locctr => Vec::new(),
+            Expr::Symbol(ref name) => vec![name.as_str()],
+            Expr::Negate(ref expr) => expr.symbols(),
+            Expr::Binary(_, ref left, ref right) => {
+                let mut symbols = left.symbols();
+                symbols.extend(right.symbols());
+                symbols
+            }
+        }
+    }
+
+    pub fn uses_locctr(&self) -> bool {
+        match *self {
+            Expr::Locctr => true,
+            Expr::Number(_) | Expr::Symbol(_) => false,
+            Expr::Negate(ref expr) => expr.uses_locctr(),
+            Expr::Binary(_, ref left, ref right) => left.uses_locctr() || right.uses_locctr(),
+        }
+    }
+
+    fn partial<F>(&self, locctr: i32, lookup: &F) -> Result<Partial, Diagnostic>
+        where F: Fn(&str) -> Result<Term, Diagnostic>
+    {
+        match *self {
+            Expr::Number(value) => Ok(Partial::absolute(value)),
+            Expr::Locctr => {
+                Ok(Partial {
+                    value: locctr,
+                    relative: 1,
+                    externals: Vec::new(),
+                })
+            }
+            Expr::Symbol(ref name) => {
+                let partial = match lookup(name)? {
+                    Term::Absolute(value) => Partial::absolute(value),
+                    Term::Relative(value) => {
+                        Partial {
+                            value,
+                            relative: 1,
+                            externals: Vec::new(),
+                        }
+                    }
+                    Term::External => {
+                        Partial {
+                            value: 0,
+                            relative: 0,
+                            externals: vec![(false, name.clone())],
+                        }
+                    }
+                };
+                Ok(partial)
+            }
+            Expr::Negate(ref expr) => expr.partial(locctr, lookup)?.negate(),
+            Expr::Binary(operator, ref left, ref right) => {
+                let left = left.partial(locctr, lookup)?;
+                let right = right.partial(locctr, lookup)?;
+                binary(operator, left, right)
+            }
+        }
+    }
+}
+
+fn binary(operator: Operator, mut left: Partial, right: Partial) -> Result<Partial, Diagnostic> {
+    let right = match operator {
+        Operator::Sub => right.negate()?,
+        _ => right,
+    };
+    match operator {
+        Operator::Add | Operator::Sub => {
+            left.value = left.value.checked_add(right.value).ok_or_else(overflow)?;
+            left.relative += right.relative;
+            left.externals.extend(right.externals);
+            Ok(left)
+        }
+        Operator::Mul | Operator::Div if !left.is_absolute() || !right.is_absolute() => {
+            Err(Diagnostic::error(Code::IllegalRelative,
+                                  "relative and external terms can't be multiplied or divided"
+                                      .to_owned()))
+        }
+        Operator::Mul => {
+            left.value.checked_mul(right.value).map(Partial::absolute).ok_or_else(overflow)
+        }
+        Operator::Div if right.value == 0 => {
+            Err(Diagnostic::error(Code::InvalidExpression, "division by zero".to_owned()))
+        }
+        Operator::Div => {
+            left.value.checked_div(right.value).map(Partial::absolute).ok_or_else(overflow)
+        }
+    }
+}
+
+/// Evaluates an expression with the symbols of a control section
+pub fn evaluate(session: &Session,
+                expr: &Expr,
+                csect: &str,
+                locctr: i32)
+                -> Result<Evaluated, Diagnostic> {
+    expr.evaluate(locctr, &|name: &str| {
+        let sym = session.symbols.get_symbol(name, csect)?;
+        Ok(if sym.symbol_type == SymbolType::Imported {
+            Term::External
+        } else if sym.symbol.is_relative() {
+            Term::Relative(sym.get_address())
+        } else {
+            Term::Absolute(sym.get_address())
+        })
+    })
+}
+
+/// Parses an expression, the error tells what was expected
+pub fn parse(text: &str) -> Result<Expr, String> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        at: 0,
+    };
+    let expr = parser.expression()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(c) => Err(format!("unexpected `{}` in `{}`", c, text)),
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        self.at += 1;
+        c
+    }
+
+    /// term (('+' | '-') term)*
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        loop {
+            let operator = match self.peek() {
+                Some('+') => Operator::Add,
+                Some('-') => Operator::Sub,
+                _ => return Ok(expr),
+            };
+            self.at += 1;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.term()?));
+        }
+    }
+
+    /// factor (('*' | '/') factor)*
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        loop {
+            let operator = match self.peek() {
+                Some('*') => Operator::Mul,
+                Some('/') => Operator::Div,
+                _ => return Ok(expr),
+            };
+            self.at += 1;
+            expr = Expr::Binary(operator, Box::new(expr), Box::new(self.factor()?));
+        }
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some('-') => Ok(Expr::Negate(Box::new(self.factor()?))),
+            Some('+') => self.factor(),
+            Some('*') => Ok(Expr::Locctr),
+            Some('(') => {
+                let expr = self.expression()?;
+                match self.next() {
+                    Some(')') => Ok(expr),
+                    _ => Err("missing `)`".to_owned()),
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let digits = self.take_while(c, |c| c.is_ascii_digit());
+                digits.parse::<i32>()
+                    .map(Expr::Number)
+                    .map_err(|_| format!("`{}` doesn't fit in a word", digits))
+            }
+            Some(c) if "CcXx".contains(c) && self.peek() == Some('\'') => {
+                self.at += 1;
+                self.constant(c.to_ascii_uppercase())
+            }
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+                Ok(Expr::Symbol(self.take_while(c, |c| c.is_ascii_alphanumeric() || c == '_')))
+            }
+            Some(c) => Err(format!("expected a term, found `{}`", c)),
+            None => Err("expected a term after the operator".to_owned()),
+        }
+    }
+
+    fn take_while<F: Fn(char) -> bool>(&mut self, first: char, accept: F) -> String {
+        let mut text = first.to_string();
+        while let Some(c) = self.peek().filter(|&c| accept(c)) {
+            text.push(c);
+            self.at += 1;
+        }
+        text
+    }
+
+    /// The value of C'..' or X'..', at most a word
+    fn constant(&mut self, kind: char) -> Result<Expr, String> {
+        let mut text = String::new();
+        loop {
+            match self.next() {
+                Some('\'') => break,
+                Some(c) => text.push(c),
+                None => return Err(format!("missing the closing `'` of {}'{}", kind, text)),
+            }
+        }
+        let value = if kind == 'C' {
+            if text.is_empty() || text.len() > 3 || !text.is_ascii() {
+                return Err(format!("C'{}' has to be 1 to 3 characters", text));
+            }
+            text.bytes().fold(0, |value, byte| value << 8 | byte as i32)
+        } else {
+            if text.is_empty() || text.len() > 6 {
+                return Err(format!("X'{}' has to be 1 to 6 hex digits", text));
+            }
+            i32::from_str_radix(&text, 16).map_err(|_| format!("X'{}' isn't hexadecimal", text))?
+        };
+        Ok(Expr::Number(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(name: &str) -> Result<Term, Diagnostic> {
+        match name {
+            "BUFFER" => Ok(Term::Relative(0x33)),
+            "BUFEND" => Ok(Term::Relative(0x1033)),
+            "MAXLEN" => Ok(Term::Absolute(4096)),
+            "RDREC" => Ok(Term::External),
+            _ => {
+                Err(Diagnostic::error(Code::UndefinedSymbol,
+                                      format!("undefined symbol `{}`", name)))
+            }
+        }
+    }
+
+    fn eval(text: &str) -> Result<Evaluated, Diagnostic> {
+        parse(text).unwrap().evaluate(0x100, &lookup)
+    }
+
+    fn value(text: &str) -> i32 {
+        eval(text).unwrap().value
+    }
+
+    #[test]
+    fn precedence_and_parentheses() {
+        assert_eq!(value("1+2*3"), 7);
+        assert_eq!(value("(1+2)*3"), 9);
+        assert_eq!(value("10-4-3"), 3);
+        assert_eq!(value("100/7/2"), 7);
+        assert_eq!(value("-(2+3)*-2"), 10);
+        assert_eq!(value("MAXLEN/2+1"), 2049);
+    }
+
+    #[test]
+    fn constants() {
+        assert_eq!(value("C'EOF'"), 0x454F46);
+        assert_eq!(value("X'F1'+1"), 0xF2);
+        assert_eq!(value("x'10'*c'A'"), 0x10 * 0x41);
+        assert!(parse("C'LONG'").is_err());
+        assert!(parse("X'G1'").is_err());
+        assert!(parse("C'EOF").is_err());
+    }
+
+    #[test]
+    fn relative_and_absolute() {
+        let length = eval("BUFEND-BUFFER").unwrap();
+        assert_eq!((length.value, length.relative), (0x1000, false));
+
+        let address = eval("BUFFER+MAXLEN/2").unwrap();
+        assert_eq!((address.value, address.relative), (0x833, true));
+
+        let here = eval("*-1").unwrap();
+        assert_eq!((here.value, here.relative), (0xFF, true));
+
+        assert!(eval("BUFEND-BUFFER+BUFFER").unwrap().relative);
+        assert!(!eval("*-BUFFER").unwrap().relative);
+    }
+
+    #[test]
+    fn illegal_relative_terms() {
+        assert_eq!(eval("BUFFER+BUFEND").unwrap_err().code, Code::IllegalRelative);
+        assert_eq!(eval("MAXLEN-BUFFER").unwrap_err().code, Code::IllegalRelative);
+        assert_eq!(eval("-BUFFER").unwrap_err().code, Code::IllegalRelative);
+        assert_eq!(eval("BUFFER*2").unwrap_err().code, Code::IllegalRelative);
+        assert_eq!(eval("(BUFEND-BUFFER)*2").unwrap().value, 0x2000);
+        assert_eq!(eval("RDREC/2").unwrap_err().code, Code::IllegalRelative);
+    }
+
+    #[test]
+    fn external_terms() {
+        let result = eval("RDREC-MAXLEN").unwrap();
+        assert_eq!(result.value, -4096);
+        assert!(!result.relative);
+        assert_eq!(result.externals, vec![(false, "RDREC".to_owned())]);
+        assert_eq!(eval("-RDREC").unwrap().externals, vec![(true, "RDREC".to_owned())]);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        assert!(parse("").is_err());
+        assert!(parse("A+").is_err());
+        assert!(parse("(A+B").is_err());
+        assert!(parse("A B").is_err());
+        assert!(parse("A,X").is_err());
+        assert_eq!(eval("1/0").unwrap_err().code, Code::InvalidExpression);
+        assert_eq!(eval("LENGTH+1").unwrap_err().code, Code::UndefinedSymbol);
+    }
+
+    #[test]
+    fn overflows() {
+        assert_eq!(eval("2147483647*2").unwrap_err().code, Code::ValueOutOfRange);
+        assert_eq!(eval("2147483647+1").unwrap_err().code, Code::ValueOutOfRange);
+        assert_eq!(eval("-2147483647-2").unwrap_err().code, Code::ValueOutOfRange);
+        assert_eq!(eval("(-2147483647-1)/-1").unwrap_err().code, Code::ValueOutOfRange);
+        assert_eq!(value("-2147483647-1"), i32::MIN);
+    }
+}
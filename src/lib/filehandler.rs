@@ -1,34 +1,211 @@
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
 use std::u32;
 
 use std::io::BufRead;
 
-use instruction_set::{AssemblyDef, fetch_directive, fetch_instruction, is_directive, is_instruction};
+use instruction_set::{AssemblyDef, Directive, Mnemonic, is_directive, is_instruction};
 use instruction::*;
+use statement::Statement;
 use unit_or_pair::*;
 use formats::*;
 use operand_parsing::{parse_directive_operand, parse_instruction_operand, parse_ref_operands};
+use diagnostic::{Diagnostic, Code, Span, LineSpans, Note};
+use macros::{Macro, MacroTable, MAX_NESTING};
+use conditional::{self, Variables, MAX_ITERATIONS};
+use lexer::{self, Line, SourceFormat, Token};
 use super::*;
 
 pub struct FileHandler {
-    buf: BufReader<File>,
-    pub errs: Vec<String>,
+    inputs: Vec<Input>, // The files being read, the innermost INCLUDE last
+    path: String,
+    pub errs: Vec<Diagnostic>,
     line_number: i32,
+    source: Vec<String>, // Every line read so far, as is, with the included files
+    files: Vec<SourceFile>,
+    origins: Vec<(usize, i32)>, // The file and the line of every line of source
+    include_dirs: Vec<String>,
+    macros: MacroTable,
+    definition: Option<Definition>, // The macro whose body is being read
+    expanding: Vec<(String, i32)>, // What's being expanded and where it's defined
+    invocation: Option<Span>, // The macro invocation or loop of the current source line
+    variables: Variables,
+    conditions: Vec<Condition>, // The IFs the current statement is in
+    looping: Option<Loop>, // The WHILE loop whose body is being read
+    runaway: bool, // An expansion hit MAX_ITERATIONS or MAX_NESTING, the ones around it stop too
+    case_sensitive_labels: bool,
+    format: SourceFormat,
+}
+
+/// A macro definition until its MEND, depth counts the definitions in its body
+struct Definition {
+    mac: Option<Macro>, // None when the prototype is invalid, the body is skipped
+    depth: usize,
+}
+
+/// A file whose lines are being read
+struct Input {
+    buf: Box<dyn BufRead>,
+    file: usize,
+    line: i32,
+}
+
+/// The main file or an included one
+struct SourceFile {
+    path: String,
+    lines: Vec<String>,
+    included_from: Option<(usize, i32)>, // The file and the line of the INCLUDE
+}
+
+/// An IF until its ENDIF, taken tells whether a branch was assembled
+struct Condition {
+    active: bool,
+    taken: bool,
+    in_else: bool,
+    line: i32,
+}
+
+/// A WHILE loop until its ENDW, depth counts the loops in its body
+struct Loop {
+    condition: String,
+    lines: Vec<String>,
+    depth: usize,
+    line: i32,
 }
 
 impl FileHandler {
-    pub fn new(path: String) -> FileHandler {
-        let file = File::open(&path).unwrap();
-        let f = BufReader::new(file);
+    /// Reads the program from a file
+    pub fn open(path: String) -> Result<FileHandler, Diagnostic> {
+        match File::open(&path) {
+            Ok(file) => Ok(FileHandler::from_reader(path, BufReader::new(file))),
+            Err(e) => Err(Diagnostic::error(Code::Io, format!("can't read `{}`: {}", path, e))),
+        }
+    }
+
+    /// Reads the program from any reader, ex. stdin or a string, the path
+    /// only names the program in the diagnostics, ex. `<stdin>`, and the
+    /// files it includes are looked up from its directory
+    pub fn from_reader<R: BufRead + 'static>(path: String, reader: R) -> FileHandler {
         return FileHandler {
-            buf: f,
+            inputs: vec![Input {
+                             buf: Box::new(reader),
+                             file: 0,
+                             line: 0,
+                         }],
+            files: vec![SourceFile {
+                            path: path.clone(),
+                            lines: Vec::new(),
+                            included_from: None,
+                        }],
+            path,
             errs: Vec::new(),
             line_number: 0,
+            source: Vec::new(),
+            origins: Vec::new(),
+            include_dirs: Vec::new(),
+            macros: MacroTable::new(),
+            definition: None,
+            expanding: Vec::new(),
+            invocation: None,
+            variables: Variables::new(),
+            conditions: Vec::new(),
+            looping: None,
+            runaway: false,
+            case_sensitive_labels: false,
+            format: SourceFormat::Auto,
         };
     }
 
-    pub fn parse_file(&mut self) -> Result<RawProgram, String> {
+    /// Defines a SET variable before the file is read, ex. from `-D NAME=value`
+    pub fn define(&mut self, name: &str, value: &str) {
+        self.variables.set(name, value.to_owned());
+    }
+
+    /// Adds a directory where INCLUDE looks for files, after the directory
+    /// of the file with the INCLUDE
+    pub fn include_dir(&mut self, dir: &str) {
+        self.include_dirs.push(dir.to_owned());
+    }
+
+    /// Mnemonics, directives and registers are read in any case, and so are
+    /// the labels and the symbols of the operands unless this is set, then
+    /// `loop` and `LOOP` are different labels
+    pub fn case_sensitive_labels(&mut self, on: bool) {
+        self.case_sensitive_labels = on;
+    }
+
+    /// How the fields of the lines are told apart, by default the lines laid
+    /// out in card columns are read in them and the others in words
+    pub fn source_format(&mut self, format: SourceFormat) {
+        self.format = format;
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// The lines read so far, without their line endings, the lines of an
+    /// included file follow its INCLUDE
+    pub fn source_lines(&self) -> &[String] {
+        &self.source
+    }
+
+    /// Every file read so far and its lines, the main file first
+    pub fn files(&self) -> Vec<(&str, &[String])> {
+        self.files.iter().map(|file| (file.path.as_str(), file.lines.as_slice())).collect()
+    }
+
+    /// Moves diagnostics from the lines of the source to the files the lines
+    /// were read from, the INCLUDEs they went through become notes
+    pub fn locate(&self, diagnostics: &mut [Diagnostic]) {
+        let origin = |line: i32| if line >= 1 {
+            self.origins.get(line as usize - 1).cloned()
+        } else {
+            None
+        };
+
+        for diagnostic in diagnostics.iter_mut().filter(|d| d.file == self.path) {
+            let (file, line) = match origin(diagnostic.line) {
+                Some(found) => found,
+                None => continue,
+            };
+            diagnostic.file = self.files[file].path.clone();
+            diagnostic.line = line;
+
+            for note in &mut diagnostic.notes {
+                match note.line.and_then(&origin) {
+                    Some((note_file, note_line)) if note_file == file => {
+                        note.line = Some(note_line)
+                    }
+                    Some((note_file, note_line)) => {
+                        // Notes are shown in the file of the diagnostic
+                        note.message = format!("{} at {}:{}",
+                                               note.message,
+                                               self.files[note_file].path,
+                                               note_line);
+                        note.line = None;
+                        note.span = None;
+                    }
+                    None => (),
+                }
+            }
+
+            let mut from = self.files[file].included_from;
+            while let Some((file, line)) = from {
+                diagnostic.notes.push(Note {
+                    message: format!("included from {}:{}", self.files[file].path, line),
+                    line: None,
+                    span: None,
+                });
+                from = self.files[file].included_from;
+            }
+        }
+    }
+
+    /// Reads the program, the error is a line that can't be read, the
+    /// diagnostics of the lines before it are in errs
+    pub fn parse_file(&mut self) -> Result<RawProgram, Diagnostic> {
 
         let mut prog: RawProgram = RawProgram {
             program_name: String::new(),
@@ -36,23 +213,521 @@ impl FileHandler {
             program_length: u32::MAX,
             program: Vec::new(),
             first_instruction_address: u32::MAX,
+            sections: Vec::new(),
+            modifications: Vec::new(),
         };
 
-        while let Some(line) = self.process_file() {
-            if let Some(instruction) = self.read_instruction(line) {
-                prog.program.push((String::new(), instruction));
+        let unreadable = loop {
+            match self.process_file() {
+                Ok(Some(text)) => {
+                    self.invocation = None;
+                    self.statement(&text, 0, &mut prog.program);
+                }
+                Ok(None) => break None,
+                Err(e) => break Some(e),
             }
+        };
+        if let Some(e) = unreadable {
+            let mut errs = ::std::mem::take(&mut self.errs);
+            self.locate(&mut errs);
+            self.errs = errs;
+            return Err(e);
+        }
+
+        if let Some(Definition { mac: Some(mac), .. }) = self.definition.take() {
+            self.errs.push(Diagnostic::error(Code::InvalidMacro,
+                                             format!("macro `{}` has no MEND", mac.name))
+                .in_file(&self.path)
+                .at_line(mac.line)
+                .with_help("the body of a macro ends with `MEND`".to_owned()));
         }
+        self.close_blocks(0);
+
+        let mut errs = ::std::mem::take(&mut self.errs);
+        self.locate(&mut errs);
+        self.errs = errs;
 
         Ok(prog)
     }
 
+    /// Reads a statement, collecting it into the body of a macro or a loop,
+    /// assembling it conditionally or expanding a macro, depth is the number
+    /// of expansions it's in
+    fn statement(&mut self, text: &str, depth: usize, program: &mut Vec<(String, Instruction)>) {
+        let line = self.tokenize(text);
+        let (code, comment) = text.split_at(line.code_end);
+        let card_comment = line.labeled.is_some() &&
+                           line.comment.as_ref().is_some_and(|c| !c.text.starts_with('.'));
+        let words = line.words;
+        let mnemonic_is = |words: &[Token], name: &str| {
+            words.iter().take(2).any(|word| word.text.eq_ignore_ascii_case(name))
+        };
+
+        if let Some(mut definition) = self.definition.take() {
+            if mnemonic_is(&words, "MACRO") {
+                definition.depth += 1;
+            } else if mnemonic_is(&words, "MEND") {
+                if definition.depth == 0 {
+                    if let Some(mac) = definition.mac {
+                        self.macros.define(mac);
+                    }
+                    return;
+                }
+                definition.depth -= 1;
+            }
+            if let Some(ref mut mac) = definition.mac {
+                // The parameters move the columns of the body, a comment read
+                // in them gets a `.` to stay a comment in the expansions
+                if card_comment {
+                    mac.body.push(format!("{}. {}", code, comment));
+                } else {
+                    mac.body.push(text.to_owned());
+                }
+            }
+            self.definition = Some(definition);
+            return;
+        }
+        if words.is_empty() {
+            return;
+        }
+
+        let span = words[0].span.to(words[words.len() - 1].span);
+        let directive = words[0].text.to_uppercase();
+        if let Some(mut body) = self.looping.take() {
+            if directive == "WHILE" {
+                body.depth += 1;
+            } else if directive == "ENDW" {
+                if body.depth == 0 {
+                    if self.invocation.is_none() {
+                        self.invocation = Some(span);
+                    }
+                    self.repeat(body, depth, program);
+                    return;
+                }
+                body.depth -= 1;
+            }
+            body.lines.push(text.to_owned());
+            self.looping = Some(body);
+            return;
+        }
+
+        match directive.as_str() {
+            "IF" | "ELSE" | "ENDIF" => {
+                self.conditional(&directive, code, &words);
+                return;
+            }
+            _ if self.skipping() => return,
+            "WHILE" => {
+                let condition = code[words[0].span.end..].trim();
+                if condition.is_empty() {
+                    self.error(missing_condition("WHILE").with_span(Some(span)));
+                }
+                self.looping = Some(Loop {
+                    condition: condition.to_owned(),
+                    lines: Vec::new(),
+                    depth: 0,
+                    line: self.line_number,
+                });
+                return;
+            }
+            "ENDW" => {
+                self.error(Diagnostic::error(Code::InvalidConditional,
+                                             "ENDW without a WHILE".to_owned())
+                    .with_span(Some(span)));
+                return;
+            }
+            // COPY is also a label, ex. `COPY START 0`
+            "INCLUDE" | "COPY" if words.len() == 1 || !is_mnemonic(&words[1].text) => {
+                self.include(&words, depth);
+                return;
+            }
+            _ => (),
+        }
+
+        if words.len() > 1 && words[1].text.eq_ignore_ascii_case("SET") {
+            self.set(code, &words);
+            return;
+        } else if words.len() > 1 && words[1].text.eq_ignore_ascii_case("MACRO") {
+            let operands = words.get(2).map_or("", |word| word.text.as_str());
+            let mac = Macro::new(&words[0].text, operands, self.line_number)
+                .map_err(|e| self.error(e.with_span(Some(span))))
+                .ok();
+            if words.len() > 3 {
+                self.error(unexpected_words(&words[3..]));
+            }
+            self.definition = Some(Definition { mac, depth: 0 });
+            return;
+        } else if mnemonic_is(&words, "MACRO") || mnemonic_is(&words, "MEND") {
+            let message = if mnemonic_is(&words, "MEND") {
+                "MEND without a MACRO"
+            } else {
+                "expected the macro name before MACRO"
+            };
+            self.error(Diagnostic::error(Code::InvalidMacro, message.to_owned())
+                .with_span(Some(span))
+                .with_help("macros are defined by `NAME MACRO &PARAM,...` ... `MEND`"
+                    .to_owned()));
+            return;
+        }
+
+        let substituted = match self.variables.substitute(code, false) {
+            Ok(substituted) => substituted,
+            Err(e) => {
+                let span = self.invocation.unwrap_or(span);
+                self.error(e.with_span(Some(span)));
+                return;
+            }
+        };
+        let line = self.tokenize(&substituted);
+        let mut words = line.words;
+        // The columns of a statement with variables don't match the source
+        let span = match self.invocation {
+            Some(invocation) => Some(invocation),
+            None if substituted != code => Some(span),
+            None => None,
+        };
+
+        // A macro invocation, optionally labeled
+        let names = match line.labeled {
+            Some(true) => 1..2,
+            Some(false) => 0..1,
+            None => 0..2,
+        };
+        let invoked = names.take_while(|&i| i < words.len())
+            .find(|&i| self.macros.get(&words[i].text).is_some());
+        if let Some(at) = invoked {
+            let outer = self.invocation;
+            if outer.is_none() {
+                self.invocation = Some(words[0].span.to(words[words.len() - 1].span));
+            }
+            self.expand(&words, at, depth, program);
+            self.invocation = outer;
+            return;
+        }
+
+        if let Some(span) = span {
+            // Errors in expanded statements point at the invocation
+            for word in &mut words {
+                word.span = span;
+            }
+        }
+        if words.is_empty() {
+            return;
+        }
+        if let Some(mut instruction) = self.read_instruction(words, line.labeled) {
+            if depth > 0 {
+                let expanded = substituted + comment;
+                instruction.expanded = Some(expanded.trim_end().to_owned());
+            }
+            program.push((String::new(), instruction));
+        }
+    }
+
+    /// `INCLUDE 'file'`, the lines of the file are read before the next line
+    /// of the current one
+    fn include(&mut self, words: &[Token], depth: usize) {
+        let span = words[0].span.to(words[words.len() - 1].span);
+        if depth > 0 {
+            self.error(Diagnostic::error(Code::InvalidInclude,
+                                         format!("{} in a macro or a loop", words[0].text))
+                .with_span(self.invocation)
+                .with_help("include the file outside of the macro or the loop".to_owned()));
+            return;
+        }
+        let name = match words.get(1).map(|word| word.text.trim_matches('\'')) {
+            Some(name) if !name.is_empty() && words.len() == 2 => name.to_owned(),
+            _ => {
+                self.error(Diagnostic::error(Code::InvalidInclude,
+                                             format!("expected a file after {}", words[0].text))
+                    .with_span(Some(span))
+                    .with_help("ex. `INCLUDE 'devices.asm'`, names with a `.` are quoted"
+                        .to_owned()));
+                return;
+            }
+        };
+
+        let current = self.inputs.last().unwrap().file;
+        let path = match self.find_include(&name, current) {
+            Some(path) => path,
+            None => {
+                let mut searched = vec![directory_of(&self.files[current].path)];
+                searched.extend(self.include_dirs.iter().cloned());
+                for dir in searched.iter_mut().filter(|dir| dir.is_empty()) {
+                    *dir = ".".to_owned();
+                }
+                self.error(Diagnostic::error(Code::InvalidInclude,
+                                             format!("can't find `{}`", name))
+                    .with_span(Some(words[1].span))
+                    .with_help(format!("searched in {}, more directories are given with `-I`",
+                                       searched.join(", "))));
+                return;
+            }
+        };
+
+        // A file can't include itself, directly or through other files
+        let identity = |path: &str| fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+        let mut chain = Some(current);
+        while let Some(file) = chain {
+            if identity(&self.files[file].path) == identity(&path) {
+                self.error(Diagnostic::error(Code::InvalidInclude,
+                                             format!("`{}` includes itself", path))
+                    .with_span(Some(words[1].span)));
+                return;
+            }
+            chain = self.files[file].included_from.map(|(file, _)| file);
+        }
+
+        let buf: Box<dyn BufRead> = match File::open(&path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(e) => {
+                self.error(Diagnostic::error(Code::Io, format!("can't read `{}`: {}", path, e))
+                    .with_span(Some(words[1].span)));
+                return;
+            }
+        };
+        let line = self.inputs.last().unwrap().line;
+        self.files.push(SourceFile {
+            path,
+            lines: Vec::new(),
+            included_from: Some((current, line)),
+        });
+        self.inputs.push(Input {
+            buf,
+            file: self.files.len() - 1,
+            line: 0,
+        });
+    }
+
+    /// The path of an included file, next to the file that includes it or
+    /// in one of the include directories
+    fn find_include(&self, name: &str, current: usize) -> Option<String> {
+        if Path::new(name).is_absolute() {
+            return Some(name.to_owned()).filter(|path| Path::new(path).is_file());
+        }
+        let mut dirs = vec![directory_of(&self.files[current].path)];
+        dirs.extend(self.include_dirs.iter().cloned());
+        dirs.iter()
+            .map(|dir| Path::new(dir).join(name))
+            .find(|path| path.is_file())
+            .map(|path| path.to_string_lossy().into_owned())
+    }
+
+    /// `&NAME SET value`
+    fn set(&mut self, code: &str, words: &[Token]) {
+        let span = words[0].span.to(words[words.len() - 1].span);
+        let name = &words[0].text;
+        if !name.starts_with('&') || !is_label(&name[1..]) {
+            self.error(Diagnostic::error(Code::InvalidConditional,
+                                         format!("`{}` isn't a SET variable", name))
+                .with_span(Some(words[0].span))
+                .with_help("SET variables are written `&NAME`".to_owned()));
+            return;
+        }
+
+        let value = self.variables
+            .substitute(&code[words[1].span.end..], false)
+            .and_then(|operand| conditional::set_value(&operand));
+        match value {
+            Ok(value) => self.variables.set(&name[1..], value),
+            Err(e) => {
+                let span = self.invocation.unwrap_or(span);
+                self.error(e.with_span(Some(span)));
+            }
+        }
+    }
+
+    /// IF, ELSE and ENDIF, the statements are skipped while any condition
+    /// they're in is false
+    fn conditional(&mut self, directive: &str, code: &str, words: &[Token]) {
+        let span = words[0].span.to(words[words.len() - 1].span);
+        if directive != "IF" && words.len() > 1 {
+            self.error(unexpected_words(&words[1..]));
+        }
+
+        match directive {
+            "IF" => {
+                let active = !self.skipping() && self.test(&code[words[0].span.end..], span);
+                self.conditions.push(Condition {
+                    active,
+                    taken: active || self.skipping(),
+                    in_else: false,
+                    line: self.line_number,
+                });
+            }
+            "ELSE" => {
+                let error = match self.conditions.last_mut() {
+                    Some(ref condition) if condition.in_else => Some("ELSE after an ELSE"),
+                    Some(condition) => {
+                        condition.in_else = true;
+                        condition.active = !condition.taken;
+                        None
+                    }
+                    None => Some("ELSE without an IF"),
+                };
+                if let Some(message) = error {
+                    self.error(Diagnostic::error(Code::InvalidConditional, message.to_owned())
+                        .with_span(Some(span)));
+                }
+            }
+            _ => {
+                if self.conditions.pop().is_none() {
+                    self.error(Diagnostic::error(Code::InvalidConditional,
+                                                 "ENDIF without an IF".to_owned())
+                        .with_span(Some(span)));
+                }
+            }
+        }
+    }
+
+    fn skipping(&self) -> bool {
+        self.conditions.iter().any(|condition| !condition.active)
+    }
+
+    /// Evaluates a condition, an invalid one is false
+    fn test(&mut self, condition: &str, span: Span) -> bool {
+        if condition.trim().is_empty() {
+            self.error(missing_condition("IF").with_span(Some(span)));
+            return false;
+        }
+        let result = self.variables
+            .substitute(condition, true)
+            .and_then(|condition| conditional::condition(&condition));
+        match result {
+            Ok(result) => result,
+            Err(e) => {
+                let span = self.invocation.unwrap_or(span);
+                self.error(e.with_span(Some(span)));
+                false
+            }
+        }
+    }
+
+    /// Assembles the body of a WHILE loop until its condition is false
+    fn repeat(&mut self, body: Loop, depth: usize, program: &mut Vec<(String, Instruction)>) {
+        let span = self.invocation.unwrap();
+        if depth >= MAX_NESTING {
+            self.runaway = true;
+            self.error(Diagnostic::error(Code::InvalidConditional,
+                                         format!("loops and macros are nested more than {} deep",
+                                                 MAX_NESTING))
+                .with_span(Some(span)));
+            return;
+        }
+
+        self.expanding.push(("in the WHILE loop".to_owned(), body.line));
+        let mut iterations = 0;
+        while !self.runaway && self.test(&body.condition, span) {
+            if iterations == MAX_ITERATIONS {
+                self.runaway = true;
+                self.error(Diagnostic::error(Code::InvalidConditional,
+                                             format!("the loop repeats more than {} times",
+                                                     MAX_ITERATIONS))
+                    .with_span(Some(span))
+                    .with_help("check that the condition of the WHILE becomes false"
+                        .to_owned()));
+                break;
+            }
+            iterations += 1;
+
+            let conditions = self.conditions.len();
+            for line in &body.lines {
+                if self.runaway {
+                    break;
+                }
+                self.statement(line, depth + 1, program);
+            }
+            self.close_blocks(conditions);
+        }
+        self.expanding.pop();
+        if self.expanding.is_empty() {
+            self.runaway = false;
+        }
+    }
+
+    /// Reports the IF and WHILE blocks left open since there were only the
+    /// given number of conditions, at the end of an expansion or the file
+    fn close_blocks(&mut self, conditions: usize) {
+        let mut unclosed = Vec::new();
+        if let Some(body) = self.looping.take() {
+            unclosed.push(("WHILE without an ENDW", body.line));
+        }
+        while self.conditions.len() > conditions {
+            let condition = self.conditions.pop().unwrap();
+            unclosed.push(("IF without an ENDIF", condition.line));
+        }
+
+        for (message, line) in unclosed {
+            let mut diagnostic = Diagnostic::error(Code::InvalidConditional, message.to_owned());
+            if self.expanding.is_empty() {
+                diagnostic = diagnostic.in_file(&self.path).at_line(line);
+                self.errs.push(diagnostic);
+            } else {
+                // Blocks can't start in an expansion and end after it
+                let span = self.invocation;
+                self.error(diagnostic.with_span(span));
+            }
+        }
+    }
+
+    /// Expands the macro named by words[at], a label before it is defined at
+    /// the location of the expansion
+    fn expand(&mut self,
+              words: &[Token],
+              at: usize,
+              depth: usize,
+              program: &mut Vec<(String, Instruction)>) {
+        let mac = self.macros.get(&words[at].text).unwrap().clone();
+        if depth >= MAX_NESTING {
+            self.runaway = true;
+            self.error(Diagnostic::error(Code::InvalidMacroCall,
+                                         format!("macros are nested more than {} deep",
+                                                 MAX_NESTING))
+                .with_span(self.invocation)
+                .with_help(format!("check that `{}` stops invoking itself", mac.name)));
+            return;
+        }
+        if words.len() > at + 2 {
+            self.error(unexpected_words(&words[at + 2..]));
+        }
+
+        let args = words.get(at + 1).map_or("", |word| word.text.as_str());
+        let unique = self.macros.next_unique();
+        let lines = match mac.expand(args, &unique) {
+            Ok(lines) => lines,
+            Err(e) => {
+                let span = self.invocation;
+                self.error(e.with_span(span));
+                return;
+            }
+        };
+
+        if at == 1 {
+            self.statement(&format!("{} EQU *", words[0].text), depth, program);
+        }
+        self.expanding.push((format!("in the expansion of macro `{}`", mac.name), mac.line));
+        let conditions = self.conditions.len();
+        for line in lines {
+            if self.runaway {
+                break;
+            }
+            self.statement(&line, depth + 1, program);
+        }
+        self.close_blocks(conditions);
+        self.expanding.pop();
+        if self.expanding.is_empty() {
+            self.runaway = false;
+        }
+    }
+
     #[allow(unused_mut)]
-    fn read_instruction(&mut self, line: Vec<String>) -> Option<Instruction> {
+    fn read_instruction(&mut self,
+                        line: Vec<Token>,
+                        labeled: Option<bool>)
+                        -> Option<Instruction> {
 
         let mut inst: Instruction;
         let mut def: AssemblyDef;
-        match self.parse_line_of_code(line) {
+        match self.parse_line_of_code(line, labeled) {
             None => return None,
             Some((instruction, defi)) => {
                 inst = instruction;
@@ -65,158 +740,246 @@ impl FileHandler {
         return Some(inst);
     }
 
+    fn tokenize(&self, text: &str) -> Line {
+        lexer::tokenize_as(text, self.format)
+    }
+
+    /// Labels and operands are read in uppercase, the quoted texts keep their case
+    /// but for the hex digits of `X'..'`
+    fn fold_case(&self, text: &str) -> String {
+        if self.case_sensitive_labels {
+            lexer::uppercase_constants(text)
+        } else {
+            lexer::uppercase_unquoted(text)
+        }
+    }
+
+    fn error(&mut self, diagnostic: Diagnostic) {
+        let mut diagnostic = diagnostic.in_file(&self.path).at_line(self.line_number);
+        if let Some(&(ref note, line)) = self.expanding.last() {
+            diagnostic = diagnostic.with_note_at(note.clone(), line, None);
+        }
+        self.errs.push(diagnostic);
+    }
+
     #[allow(unused_mut)]
     #[allow(unused_assignments)]
-    fn parse_line_of_code(&mut self, mut words: Vec<String>) -> Option<(Instruction, AssemblyDef)> {
+    fn parse_line_of_code(&mut self,
+                          mut words: Vec<Token>,
+                          labeled: Option<bool>)
+                          -> Option<(Instruction, AssemblyDef)> {
 
         let mut label: String = String::new();
-        let mut instruction: String;
-        let mut instruction_def: AssemblyDef = AssemblyDef::dummy();
+        let mut mnemonic: Mnemonic;
         let mut is_format_4 = false;
-        let mut is_asm_directive = false;
         let mut operands: UnitOrPair<AsmOperand> = UnitOrPair::None;
-
-        if words.len() > 3 || words.is_empty() {
-            self.errs.push(format!("Invalid code at line #{}, {:?}", self.line_number, words));
-        }
+        let mut spans = LineSpans::default();
 
         // for format four instructions
-        let mut temp = words[0].clone();
+        let mut temp = words[0].text.clone();
         if temp.starts_with("+") {
             temp = temp[1..].to_owned();
         }
 
-        // Allow for labels that have the same name as mnemonics (words.len()==3)
-        if (!is_instruction(&temp) && !is_directive(&temp)) || words.len() == 3 {
+        // Allow for labels that have the same name as mnemonics (words.len()==3),
+        // in card columns the label is known
+        let labeled = labeled
+            .unwrap_or((!is_instruction(&temp) && !is_directive(&temp)) || words.len() == 3);
+        if labeled {
+            let word = words.remove(0);
             if !is_label(&temp) {
-                self.errs.push(format!("Invalid label token at line #{} or might've exceeded \
-                                        the allowed length : {}",
-                                       self.line_number,
-                                       words[0]));
+                self.error(Diagnostic::error(Code::InvalidLabel,
+                                             format!("invalid label `{}`", word.text))
+                    .with_span(Some(word.span))
+                    .with_help("labels start with a letter or `_` followed by letters, \
+                                digits or `_`"
+                        .to_owned()));
             }
-            label = words.remove(0);
+            label = self.fold_case(&word.text);
+            spans.label = Some(word.span);
+        }
+
+        if words.is_empty() {
+            self.error(Diagnostic::error(Code::InvalidLine,
+                                         format!("expected a mnemonic after `{}`", temp))
+                .with_span(spans.label));
+            return None;
         }
 
-        instruction = words.remove(0);
-        match get_def(&mut instruction) {
-            Ok((def, is_4, is_dir)) => {
-                instruction_def = def;
+        let word = words.remove(0);
+        spans.mnemonic = Some(word.span);
+        match get_def(&word.text) {
+            Ok((def, is_4)) => {
+                mnemonic = def;
                 is_format_4 = is_4;
-                is_asm_directive = is_dir;
             }
             Err(e) => {
-                self.errs.push(format!("{} at line {}", e, self.line_number));
+                self.error(e.with_span(spans.mnemonic));
                 return None;
             }
         }
 
+        let mut parsed = true;
         if !words.is_empty() {
-            let mut op = words.remove(0);
-            // Operand of BYTE may contain spaces, and that would cause them to be split
-            // by the file reader, concat them, any erros will be handled by operand parser
-
-            if (op.starts_with("C'") || op.starts_with("c'")) && op.len() > 0 {
-                for x in &words {
-                    op = op + x.as_str();
-                }
-                words.clear();
-            }
-            match parse_operands(&op, is_asm_directive, &instruction) {
+            let word = words.remove(0);
+            let op = self.fold_case(&word.text);
+            let span = word.span;
+            spans.operands = Some(span);
+            match parse_operands(&op, mnemonic) {
                 Ok(e) => operands = e,
-                Err(e) => self.errs.push(format!("Failed to parse {{ {:#?} }} As {}", op, e)),
+                Err(errs) => {
+                    parsed = false;
+                    for e in errs {
+                        self.error(e.or_span(Some(span)));
+                    }
+                }
             };
         }
 
-        let mut inst = Instruction::new(label, instruction, operands);
+        if !words.is_empty() {
+            self.error(unexpected_words(&words));
+        }
+
+        // An operand that didn't parse is already reported
+        let statement = if parsed {
+            Statement::parse(mnemonic, &operands).unwrap_or_else(|e| {
+                self.error(e.or_span(spans.operands.or(spans.mnemonic)));
+                Statement::unparsed(mnemonic)
+            })
+        } else {
+            Statement::unparsed(mnemonic)
+        };
+
+        let mut inst = Instruction::new(label, statement, operands);
         inst.set_line_number(self.line_number);
+        inst.spans = spans;
 
         if is_format_4 {
             inst.set_format(Format::Four);
         }
 
-        Some((inst, instruction_def))
+        Some((inst, mnemonic.definition()))
     }
 
-    /// Reads a line of code, removing the comments and bypassing empty lines
-    fn process_file(&mut self) -> Option<Vec<String>> {
+    /// Reads a line of code with its comment, bypassing empty lines and comments
+    fn process_file(&mut self) -> Result<Option<String>, Diagnostic> {
         // Returns ->
-        // None -> EOF
-        // Some -> Code
-        // Panic -> I/O error
+        // Ok(None) -> EOF
+        // Ok(Some) -> Code
+        // Err -> I/O error, ex. a line that isn't UTF-8
 
         let mut line: String = String::new();
 
-        while self.buf.read_line(&mut line).unwrap() > 0 {
+        loop {
+            let input = match self.inputs.last_mut() {
+                Some(input) => input,
+                None => return Ok(None),
+            };
+            let read = match input.buf.read_line(&mut line) {
+                Ok(read) => read,
+                Err(e) => {
+                    let path = &self.files[input.file].path;
+                    return Err(Diagnostic::error(Code::Io,
+                                                 format!("can't read `{}`: {}", path, e))
+                        .in_file(path)
+                        .at_line(input.line + 1));
+                }
+            };
+            if read == 0 {
+                // The end of an included file continues the file that included it
+                self.inputs.pop();
+                continue;
+            }
+            input.line += 1;
             self.line_number = self.line_number + 1;
-            let temp: String = (*COMMENT_REGEX.replace_all(line.as_str(), "")).to_owned();
-
-            // Remove whitespace on right, whitespace on the left will be
-            // used to extract the labels
-            let temp = temp.trim_right();
-            // Remove space after commas, more flexible code style
-            let temp = &(*COMMA_WHITESPACE_REGEX.replace(temp, ","));
-            // Split the source code lines to label, instruciton and operands
-            let temp = SPLIT_SOURCE_LINE_SPLIT_REGEX.split(temp)
-                .filter(|ref x| !x.replace(" ", "").is_empty())
-                .map(|x| x.to_owned())
-                .collect::<Vec<String>>();
+            let text = line.trim_end_matches(['\n', '\r']).to_owned();
+            self.source.push(text.clone());
+            self.origins.push((input.file, input.line));
+            self.files[input.file].lines.push(text.clone());
             line.clear();
-            if temp.is_empty() {
+
+            if self.tokenize(&text).is_empty() {
                 continue;
             }
-            return Some(temp.to_owned());
+            return Ok(Some(text));
         }
-        None
     }
 }
 
+/// The directory of a file, empty for the current directory
+fn directory_of(path: &str) -> String {
+    Path::new(path).parent().map_or(String::new(), |dir| dir.to_string_lossy().into_owned())
+}
+
+fn is_mnemonic(word: &str) -> bool {
+    let mnemonic = word.trim_start_matches('+').to_uppercase();
+    is_instruction(&mnemonic) || is_directive(&mnemonic)
+}
+
+fn missing_condition(directive: &str) -> Diagnostic {
+    Diagnostic::error(Code::InvalidConditional,
+                      format!("expected a condition after {}", directive))
+        .with_help(format!("ex. `{} (&SIZE GT 100)`", directive))
+}
+
+fn unexpected_words(words: &[Token]) -> Diagnostic {
+    let extra = words[0].span.to(words[words.len() - 1].span);
+    Diagnostic::error(Code::InvalidLine,
+                      format!("unexpected `{}` after the operands", words[0].text))
+        .with_span(Some(extra))
+        .with_help("comments start with `.`".to_owned())
+}
+
 fn parse_operands(operand_string: &str,
-                  is_directive: bool,
-                  instruction: &str)
-                  -> Result<UnitOrPair<AsmOperand>, String> {
+                  mnemonic: Mnemonic)
+                  -> Result<UnitOrPair<AsmOperand>, Vec<Diagnostic>> {
     let ops: Vec<&str> = operand_string.split(",").collect();
-    let mut errs: Vec<String> = Vec::new();
-
-    if instruction == "EXTREF" || instruction == "EXTDEF" {
+    let mut errs: Vec<Diagnostic> = Vec::new();
+    if let Some(Directive::Extref) | Some(Directive::Extdef) = mnemonic.directive() {
         let op_vec: Vec<String> = ops.iter().map(|opx| String::from(*opx)).collect::<Vec<String>>();
         let opr = UnitOrPair::Unit(parse_ref_operands(op_vec));
         return Ok(opr);
     }
 
     match ops.len() {
-        0 => return Ok(UnitOrPair::None),
+        0 => Ok(UnitOrPair::None),
         1 => {
-            let op = if is_directive {
-                parse_directive_operand(ops[0], instruction)
-            } else {
-                parse_instruction_operand(ops[0])
+            let op = match mnemonic {
+                Mnemonic::Directive(directive) => parse_directive_operand(ops[0], directive),
+                Mnemonic::Instruction(_) => parse_instruction_operand(ops[0]),
             };
             match op {
-                Ok(o) => return Ok(UnitOrPair::Unit(o)),
+                Ok(o) => Ok(UnitOrPair::Unit(o)),
                 Err(e) => {
                     errs.push(e);
-                    return Err(errs.join("\n"));
+                    Err(errs)
                 }
             }
         }
         2 => {
-            if is_directive {
-                panic!("Assembler directives can't have 2 Operands");
+            if mnemonic.directive().is_some() {
+                errs.push(Diagnostic::error(Code::InvalidOperands,
+                                            format!("`{}` can't have 2 operands", mnemonic))
+                    .with_help("only instructions take 2 operands, ex. `LDA BUFFER,X`"
+                        .to_owned()));
+                return Err(errs);
             }
             let op1 = parse_instruction_operand(ops[0]);
             let op2 = parse_instruction_operand(ops[1]);
-            if op1.is_ok() && op2.is_ok() {
-                return Ok(UnitOrPair::Pair(op1.unwrap(), op2.unwrap()));
-            } else {
-                let _ = op1.map_err(|e| errs.push(e));
-                let _ = op2.map_err(|e| errs.push(e));
-                return Err(errs.join("\n"));
+            match (op1, op2) {
+                (Ok(op1), Ok(op2)) => Ok(UnitOrPair::Pair(op1, op2)),
+                (op1, op2) => {
+                    errs.extend(op1.err());
+                    errs.extend(op2.err());
+                    Err(errs)
+                }
             }
 
         }
         _ => {
-            errs.push(format!("expected . or newline instead of `{}`", ops[2]));
-            return Err(errs.join("\n"));
+            errs.push(Diagnostic::error(Code::InvalidOperand,
+                                        format!("expected . or newline instead of `{}`", ops[2])));
+            Err(errs)
         }
     }
 }
@@ -226,35 +989,25 @@ fn set_format(inst: &mut Instruction, instruction_def: AssemblyDef) {
         return;
     }
 
-    let format = unwrap_to_vec(&instruction_def.format);
-    match format.len() {
-        0 => (),
-        1 => inst.set_format(format[0]),
-        2 => inst.set_format(Format::Three),
-        _ => panic!("We Just found an instruction that had more than 2 formats! you are screwed"),
+    // Format 3/4 instructions are format 3, unless a `+` already made them format 4
+    match instruction_def.format {
+        UnitOrPair::None => (),
+        UnitOrPair::Unit(format) => inst.set_format(format),
+        UnitOrPair::Pair(..) => inst.set_format(Format::Three),
     }
 }
 
-#[allow(unused_mut)] // Compiler generates false warnings
-fn get_def(inst: &mut String) -> Result<(AssemblyDef, bool, bool), String> {
-    let mut instruction_def: AssemblyDef;
-    let mut is_format_4 = false;
-    let mut is_directive = false;
-
-    if inst.starts_with("+") {
-        is_format_4 = true;
-        *inst = inst.trim_left_matches("+").to_owned(); // Ignore the '+' sign
-    }
-
-    if let Ok(def) = fetch_instruction(&inst) {
-        instruction_def = def;
-    } else if let Ok(def) = fetch_directive(&inst) {
-        is_directive = true;
-        instruction_def = def;
-    } else {
-        return Err(format!("\"{}\" isn't an instruction nor directive", inst));
+/// The mnemonic of a word and whether it's format 4
+fn get_def(word: &str) -> Result<(Mnemonic, bool), Diagnostic> {
+    let is_format_4 = word.starts_with('+');
+    match Mnemonic::parse(word.trim_start_matches('+')) {
+        Some(mnemonic) => Ok((mnemonic, is_format_4)),
+        None => {
+            Err(Diagnostic::error(Code::UnknownMnemonic,
+                                  format!("`{}` isn't an instruction nor a directive",
+                                          word.trim_start_matches('+'))))
+        }
     }
-    return Ok((instruction_def, is_format_4, is_directive));
 }
 
 
@@ -262,13 +1015,15 @@ fn get_def(inst: &mut String) -> Result<(AssemblyDef, bool, bool), String> {
 #[cfg(test)]
 mod tests {
     use super::*; // Use all your parent's imports
+    use instruction_set::Opcode;
     use regex::Regex;
     use std::io::Read;
 
     #[test]
-    #[should_panic]
     fn test_file_opening() {
-        FileHandler::new("God Damn long file name that should never exit.asm".to_string());
+        let result = FileHandler::open("God Damn long file name that should never exit.asm"
+            .to_string());
+        assert_eq!(result.err().unwrap().code, Code::Io);
     }
 
     #[test]
@@ -285,7 +1040,7 @@ mod tests {
     fn test_parse_file() {
         let lines = with_regex();
         // Without regex
-        let mut asm_file = FileHandler::new("src/tests/test1.asm".to_owned());
+        let mut asm_file = FileHandler::open("src/tests/test1.asm".to_owned()).unwrap();
         let prog = asm_file.parse_file().unwrap();
 
         for i in 0..prog.program.len() {
@@ -296,19 +1051,270 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_diagnostic_positions() {
+        let mut asm_file = FileHandler::open("src/tests/test1.asm".to_owned()).unwrap();
+        asm_file.parse_file().unwrap();
+
+        assert_eq!(asm_file.errs.len(), 1);
+        let err = &asm_file.errs[0];
+        assert_eq!(err.code, Code::InvalidLine);
+        assert_eq!(err.file, "src/tests/test1.asm");
+        assert_eq!((err.line, err.column()), (29, 16));
+    }
+
+    #[test]
+    fn macros_are_expanded() {
+        let mut asm_file = FileHandler::open("src/tests/macros.asm".to_owned()).unwrap();
+        let prog = asm_file.parse_file().unwrap();
+        assert!(asm_file.errs.is_empty());
+
+        let expanded = prog.program
+            .iter()
+            .filter(|(_, instr)| instr.src_line_num == 29)
+            .map(|(_, instr)| instr.expanded.clone().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(expanded.len(), 13);
+        assert_eq!(expanded[4], "_AALOOP    TD      =X'F1'");
+        assert_eq!(expanded[9], "         STCH    BUFFER,X");
+
+        // The nested invocations and the keyword arguments
+        let devices = prog.program
+            .iter()
+            .filter(|(_, instr)| instr.mnemonic() == Mnemonic::Instruction(Opcode::Td))
+            .map(|(_, instr)| (instr.label.clone(), instr.src_line_num))
+            .collect::<Vec<_>>();
+        assert_eq!(devices,
+                   vec![("_AALOOP".to_owned(), 29),
+                        ("_ACLOOP".to_owned(), 30),
+                        ("_ADLOOP".to_owned(), 30),
+                        ("_AELOOP".to_owned(), 31)]);
+        // The label of an invocation isn't part of the expansion
+        let cloop = prog.program.iter().find(|(_, instr)| instr.label == "CLOOP").unwrap();
+        assert_eq!(cloop.1.expanded, None);
+    }
+
+    #[test]
+    fn macro_expansions_keep_comments() {
+        let mut asm_file = FileHandler::open("src/tests/macro_comments.asm".to_owned()).unwrap();
+        let prog = asm_file.parse_file().unwrap();
+        assert!(asm_file.errs.is_empty());
+
+        let expanded = prog.program
+            .iter()
+            .filter_map(|(_, instr)| instr.expanded.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(expanded,
+                   vec!["_AALOOP    TD      =X'F1'    . wait for the device",
+                        "         JEQ     _AALOOP       . not ready yet"]);
+    }
+
+    #[test]
+    fn card_columns() {
+        let mut asm_file = FileHandler::open("src/tests/cards.asm".to_owned()).unwrap();
+        let prog = asm_file.parse_file().unwrap();
+        assert!(asm_file.errs.is_empty());
+
+        let statements = prog.program
+            .iter()
+            .map(|(_, instr)| (instr.label.as_str(), instr.mnemonic().to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(statements,
+                   vec![("CARDS", "START".to_owned()),
+                        ("T1", "EQU".to_owned()),
+                        ("", "LDA".to_owned()),
+                        ("T2", "EQU".to_owned()),
+                        ("", "LDA".to_owned()),
+                        ("RSUB", "EQU".to_owned()),
+                        ("", "LDX".to_owned()),
+                        ("", "RSUB".to_owned()),
+                        ("", "END".to_owned())]);
+        // The columns of the expansion moved, its comment is still a comment
+        let lda = &prog.program[2].1;
+        assert_eq!(lda.expanded.as_deref(), Some("         LDA  #1           . 01"));
+    }
+
+    #[test]
+    fn macro_errors() {
+        let mut asm_file = FileHandler::open("src/tests/macro_errors.asm".to_owned()).unwrap();
+        asm_file.parse_file().unwrap();
+
+        let errs = asm_file.errs
+            .iter()
+            .map(|e| (e.code, e.line))
+            .collect::<Vec<_>>();
+        assert_eq!(errs,
+                   vec![(Code::InvalidMacroCall, 5),
+                        (Code::UnknownMnemonic, 6),
+                        (Code::InvalidMacro, 7),
+                        (Code::InvalidMacro, 8),
+                        (Code::InvalidMacroCall, 12),
+                        (Code::InvalidMacro, 13)]);
+        // Errors in an expansion point at the invocation and the definition
+        assert_eq!(asm_file.errs[1].span, Some(Span::new(9, 20)));
+        assert_eq!(asm_file.errs[1].notes[0].line, Some(2));
+    }
+
+    #[test]
+    fn conditional_assembly() {
+        let statements = |defines: &[(&str, &str)]| {
+            let mut asm_file = FileHandler::open("src/tests/conditional.asm".to_owned()).unwrap();
+            for &(name, value) in defines {
+                asm_file.define(name, value);
+            }
+            let prog = asm_file.parse_file().unwrap();
+            assert!(asm_file.errs.is_empty());
+            prog.program
+                .iter()
+                .map(|(_, instr)| {
+                    let operand = instr.unwrap_operands().first().map(|op| format!("{:?}", op.val));
+                    format!("{} {}", instr.mnemonic(), operand.unwrap_or_default())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let release = statements(&[]);
+        assert_eq!(release[2], "TD \"Literal/Bytes =X'F1'\"");
+        assert_eq!(release[4], "LDA \"Immediate 0\"");
+
+        let debug = statements(&[("DEBUG", "1"), ("DEV", "05")]);
+        assert_eq!(debug[2], "TD \"Literal/Bytes =X'05'\"");
+        assert_eq!(debug[4], "LDA \"Immediate 4\"");
+
+        // The WHILE loop makes a word for every square
+        let mut asm_file = FileHandler::open("src/tests/conditional.asm".to_owned()).unwrap();
+        let prog = asm_file.parse_file().unwrap();
+        let squares = prog.program
+            .iter()
+            .filter_map(|(_, instr)| instr.expanded.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(squares,
+                   vec!["SQ1     WORD    1*1",
+                        "SQ2     WORD    2*2",
+                        "SQ3     WORD    3*3",
+                        "SQ4     WORD    4*4"]);
+    }
+
+    #[test]
+    fn conditional_errors() {
+        let mut asm_file = FileHandler::open("src/tests/conditional_errors.asm".to_owned())
+            .unwrap();
+        asm_file.parse_file().unwrap();
+
+        let errs = asm_file.errs
+            .iter()
+            .map(|e| (e.code, e.line))
+            .collect::<Vec<_>>();
+        assert_eq!(errs,
+                   vec![(Code::InvalidConditional, 4),
+                        (Code::InvalidConditional, 5),
+                        (Code::InvalidConditional, 8),
+                        (Code::UndefinedVariable, 10),
+                        (Code::InvalidConditional, 11),
+                        (Code::InvalidConditional, 15),
+                        (Code::InvalidConditional, 16),
+                        (Code::InvalidConditional, 17),
+                        (Code::InvalidConditional, 17)]);
+        assert_eq!(asm_file.errs[5].notes[0].line, Some(13));
+    }
+
+    #[test]
+    fn included_files() {
+        let mut asm_file = FileHandler::open("src/tests/includes.asm".to_owned()).unwrap();
+        asm_file.include_dir("src/tests/include");
+        let prog = asm_file.parse_file().unwrap();
+        assert!(asm_file.errs.is_empty());
+
+        let labels = prog.program
+            .iter()
+            .map(|(_, instr)| (instr.label.as_str(), instr.mnemonic().name()))
+            .collect::<Vec<_>>();
+        assert_eq!(labels,
+                   vec![("INCTST", "START"),
+                        ("MAXLEN", "EQU"),
+                        ("FIRST", "EQU"),
+                        ("_AALOOP", "TD"),
+                        ("", "JEQ"),
+                        ("", "LDT"),
+                        ("HALF", "EQU"),
+                        ("", "LDA"),
+                        ("", "RSUB"),
+                        ("", "END")]);
+
+        let files = asm_file.files();
+        assert_eq!(files.iter().map(|file| file.0).collect::<Vec<_>>(),
+                   vec!["src/tests/includes.asm",
+                        "src/tests/include/devices.asm",
+                        "src/tests/include/consts.asm",
+                        "src/tests/include/sizes.asm"]);
+        assert_eq!(files[2].1, &[". Constants of the buffers", "MAXLEN   EQU     4096"]);
+        // The listing has the included lines after their INCLUDE
+        assert_eq!(asm_file.source_lines().len(), 17);
+        assert_eq!(asm_file.source_lines()[9], "MAXLEN   EQU     4096");
+    }
+
+    #[test]
+    fn include_errors() {
+        let mut asm_file = FileHandler::open("src/tests/include_errors.asm".to_owned()).unwrap();
+        asm_file.parse_file().unwrap();
+
+        let errs = asm_file.errs
+            .iter()
+            .map(|e| (e.code, e.file.as_str(), e.line))
+            .collect::<Vec<_>>();
+        assert_eq!(errs,
+                   vec![(Code::InvalidInclude, "src/tests/include/cycle_b.asm", 2),
+                        (Code::InvalidInclude, "src/tests/include_errors.asm", 3),
+                        (Code::InvalidInclude, "src/tests/include_errors.asm", 4),
+                        (Code::InvalidInclude, "src/tests/include_errors.asm", 8)]);
+
+        let chain = asm_file.errs[0]
+            .notes
+            .iter()
+            .map(|note| note.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(chain,
+                   vec!["included from src/tests/include/cycle_a.asm:1",
+                        "included from src/tests/include_errors.asm:2"]);
+    }
+
+    #[test]
+    fn quoted_texts() {
+        let mut asm_file = FileHandler::open("src/tests/strings.asm".to_owned()).unwrap();
+        let prog = asm_file.parse_file().unwrap();
+        assert!(asm_file.errs.is_empty());
+
+        let operands = prog.program
+            .iter()
+            .filter_map(|(_, instr)| {
+                instr.unwrap_operands().first().map(|op| format!("{:?}", op.val))
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(operands,
+                   vec!["\"Raw  0x0\"",
+                        "\"Literal/Bytes =C'A B'\"",
+                        "\"Label DOT\"",
+                        "\"Literal/Bytes C'HELLO WORLD'\"",
+                        "\"Literal/Bytes C'A.B'\"",
+                        "\"Literal/Bytes C'IT''S'\"",
+                        "\"Label FIRST\""]);
+        let dot = prog.program.iter().find(|(_, instr)| instr.label == "DOT").unwrap();
+        assert_eq!(dot.1.spans.operands, Some(Span::new(17, 23)));
+    }
+
     /// Extracts code in file using regex
     fn with_regex() -> Vec<String> {
         /// Matches the number of instructions that come out from code
         /// with the number of instructions in file
 
-        let mut asm_file = FileHandler::new("src/tests/test1.asm".to_owned());
+        let mut asm_file = FileHandler::open("src/tests/test1.asm".to_owned()).unwrap();
 
         // Regex reference: http://kbknapp.github.io/doapi-rs/docs/regex/index.html
         // Escape all empty lines or comment lines
         let empty_lines_regex = Regex::new(r"(?m)^\s*\n|^\s+").unwrap();
         let comment_regex = Regex::new(r"(?m)\..+").unwrap();
         let mut file_content: String = String::new();
-        match asm_file.buf.read_to_string(&mut file_content) {
+        match asm_file.inputs[0].buf.read_to_string(&mut file_content) {
             Err(e) => println!("error: {}", e),
             _ => (),
         };
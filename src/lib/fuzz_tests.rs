@@ -0,0 +1,212 @@
+/// Assembles random programs and mangled copies of the test programs, every
+/// mistake must come out as a diagnostic, never as a panic
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::fs;
+    use std::io::Cursor;
+    use std::panic;
+    use std::sync::Once;
+    use assembler::{assemble, assemble_str, Options};
+    use diagnostic::Code;
+    use filehandler::FileHandler;
+    use instruction_set::{Directive, Opcode};
+
+    /// A xorshift generator, the runs are repeatable
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.below(items.len())]
+        }
+    }
+
+    const LABELS: &[&str] = &["", "", "", "LOOP", "BUFFER", "BUFEND", "LENGTH", "FIRST", "RDREC",
+                              "X", "A", "1ABC", "lda", "$LOOP", "&VAR", "_", "COPY"];
+    const OTHERS: &[&str] = &["MACRO", "MEND", "SET", "IF", "ELSE", "ENDIF", "WHILE", "ENDW",
+                              "INCLUDE", "+LDA", "+JSUB", "+RMO", "+BYTE", "lda", "resw",
+                              "Foo", "+", "=", "."];
+    const OPERANDS: &[&str] = &["", "0", "1", "-1", "3", "4096", "2147483647", "-2147483648",
+                                "99999999999", "FFFFFF", "1000", "LOOP", "BUFFER", "BUFEND",
+                                "LENGTH", "#0", "#4096", "#-5", "#LENGTH", "@LENGTH",
+                                "@RETADR+3", "#", "@", "=", "*", "=*", "C'EOF'", "C''",
+                                "C'A B'", "C'IT''S'", "C'", "X'F1'", "X'F'", "X''", "X'G1'",
+                                "=C'EOF'", "=X'05'", "=X'", "=C'A.B'", "BUFFER,X", "A,X",
+                                "A,S", "X,A", "T", "A,4", "S,16", "BUFFER,X,X", ",", ",,",
+                                "BUFEND-BUFFER", "BUFFER+BUFEND", "(LENGTH+2)/3", "3/0",
+                                "LENGTH*LENGTH*LENGTH", "((", ")", "-", "+", "*+3", "*-*",
+                                "BUFFER,LENGTH", "&X", "(1 EQ 1)", "(&N LT 3)", "'F1'",
+                                "'missing.asm'", "X'FFFFFFFFFF'", "#X'F1'"];
+
+    fn statement(random: &mut Random) -> String {
+        let label = random.pick(LABELS);
+        let mnemonic = match random.below(10) {
+            0..=4 => Opcode::ALL[random.below(Opcode::ALL.len())].name(),
+            5..=8 => Directive::ALL[random.below(Directive::ALL.len())].name(),
+            _ => random.pick(OTHERS),
+        };
+        let mut operand = random.pick(OPERANDS).to_owned();
+        if random.below(6) == 0 {
+            operand = format!("{},{}", operand, random.pick(OPERANDS));
+        }
+        if random.below(8) == 0 {
+            operand = format!("{} {}", operand, random.pick(OPERANDS));
+        }
+        format!("{:8} {:7} {}", label, mnemonic, operand)
+    }
+
+    fn program(random: &mut Random) -> String {
+        let mut lines = Vec::new();
+        if random.below(5) != 0 {
+            lines.push("PROG     START   1000".to_owned());
+        }
+        for _ in 0..random.below(25) {
+            lines.push(statement(random));
+        }
+        if random.below(5) != 0 {
+            lines.push("         END     FIRST".to_owned());
+        }
+        lines.join("\n")
+    }
+
+    /// Drops, repeats, swaps and garbles the lines of a program
+    fn mangle(random: &mut Random, source: &str) -> String {
+        let mut lines = source.lines().map(|line| line.to_owned()).collect::<Vec<String>>();
+        for _ in 0..1 + random.below(4) {
+            if lines.is_empty() {
+                break;
+            }
+            let at = random.below(lines.len());
+            match random.below(6) {
+                0 => {
+                    lines.remove(at);
+                }
+                1 => {
+                    let line = lines[at].clone();
+                    lines.insert(at, line);
+                }
+                2 => {
+                    let other = random.below(lines.len());
+                    lines.swap(at, other);
+                }
+                3 => lines[at] = statement(random),
+                4 => {
+                    let end = random.below(lines[at].len() + 1);
+                    let end = (0..=end).rev().find(|&i| lines[at].is_char_boundary(i)).unwrap();
+                    lines[at].truncate(end);
+                }
+                _ => {
+                    let garbage = ['\'', ',', '.', '+', '#', '@', '=', '&', ' ', '\t', '(', 'é'];
+                    let mut chars = lines[at].chars().collect::<Vec<char>>();
+                    let i = random.below(chars.len() + 1);
+                    chars.insert(i, garbage[random.below(garbage.len())]);
+                    lines[at] = chars.into_iter().collect();
+                }
+            }
+        }
+        lines.join("\n")
+    }
+
+    thread_local! {
+        /// The last panic message of the thread, None when it isn't fuzzing
+        static PANIC: RefCell<Option<String>> = const { RefCell::new(None) };
+    }
+
+    static HOOK: Once = Once::new();
+
+    /// The sources that made the assembler panic, with where and why
+    fn panics(sources: Vec<String>) -> Vec<(String, String)> {
+        // The other tests keep the default hook
+        HOOK.call_once(|| {
+            let default = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                let fuzzing = PANIC.with(|panic| {
+                    panic.borrow_mut().as_mut().map(|last| *last = info.to_string()).is_some()
+                });
+                if !fuzzing {
+                    default(info);
+                }
+            }));
+        });
+
+        PANIC.with(|panic| *panic.borrow_mut() = Some(String::new()));
+        let found = sources.into_iter()
+            .filter(|source| {
+                panic::catch_unwind(|| {
+                        if let Ok(assembly) = assemble_str(source, &Options::default()) {
+                            assembly.object_program();
+                        }
+                    })
+                    .is_err()
+            })
+            .map(|source| (source, PANIC.with(|panic| panic.borrow().clone().unwrap_or_default())))
+            .collect();
+        PANIC.with(|panic| *panic.borrow_mut() = None);
+        found
+    }
+
+    fn assert_no_panics(sources: Vec<String>) {
+        let found = panics(sources);
+        if let Some((source, message)) = found.first() {
+            panic!("{} of the programs panicked, the first {}\n{}",
+                   found.len(),
+                   message,
+                   source);
+        }
+    }
+
+    #[test]
+    fn random_programs() {
+        let mut random = Random(0x5EED_CAFE);
+        assert_no_panics((0..1500).map(|_| program(&mut random)).collect());
+    }
+
+    #[test]
+    fn mangled_test_programs() {
+        let mut random = Random(0xA55E_3B1E);
+        let mut originals = fs::read_dir("src/tests")
+            .unwrap()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "asm"))
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .collect::<Vec<String>>();
+        originals.sort();
+        assert!(!originals.is_empty());
+
+        let sources = (0..1500)
+            .map(|i| mangle(&mut random, &originals[i % originals.len()]))
+            .collect();
+        assert_no_panics(sources);
+    }
+
+    #[test]
+    fn sources_that_arent_utf8() {
+        let assemble_bytes = |bytes: Vec<u8>| {
+            let mut file = FileHandler::from_reader("<fuzz>".to_owned(), Cursor::new(bytes));
+            assemble(&mut file).err().unwrap()
+        };
+        let errs = assemble_bytes(b"P START 0\n RSUB\n LDA \xFF\n END\n".to_vec());
+        assert_eq!(errs.len(), 1);
+        assert_eq!((errs[0].code, errs[0].line), (Code::Io, 3));
+
+        let mut random = Random(0xB17E_5EED);
+        let original = fs::read("src/tests/test2.asm").unwrap();
+        for _ in 0..200 {
+            let mut bytes = original.clone();
+            let at = random.below(bytes.len());
+            bytes.insert(at, 0x80 + random.below(0x80) as u8);
+            assert!(assemble_bytes(bytes).iter().any(|e| e.code == Code::Io));
+        }
+    }
+}
@@ -0,0 +1,46 @@
+/// A control section is the part of the program from START or CSECT up
+/// to the next CSECT or END, every section gets its own H/D/R/T/M/E
+/// records so a linking loader can place it anywhere in memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ControlSection {
+    /// The name written in the header record
+    pub name: String,
+    /// The name used by the symbol table, empty for the first section
+    pub csect: String,
+    pub start: u32,
+    pub length: u32,
+    /// EXTDEF symbols with their addresses, in order of appearance
+    pub defines: Vec<(String, u32)>,
+    /// EXTREF symbols, in order of appearance
+    pub refers: Vec<String>,
+}
+
+impl ControlSection {
+    pub fn new(name: &str, csect: &str, start: u32) -> ControlSection {
+        ControlSection {
+            name: name.to_owned(),
+            csect: csect.to_owned(),
+            start,
+            length: 0,
+            defines: Vec::new(),
+            refers: Vec::new(),
+        }
+    }
+
+    /// Sets the length of the section from the address following its last byte
+    pub fn close(&mut self, end: u32) {
+        self.length = end.saturating_sub(self.start);
+    }
+
+    pub fn add_define(&mut self, name: &str) {
+        if !self.defines.iter().any(|(def, _)| def == name) {
+            self.defines.push((name.to_owned(), 0));
+        }
+    }
+
+    pub fn add_refer(&mut self, name: &str) {
+        if !self.refers.iter().any(|refer| refer == name) {
+            self.refers.push(name.to_owned());
+        }
+    }
+}
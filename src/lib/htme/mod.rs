@@ -4,4 +4,7 @@
 
 pub mod record_string;
 pub mod raw_program;
+pub mod control_section;
+pub mod modification;
+pub mod reader;
 pub mod tests;
@@ -0,0 +1,41 @@
+/// A field of the object code the loader has to adjust once it knows
+/// where a control section or an external symbol ends up in memory
+///
+/// M^address^length^+SYMBOL, the symbol is the name of a control
+/// section for relocation, or an EXTREF symbol for linking
+#[derive(Debug, Clone, PartialEq)]
+pub struct Modification {
+    /// The control section the field is in, as named in the symbol table
+    pub csect: String,
+    pub address: u32,
+    /// Length of the field in half bytes, 05 for format 4 and 06 for WORD
+    pub half_bytes: u8,
+    pub negative: bool,
+    pub symbol: String,
+}
+
+impl Modification {
+    pub fn new(csect: &str, address: u32, half_bytes: u8, symbol: &str) -> Modification {
+        Modification {
+            csect: csect.to_owned(),
+            address,
+            half_bytes,
+            negative: false,
+            symbol: symbol.to_owned(),
+        }
+    }
+
+    /// The symbol is subtracted from the field instead of added to it
+    pub fn negated(mut self) -> Modification {
+        self.negative = !self.negative;
+        self
+    }
+
+    pub fn record(&self) -> String {
+        format!("M{:06X}{:02X}{}{}",
+                self.address,
+                self.half_bytes,
+                if self.negative { '-' } else { '+' },
+                self.symbol)
+    }
+}
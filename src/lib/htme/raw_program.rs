@@ -1,9 +1,10 @@
 use std::io::prelude::*;
-use std::fs::File;
-use formats::Format;
+use std::fs::{self, File};
+use std::io;
 use instruction::Instruction;
-use operands::*;
 use htme::record_string::*;
+use htme::control_section::ControlSection;
+use htme::modification::Modification;
 use std::fmt;
 
 pub struct RawProgram {
@@ -12,8 +13,17 @@ pub struct RawProgram {
     pub program_length: u32,
     pub program: Vec<(String, Instruction)>,
     pub first_instruction_address: u32,
+    /// Filled by pass one, empty when the program is built by hand
+    pub sections: Vec<ControlSection>,
+    /// Filled by pass two
+    pub modifications: Vec<Modification>,
 }
 
+/// Symbols per D record, 6 * 12 columns
+const DEFINES_PER_RECORD: usize = 6;
+/// Symbols per R record, 12 * 6 columns
+const REFERS_PER_RECORD: usize = 12;
+
 
 impl RawProgram {
     // pub fn new(program_name: String, starting_address: u32, program_length:u32, program: Vec<(u32, String, Instruction)>, first_instruction_address: u32)->RawProgram{
@@ -33,130 +43,164 @@ impl RawProgram {
     //
     // }
 
-    pub fn end_record(&self) -> String {
-
-        //initing record
-        let record = String::from("E");
+    /// The control sections of the program, a program without CSECTs
+    /// is a single section spanning the whole program
+    pub fn control_sections(&self) -> Vec<ControlSection> {
+        if !self.sections.is_empty() {
+            return self.sections.clone();
+        }
+        let mut section = ControlSection::new(&self.program_name, "", self.starting_address);
+        section.length = self.program_length;
+        vec![section]
+    }
 
-        //getting hexacode s from 1st instruction address with right amount of zeros.
-        let record_width_in_bytes: u8 = 3;
-        let s = string_from_object_code(self.first_instruction_address, record_width_in_bytes);
+    /// The object code and instructions that belong to the given section
+    fn section_program(&self, section: &ControlSection) -> Vec<(String, Instruction)> {
+        self.program
+            .iter()
+            .filter(|(_, instruction)| instruction.csect == section.csect)
+            .cloned()
+            .collect()
+    }
 
-        //returning "E"+Some Hexa
-        return record + s.as_str();
+    /// E^first instruction for the first section, a bare E for the others
+    pub fn end_record(&self, section: &ControlSection) -> String {
+        if section.csect.is_empty() {
+            return String::from("E") + &string_from_object_code(self.first_instruction_address, 3);
+        }
+        String::from("E")
     }
 
+    pub fn text_records(&self, section: &ControlSection) -> String {
+        // Program blocks and ORG put the code out of address order
+        let mut program = self.section_program(section);
+        program.sort_by_key(|(_, instruction)| instruction.locctr);
+        let mut records: Vec<String> = Vec::new();
+        let mut piece: Vec<(String, Instruction)> = Vec::new();
+        let mut piece_end: i32 = 0;
+        let mut piece_len: usize = 0;
+
+        for (code, instruction) in program {
+            let code = padded_object_code(&code);
+            if code.is_empty() {
+                // Reserved memory and directives don't generate object code
+                continue;
+            }
+            let len = code.len() / 2;
+
+            // A record holds contiguous code that fits in a single record
+            if !piece.is_empty() &&
+               (instruction.locctr != piece_end || piece_len + len > MAX_TEXT_BYTES) {
+                records.push(text_record_from_program(&piece));
+                piece.clear();
+                piece_len = 0;
+            }
 
-    //TODO:make this a lot less ugly
-    pub fn text_records(&self) -> String {
-        //intializing prev_address and prev_format
-        let ref program = self.program;
-        let (_, ref first_instruction) = program[0];
-        let first_address = first_instruction.locctr;
-        let mut prev_address = first_address;
-        let mut prev_format = first_instruction.get_format();
-        let mut records = String::from("");
-
-        //counters
-        let mut i = 0;
-        let mut begin = 0;
-        let mut bytes_left = 30;
-
-        //iterating on program: address, code, instruction tuple.
-        for &(_, ref instruction) in program.iter() {
-            //I only care about instruction's format
-            let format = (*instruction).get_format();
-            let address = (*instruction).locctr;
-
-            //terminating conditions for a single T record
-            if address - prev_address > prev_format as i32 || bytes_left < format as u32 {
-                let mut vec = vec![];
-                vec.extend_from_slice(&program[begin..i]);
-                let record = text_record_from_program(&vec);
-                //TODO: make Hamdy handle his own errors
-                if record.to_owned() != "T" {
-                    if begin != 0 {
-                        records.push_str("\n");
-                    }
-                    records.push_str(&record);
+            piece_end = instruction.locctr + len as i32;
+            if len > MAX_TEXT_BYTES {
+                // Long constants are split over as many records as needed
+                let mut address = instruction.locctr as u32;
+                for chunk in code.as_bytes().chunks(MAX_TEXT_BYTES * 2) {
+                    let chunk = String::from_utf8_lossy(chunk);
+                    records.push(text_record(address, &chunk));
+                    address += (chunk.len() / 2) as u32;
                 }
-                begin = i;
-                bytes_left = 30;
+                continue;
             }
-            bytes_left = bytes_left - format as u32;
-            i = i + 1;
-            prev_address = address;
-            prev_format = format;
+
+            piece_len += len;
+            piece.push((code, instruction));
         }
 
-        let mut vec = vec![];
-        vec.extend_from_slice(&program[begin..i]);
-        let record = text_record_from_program(&vec);
-        if begin != 0 {
-            records.push_str("\n");
+        if !piece.is_empty() {
+            records.push(text_record_from_program(&piece));
         }
-        records.push_str(&record);
 
-        return records;
+        records.join("\n")
     }
 
+    /// H^name^start^length
+    pub fn header_record(&self, section: &ControlSection) -> String {
+        format!("H{:<6}{}{}",
+                section.name,
+                string_from_object_code(section.start, 3),
+                string_from_object_code(section.length, 3))
+    }
 
-    pub fn header_record(&self) -> String {
-        let record_type = String::from("H");
-        let mut header = record_type;
-        let ref name = self.program_name;
-
-        header = header + &name;
-
-        //6 columns
-        let record_width_in_bytes: u8 = 3;
-
-        //appending starting address
-        let record = string_from_object_code(self.starting_address, record_width_in_bytes);
-        header = header + &record;
-
-        //appending program length
-        let record = string_from_object_code(self.program_length, record_width_in_bytes);
-        header = header + &record;
-
-        return header;
+    /// D^name^address for the symbols the section exports
+    pub fn define_records(&self, section: &ControlSection) -> String {
+        section.defines
+            .chunks(DEFINES_PER_RECORD)
+            .map(|defines| {
+                defines.iter().fold(String::from("D"), |record, &(ref name, address)| {
+                    format!("{}{:<6}{}", record, name, string_from_object_code(address, 3))
+                })
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
-    pub fn modification_records(&self) -> String {
-        let mut records = String::from("");
+    /// R^name for the symbols the section imports
+    pub fn refer_records(&self, section: &ControlSection) -> String {
+        section.refers
+            .chunks(REFERS_PER_RECORD)
+            .map(|refers| {
+                refers.iter()
+                    .fold(String::from("R"), |record, name| format!("{}{:<6}", record, name))
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
 
-        for &(_, ref instruction) in self.program.iter() {
-            let format = instruction.get_format();
-            let address: u32 = instruction.locctr as u32;
+    pub fn modification_records(&self, section: &ControlSection) -> String {
+        self.modifications
+            .iter()
+            .filter(|modification| modification.csect == section.csect)
+            .map(|modification| modification.record() + "\n")
+            .collect()
+    }
 
-            if format == Format::Four {
-                let operands_vector = instruction.unwrap_operands();
-                if operands_vector[0].opr_type == OperandType::Label {
-                    let record =
-                        String::from("M") + string_from_object_code(address + 1, 3).as_str() +
-                        string_from_object_code(5, 1).as_str() + "\n";
-                    records = records + record.as_str();
-                }
-            }
-        }
-        return records;
+    /// The records of a single control section, H D R T M E
+    pub fn section_records(&self, section: &ControlSection) -> String {
+        let records = vec![self.header_record(section),
+                           self.define_records(section),
+                           self.refer_records(section),
+                           self.text_records(section),
+                           self.modification_records(section).trim_end().to_owned(),
+                           self.end_record(section)];
+        records.into_iter()
+            .filter(|record| !record.is_empty())
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
     pub fn all_records(&self) -> String {
-        return format!("{}\n{}\n{}{}",
-                       self.header_record(),
-                       self.text_records(),
-                       self.modification_records(),
-                       self.end_record());
+        self.control_sections()
+            .iter()
+            .map(|section| self.section_records(section))
+            .collect::<Vec<String>>()
+            .join("\n")
     }
 
 
-    pub fn output_to_file(&self) {
-        let mut file: File = match File::create(self.program_name.clone() + ".objcode") {
-            Ok(file) => file,
-            Err(e) => panic!("{}", e),
-        };
-        write!(file, "{}", self.all_records()).unwrap();
+    /// Writes the records to PROGNAME.objcode
+    pub fn output_to_file(&self) -> io::Result<()> {
+        self.output_to_path(&(self.program_name.clone() + ".objcode"))
+    }
+
+    /// Writes the records to a file, the records are written to a temporary
+    /// file first so an existing object file is only replaced once the new
+    /// one is complete
+    pub fn output_to_path(&self, path: &str) -> io::Result<()> {
+        let path = path.to_owned();
+        let temp_path = path.clone() + ".tmp";
+        let result = File::create(&temp_path)
+            .and_then(|mut file| write!(file, "{}", self.all_records()).and(file.sync_all()))
+            .and_then(|_| fs::rename(&temp_path, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
     }
 }
 
@@ -0,0 +1,515 @@
+/// Reads an object program back into typed records, checking the record
+/// layout, the hex fields, the addresses against the section bounds and
+/// the order of the records (H, D, R, T, M, E for every control section)
+///
+/// The records print the same way the assembler writes them, reading
+/// `RawProgram::all_records` and printing the records gives it back.
+use std::fmt;
+use diagnostic::{Diagnostic, Code};
+use htme::modification::Modification;
+use htme::record_string::{text_record, string_from_object_code, MAX_TEXT_BYTES};
+
+/// SIC/XE has 1 MB of memory
+pub const MEMORY_SIZE: u32 = 0x100000;
+/// Width of the name fields of the H, D, R and M records
+const NAME_WIDTH: usize = 6;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub name: String,
+    pub start: u32,
+    pub length: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Define {
+    pub symbols: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Refer {
+    pub symbols: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Text {
+    pub start: u32,
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct End {
+    /// Only the main section names the first instruction
+    pub entry: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Header(Header),
+    Define(Define),
+    Refer(Refer),
+    Text(Text),
+    Modification(Modification),
+    End(End),
+}
+
+impl Record {
+    /// Records of a section come in this order
+    fn rank(&self) -> u8 {
+        match *self {
+            Record::Header(_) => 0,
+            Record::Define(_) => 1,
+            Record::Refer(_) => 2,
+            Record::Text(_) => 3,
+            Record::Modification(_) => 4,
+            Record::End(_) => 5,
+        }
+    }
+
+    fn kind(&self) -> char {
+        ['H', 'D', 'R', 'T', 'M', 'E'][self.rank() as usize]
+    }
+}
+
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Record::Header(ref header) => {
+                write!(f,
+                       "H{:<6}{}{}",
+                       header.name,
+                       string_from_object_code(header.start, 3),
+                       string_from_object_code(header.length, 3))
+            }
+            Record::Define(ref define) => {
+                write!(f, "D")?;
+                for &(ref name, address) in &define.symbols {
+                    write!(f, "{:<6}{}", name, string_from_object_code(address, 3))?;
+                }
+                Ok(())
+            }
+            Record::Refer(ref refer) => {
+                write!(f, "R")?;
+                for name in &refer.symbols {
+                    write!(f, "{:<6}", name)?;
+                }
+                Ok(())
+            }
+            Record::Text(ref text) => {
+                let code = text.bytes
+                    .iter()
+                    .map(|byte| format!("{:02X}", byte))
+                    .collect::<String>();
+                write!(f, "{}", text_record(text.start, &code))
+            }
+            Record::Modification(ref modification) => write!(f, "{}", modification.record()),
+            Record::End(ref end) => {
+                match end.entry {
+                    Some(entry) => write!(f, "E{}", string_from_object_code(entry, 3)),
+                    None => write!(f, "E"),
+                }
+            }
+        }
+    }
+}
+
+/// The records of a single control section
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectSection {
+    pub name: String,
+    pub start: u32,
+    pub length: u32,
+    /// EXTDEF symbols with their assembled addresses
+    pub defines: Vec<(String, u32)>,
+    pub refers: Vec<String>,
+    pub texts: Vec<Text>,
+    pub modifications: Vec<Modification>,
+    pub entry: Option<u32>,
+    /// Line of the H record
+    pub line: i32,
+}
+
+impl ObjectSection {
+    fn new(header: &Header, line: i32) -> ObjectSection {
+        ObjectSection {
+            name: header.name.clone(),
+            start: header.start,
+            length: header.length,
+            defines: Vec::new(),
+            refers: Vec::new(),
+            texts: Vec::new(),
+            modifications: Vec::new(),
+            entry: None,
+            line,
+        }
+    }
+
+    fn end(&self) -> u32 {
+        self.start + self.length
+    }
+
+    /// Checks a record against the bounds of the section and adds it
+    fn add(&mut self, record: Record) -> Result<(), String> {
+        match record {
+            Record::Header(_) => unreachable!("a header starts a new section"),
+            Record::Define(define) => {
+                for &(ref name, address) in &define.symbols {
+                    if address < self.start || address > self.end() {
+                        return Err(format!("`{}` is defined at {:06X}, outside of section \
+                                            `{}`",
+                                           name,
+                                           address,
+                                           self.name));
+                    }
+                }
+                self.defines.extend(define.symbols);
+            }
+            Record::Refer(refer) => self.refers.extend(refer.symbols),
+            Record::Text(text) => {
+                if text.start < self.start || text.start + text.bytes.len() as u32 > self.end() {
+                    return Err(format!("text record at {:06X} is outside of section `{}`",
+                                       text.start,
+                                       self.name));
+                }
+                self.texts.push(text);
+            }
+            Record::Modification(mut modification) => {
+                let len = (modification.half_bytes as u32).div_ceil(2);
+                if modification.address < self.start ||
+                   modification.address + len > self.end() {
+                    return Err(format!("modification at {:06X} is outside of section `{}`",
+                                       modification.address,
+                                       self.name));
+                }
+                if modification.symbol != self.name &&
+                   !self.refers.contains(&modification.symbol) {
+                    return Err(format!("`{}` isn't the name of the section nor is it in an R \
+                                        record",
+                                       modification.symbol));
+                }
+                modification.csect = self.name.clone();
+                self.modifications.push(modification);
+            }
+            Record::End(end) => {
+                if let Some(entry) = end.entry {
+                    if entry < self.start || entry >= self.end().max(self.start + 1) {
+                        return Err(format!("entry point {:06X} is outside of section `{}`",
+                                           entry,
+                                           self.name));
+                    }
+                }
+                self.entry = end.entry;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a single record, without checking it against the other records
+pub fn parse_record(record: &str) -> Result<Record, String> {
+    if !record.is_ascii() {
+        return Err("records can only contain ASCII characters".to_owned());
+    }
+
+    match record.chars().next() {
+        Some('H') => {
+            exact_length(record, 19, "header")?;
+            let name = name_field(record, 1)?;
+            let start = hex_field(record, 7, 6)?;
+            let length = hex_field(record, 13, 6)?;
+            if start + length > MEMORY_SIZE {
+                return Err(format!("section `{}` doesn't fit in memory", name));
+            }
+            Ok(Record::Header(Header {
+                name,
+                start,
+                length,
+            }))
+        }
+        Some('D') => {
+            if record.len() < 13 || !(record.len() - 1).is_multiple_of(12) {
+                return Err("define record must hold 1 or more name and address pairs"
+                    .to_owned());
+            }
+            let symbols = (1..record.len())
+                .step_by(12)
+                .map(|at| Ok((name_field(record, at)?, hex_field(record, at + 6, 6)?)))
+                .collect::<Result<Vec<(String, u32)>, String>>()?;
+            Ok(Record::Define(Define { symbols }))
+        }
+        Some('R') => {
+            // The last name may come without its padding
+            let record = record.trim_end();
+            if record.len() < 2 {
+                return Err("refer record must hold 1 or more names".to_owned());
+            }
+            let symbols = (1..record.len())
+                .step_by(NAME_WIDTH)
+                .map(|at| {
+                    let name = &record[at..(at + NAME_WIDTH).min(record.len())];
+                    name_field(&format!("{:<6}", name), 0)
+                })
+                .collect::<Result<Vec<String>, String>>()?;
+            Ok(Record::Refer(Refer { symbols }))
+        }
+        Some('T') => {
+            let start = hex_field(record, 1, 6)?;
+            let length = hex_field(record, 7, 2)? as usize;
+            if length == 0 || length > MAX_TEXT_BYTES {
+                return Err(format!("text record holds {} bytes, expected 1 to {}",
+                                   length,
+                                   MAX_TEXT_BYTES));
+            }
+            exact_length(record, 9 + length * 2, "text")?;
+            let bytes = (0..length)
+                .map(|i| hex_field(record, 9 + i * 2, 2).map(|byte| byte as u8))
+                .collect::<Result<Vec<u8>, String>>()?;
+            Ok(Record::Text(Text { start, bytes }))
+        }
+        Some('M') => {
+            if record.len() < 11 || record.len() > 10 + NAME_WIDTH {
+                return Err("modification record must be M, address, length, sign and symbol"
+                    .to_owned());
+            }
+            let address = hex_field(record, 1, 6)?;
+            let half_bytes = hex_field(record, 7, 2)?;
+            if half_bytes == 0 || half_bytes > 6 {
+                return Err(format!("can't modify {} half bytes, expected 1 to 6", half_bytes));
+            }
+            let symbol = name_field(&format!("{:<6}", &record[10..]), 0)?;
+            let modification = Modification::new("", address, half_bytes as u8, &symbol);
+            match &record[9..10] {
+                "+" => Ok(Record::Modification(modification)),
+                "-" => Ok(Record::Modification(modification.negated())),
+                sign => Err(format!("expected + or - in modification record, found `{}`", sign)),
+            }
+        }
+        Some('E') => {
+            if record.len() == 1 {
+                return Ok(Record::End(End { entry: None }));
+            }
+            exact_length(record, 7, "end")?;
+            Ok(Record::End(End { entry: Some(hex_field(record, 1, 6)?) }))
+        }
+        Some(kind) => Err(format!("unknown record type `{}`", kind)),
+        None => Err("empty record".to_owned()),
+    }
+}
+
+/// Reads every record of an object program with its line number
+pub fn read_records(text: &str) -> Result<Vec<(i32, Record)>, Vec<Diagnostic>> {
+    let mut records: Vec<(i32, Record)> = Vec::new();
+    let mut errs: Vec<Diagnostic> = Vec::new();
+
+    for (i, record) in text.lines().enumerate() {
+        let line = i as i32 + 1;
+        let record = record.trim_end_matches('\r');
+        if record.trim().is_empty() {
+            continue;
+        }
+        match parse_record(record) {
+            Ok(record) => records.push((line, record)),
+            Err(e) => errs.push(Diagnostic::error(Code::InvalidRecord, e).at_line(line)),
+        }
+    }
+
+    if errs.is_empty() { Ok(records) } else { Err(errs) }
+}
+
+/// Reads the control sections of an object program, the records of
+/// every section are checked against its header and each other
+pub fn read_sections(text: &str) -> Result<Vec<ObjectSection>, Vec<Diagnostic>> {
+    let mut sections: Vec<ObjectSection> = Vec::new();
+    let mut current: Option<(ObjectSection, u8)> = None;
+    let mut errs: Vec<Diagnostic> = Vec::new();
+
+    for (line, record) in read_records(text)? {
+        let err = |message: String| Diagnostic::error(Code::InvalidRecord, message).at_line(line);
+
+        if let Record::Header(ref header) = record {
+            if let Some((section, _)) = current.take() {
+                errs.push(err(format!("section `{}` has no end record", section.name))
+                    .with_help("every section ends with an E record".to_owned()));
+            }
+            current = Some((ObjectSection::new(header, line), record.rank()));
+            continue;
+        }
+
+        let (section, rank) = match current.as_mut() {
+            Some(&mut (ref mut section, ref mut rank)) => (section, rank),
+            None => {
+                errs.push(err(format!("`{}` record before a header record", record.kind())));
+                continue;
+            }
+        };
+
+        if record.rank() < *rank {
+            errs.push(err(format!("`{}` record is out of order", record.kind()))
+                .with_help("the records of a section come in the order H, D, R, T, M, E"
+                    .to_owned()));
+            continue;
+        }
+        *rank = record.rank();
+
+        let is_end = record.rank() == 5;
+        if let Err(e) = section.add(record) {
+            errs.push(err(e));
+        }
+        if is_end {
+            sections.extend(current.take().map(|(section, _)| section));
+        }
+    }
+
+    if let Some((section, _)) = current {
+        errs.push(Diagnostic::error(Code::InvalidRecord,
+                                    format!("section `{}` has no end record", section.name))
+            .at_line(section.line));
+    }
+
+    if errs.is_empty() { Ok(sections) } else { Err(errs) }
+}
+
+/// The field must hold exactly this many characters
+fn exact_length(record: &str, len: usize, kind: &str) -> Result<(), String> {
+    if record.len() != len {
+        return Err(format!("{} record is {} characters long, expected {}",
+                           kind,
+                           record.len(),
+                           len));
+    }
+    Ok(())
+}
+
+fn name_field(record: &str, at: usize) -> Result<String, String> {
+    let name = record.get(at..at + NAME_WIDTH)
+        .ok_or_else(|| "record is too short".to_owned())?
+        .trim_end();
+    if name.is_empty() || name.contains(' ') {
+        return Err(format!("invalid name `{}`", name));
+    }
+    Ok(name.to_owned())
+}
+
+fn hex_field(record: &str, at: usize, len: usize) -> Result<u32, String> {
+    let field = record.get(at..at + len).ok_or_else(|| "record is too short".to_owned())?;
+    if !field.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("`{}` isn't a hex number", field));
+    }
+    Ok(u32::from_str_radix(field, 16).unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::{assemble, object_program_of};
+    use filehandler::FileHandler;
+
+    fn error(text: &str) -> String {
+        let errs = read_sections(text).unwrap_err();
+        assert_eq!(errs[0].code, Code::InvalidRecord);
+        errs[0].message.clone()
+    }
+
+    #[test]
+    fn typed_records() {
+        assert_eq!(parse_record("HCOPY  000000001033"),
+                   Ok(Record::Header(Header {
+                       name: "COPY".to_owned(),
+                       start: 0,
+                       length: 0x1033,
+                   })));
+        assert_eq!(parse_record("RRDREC WRREC"),
+                   Ok(Record::Refer(Refer {
+                       symbols: vec!["RDREC".to_owned(), "WRREC".to_owned()],
+                   })));
+        assert_eq!(parse_record("T00001D03010003"),
+                   Ok(Record::Text(Text {
+                       start: 0x1D,
+                       bytes: vec![0x01, 0x00, 0x03],
+                   })));
+        assert_eq!(parse_record("M00002806-BUFFER"),
+                   Ok(Record::Modification(Modification::new("", 0x28, 6, "BUFFER").negated())));
+        assert_eq!(parse_record("E"), Ok(Record::End(End { entry: None })));
+    }
+
+    #[test]
+    fn round_trip() {
+        for path in &["src/tests/test2.asm", "src/tests/csect.asm"] {
+            let records = object_program_of(path);
+            let read = read_records(&records)
+                .unwrap()
+                .iter()
+                .map(|(_, record)| record.to_string())
+                .collect::<Vec<String>>();
+            assert_eq!(read.join("\n"), records);
+            assert!(read_sections(&records).is_ok());
+        }
+    }
+
+    #[test]
+    fn written_object_files_are_read_back() {
+        use std::{env, fs};
+
+        for &(path, name) in &[("src/tests/macros.asm", "MACROS"),
+                               ("src/tests/conditional.asm", "CONDS"),
+                               ("src/tests/strings.asm", "STRS")] {
+            let assembly = assemble(&mut FileHandler::open(path.to_owned()).unwrap()).unwrap();
+            let file = env::temp_dir().join(format!("{}-{}.objcode", name, ::std::process::id()));
+            let file = file.to_str().unwrap();
+            assembly.program.output_to_path(file).unwrap();
+            let text = fs::read_to_string(file).unwrap();
+            fs::remove_file(file).unwrap();
+
+            let sections = read_sections(&text).unwrap();
+            assert_eq!(sections[0].name, name);
+        }
+    }
+
+    #[test]
+    fn sections_of_a_program() {
+        let sections = read_sections(&object_program_of("src/tests/csect.asm")).unwrap();
+
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[0].defines[1], ("BUFEND".to_owned(), 0x1033));
+        assert_eq!(sections[1].refers, vec!["BUFFER", "LENGTH", "BUFEND"]);
+        assert_eq!(sections[1].modifications[0].csect, "RDREC");
+        assert_eq!(sections[0].entry, Some(0));
+        assert_eq!(sections[2].entry, None);
+    }
+
+    #[test]
+    fn invalid_fields() {
+        assert_eq!(parse_record("HCOPY  00000000103"),
+                   Err("header record is 18 characters long, expected 19".to_owned()));
+        assert_eq!(parse_record("T0000G103010003"), Err("`0000G1` isn't a hex number".to_owned()));
+        assert_eq!(parse_record("T00001D04010003"),
+                   Err("text record is 15 characters long, expected 17".to_owned()));
+        assert_eq!(parse_record("M00000405*RDREC"),
+                   Err("expected + or - in modification record, found `*`".to_owned()));
+        assert_eq!(parse_record("HCOPY  0FFFFF000010"),
+                   Err("section `COPY` doesn't fit in memory".to_owned()));
+        assert!(parse_record("X").is_err());
+    }
+
+    #[test]
+    fn records_are_checked_against_their_section() {
+        assert_eq!(error("HCOPY  000000000010\nT00000E03010003\nE"),
+                   "text record at 00000E is outside of section `COPY`");
+        assert_eq!(error("HCOPY  000000000010\nM00000405+RDREC\nE"),
+                   "`RDREC` isn't the name of the section nor is it in an R record");
+        assert_eq!(error("HCOPY  000000000010\nE000020"),
+                   "entry point 000020 is outside of section `COPY`");
+    }
+
+    #[test]
+    fn records_are_ordered() {
+        assert_eq!(error("HCOPY  000000000010\nT00000003010003\nRRDREC\nE"),
+                   "`R` record is out of order");
+        assert_eq!(error("T00000003010003\n"), "`T` record before a header record");
+        assert_eq!(error("HCOPY  000000000010\nHRDREC 000000000010\nE"),
+                   "section `COPY` has no end record");
+
+        let errs = read_sections("HCOPY  000000000010\n").unwrap_err();
+        assert_eq!(errs[0].line, 1);
+    }
+}
@@ -2,33 +2,42 @@ use instruction::Instruction;
 //TODO: decide if I need to move these?
 
 
+/// The most bytes of object code a single T record holds
+pub const MAX_TEXT_BYTES: usize = 30;
+
 /*
- * returns string containing all records from program if all code and format pair make sense.
+ * returns the T record holding the object code of a contiguous piece of the program
  */
 pub fn text_record_from_program(program: &Vec<(String, Instruction)>) -> String {
+    let start = program.first().map_or(0, |(_, instruction)| instruction.locctr as u32);
+    let code = program.iter()
+        .map(|(code, _)| padded_object_code(code))
+        .collect::<Vec<String>>()
+        .join("");
+    text_record(start, &code)
+}
 
-    //counter to know where error happened
-    let mut i = 0;
-    let record_type = String::from("T");
-    //empty record we keep appending to
-    let mut record = String::from("");
-
-    //iterate on program (address, code, instruction tuple)
-    for &(ref code, _) in program.iter() {
-        // let address = instruction.locctr;
-        //push string onto record
-        record.push_str(&code);
+/*
+ * returns T^start^length^code, code is at most MAX_TEXT_BYTES long
+ */
+pub fn text_record(start: u32, code: &str) -> String {
+    format!("T{}{}{}",
+            string_from_object_code(start, 3),
+            string_from_object_code((code.len() / 2) as u32, 1),
+            code)
+}
 
-        //next
-        i = i + 1;
+/*
+ * returns the object code padded to a whole number of bytes, ex. BYTE X'F' -> 0F
+ */
+pub fn padded_object_code(code: &str) -> String {
+    if code.len() % 2 == 1 {
+        format!("0{}", code)
+    } else {
+        code.to_owned()
     }
-
-    //if we're here, everything went well and record is returned
-    return record_type + &record;
 }
 
-
-
 /*
  * returns string from a format-valid object-code.
  */
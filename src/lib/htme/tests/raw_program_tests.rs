@@ -3,8 +3,11 @@
 mod raw_program_tests {
     use htme::raw_program::*;
     use htme::record_string::*;
+    use htme::modification::Modification;
     use formats::Format;
     use instruction::*;
+    use instruction_set::Opcode;
+    use statement::Statement;
     use operands::*;
     use unit_or_pair::UnitOrPair;
 
@@ -12,12 +15,12 @@ mod raw_program_tests {
     #[test]
     fn test_records_from_raw_program() {
 
-        let mut instr1 = Instruction::new_simple("some mnemonic".to_string());
+        let mut instr1 = Instruction::new_simple(Statement::Instruction(Opcode::Fix));
         let mut instr2 = instr1.clone();
         let operand = AsmOperand::new(OperandType::Label, Value::Label("el_label".to_string()));
 
         let mut instr3 = Instruction::new(String::new(),
-                                          "some mnemonic".to_string(),
+                                          Statement::Instruction(Opcode::Lda),
                                           UnitOrPair::Unit(operand));
 
         let mut instr4 = instr1.clone();
@@ -49,24 +52,96 @@ mod raw_program_tests {
             program_length: 0x102A,
             program: valid_program,
             first_instruction_address: 0x1000,
+            sections: Vec::new(),
+            modifications: vec![Modification::new("", 0x1008, 5, "COPYTEST")],
         };
+        let section = &raw_program.control_sections()[0];
 
-        let end_record = raw_program.end_record();
+        let end_record = raw_program.end_record(section);
         assert_eq!(end_record, String::from("E001000"));
 
-        let header_record = raw_program.header_record();
+        let header_record = raw_program.header_record(section);
         assert_eq!(header_record, String::from("HCOPYTEST00100000102A"));
 
-        let text_records = raw_program.text_records();
-        assert_eq!(text_records, "TC400F3\nT0003F4D3\nT00430043");
+        let text_records = raw_program.text_records(section);
+        assert_eq!(text_records,
+                   "T00100003C400F3\nT001007040003F4D3\nT00100C0400430043");
 
-        let modification_records = raw_program.modification_records();
-        assert_eq!(modification_records, "M00100805\n");
+        let modification_records = raw_program.modification_records(section);
+        assert_eq!(modification_records, "M00100805+COPYTEST\n");
 
         let all_records = raw_program.all_records();
         assert_eq!(all_records,
-                   "HCOPYTEST00100000102A\nTC400F3\nT0003F4D3\nT00430043\nM00100805\nE001000");
+                   "HCOPYTEST00100000102A\nT00100003C400F3\nT001007040003F4D3\n\
+                    T00100C0400430043\nM00100805+COPYTEST\nE001000");
 
-        raw_program.output_to_file();
+        raw_program.output_to_file().unwrap();
+    }
+
+    #[test]
+    fn test_records_of_control_sections() {
+        use filehandler::FileHandler;
+        use pass_one::pass_one::pass_one;
+        use pass_two::translator::pass_two;
+        use session::Session;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/csect.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let (_, mut prog) = pass_one(&mut session, prog).unwrap();
+        assert!(pass_two(&mut session, &mut prog).is_empty());
+
+        let sections = prog.control_sections();
+        assert_eq!(sections.len(), 3);
+        assert_eq!(prog.define_records(&sections[0]),
+                   "DBUFFER000033BUFEND001033LENGTH00002D");
+        assert_eq!(prog.refer_records(&sections[1]), "RBUFFERLENGTHBUFEND");
+
+        assert_eq!(prog.all_records(),
+                   "HCOPY  000000001033\n\
+                    DBUFFER000033BUFEND001033LENGTH00002D\n\
+                    RRDREC WRREC \n\
+                    T0000001D1720274B1000000320232900003320074B1000003F2FEC0320160F2016\n\
+                    T00001D0D0100030F200A4B1000003E2000\n\
+                    T00003003454F46\n\
+                    M00000405+RDREC\n\
+                    M00001105+WRREC\n\
+                    M00002405+WRREC\n\
+                    E000000\n\
+                    HRDREC 00000000002B\n\
+                    RBUFFERLENGTHBUFEND\n\
+                    T0000001DB410B400B44077201FE3201B332FFADB2015A00433200957900000B850\n\
+                    T00001D0E3B2FE9131000004F0000F1000000\n\
+                    M00001805+BUFFER\n\
+                    M00002105+LENGTH\n\
+                    M00002806+BUFEND\n\
+                    M00002806-BUFFER\n\
+                    E\n\
+                    HWRREC 00000000001C\n\
+                    RLENGTHBUFFER\n\
+                    T0000001CB41077100000E32012332FFA53900000DF2008B8503B2FEE4F000005\n\
+                    M00000305+LENGTH\n\
+                    M00000D05+BUFFER\n\
+                    E");
+    }
+
+    #[test]
+    fn test_long_constants_span_records() {
+        let mut instr = Instruction::new_simple(Statement::Byte(Vec::new()));
+        instr.locctr = 0x10;
+        let program = RawProgram {
+            program_name: String::from("LONG"),
+            starting_address: 0,
+            program_length: 0x33,
+            program: vec![("AB".repeat(35), instr)],
+            first_instruction_address: 0,
+            sections: Vec::new(),
+            modifications: Vec::new(),
+        };
+        let section = &program.control_sections()[0];
+        assert_eq!(program.text_records(section),
+                   format!("T0000101E{}\nT00002E05{}", "AB".repeat(30), "AB".repeat(5)));
     }
 }
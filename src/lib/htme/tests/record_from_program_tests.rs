@@ -4,11 +4,13 @@ mod record_from_program_tests {
     use htme::record_string::*;
     use formats::Format;
     use instruction::Instruction;
+    use instruction_set::Opcode;
+    use statement::Statement;
 
     #[test]
     fn test_text_record_from_program() {
 
-        let mut instr1 = Instruction::new_simple("some mnemonic".to_string());
+        let mut instr1 = Instruction::new_simple(Statement::Instruction(Opcode::Fix));
         let mut instr2 = instr1.clone();
         let mut instr3 = instr1.clone();
         instr1.set_format(Format::One);
@@ -22,6 +24,6 @@ mod record_from_program_tests {
                                  (string_from_object_code(0x3F4D3, 4), instr3)];
 
         let record = text_record_from_program(&valid_program);
-        assert_eq!(record, String::from("TC400F30003F4D3"));
+        assert_eq!(record, String::from("T00100007C400F30003F4D3"));
     }
 }
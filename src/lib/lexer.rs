@@ -0,0 +1,355 @@
+/// Splits a source line into its words and its comment, ex.
+///
+///  EOF      BYTE    C'END. BYE'   . the last record
+///
+/// is the words `EOF`, `BYTE` and `C'END. BYE'` and the comment. A quoted
+/// text may hold spaces and periods, a quote in it is doubled, ex. C'IT''S'.
+/// A `.` that isn't quoted starts the comment, and spaces after a comma don't
+/// end a word, so `BUFFER, X` is one operand.
+///
+/// Sources punched for cards put the fields in fixed columns instead, the
+/// label in 1-8, the mnemonic in 10-15, the operand in 17-35 and the comment
+/// from 36 on, see `tokenize_fixed`.
+use diagnostic::Span;
+
+/// How the fields of the source lines are told apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SourceFormat {
+    /// Words separated by spaces
+    Free,
+    /// Card columns, what follows the operand is a comment
+    Fixed,
+    /// Card columns for the lines laid out in them, words for the others
+    #[default]
+    Auto,
+}
+
+/// The card columns where the mnemonic, the operand and the comment start
+const MNEMONIC_COLUMN: usize = 10;
+const OPERAND_COLUMN: usize = 17;
+const COMMENT_COLUMN: usize = 36;
+/// The tab stops of a card, every 8 columns
+const TAB_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Word,
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    /// The text without the spaces after the commas
+    pub text: String,
+    /// Byte offsets in the line
+    pub span: Span,
+    /// 1-based column of the first character, a tab counts as one
+    pub column: usize,
+}
+
+/// The tokens of a line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub words: Vec<Token>,
+    pub comment: Option<Token>,
+    /// Where the code ends, the start of the comment or the end of the line
+    pub code_end: usize,
+    /// Whether the first word is a label, only known in card columns
+    pub labeled: Option<bool>,
+}
+
+impl Line {
+    /// A blank line or a line with only a comment
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+}
+
+pub fn tokenize(line: &str) -> Line {
+    scan(line, false)
+}
+
+/// Splits a line in the given format
+pub fn tokenize_as(line: &str, format: SourceFormat) -> Line {
+    match format {
+        SourceFormat::Free => tokenize(line),
+        SourceFormat::Fixed => tokenize_fixed(line),
+        SourceFormat::Auto => {
+            let fixed = tokenize_fixed(line);
+            if fits_card(line, &fixed) { fixed } else { tokenize(line) }
+        }
+    }
+}
+
+/// Splits a line punched in card columns, a word in column 1 is the label,
+/// the next word the mnemonic and the one after it the operand if it starts
+/// before the comment column. The rest of the line is a comment, with or
+/// without a `.`, ex. the object code printed next to the statements.
+/// Spaces in parentheses don't end the operand, ex. `IF (&I LT 5)`
+pub fn tokenize_fixed(line: &str) -> Line {
+    let mut line_of_words = scan(line, true);
+    let labeled = line_of_words.words.first().is_some_and(|word| word.span.start == 0);
+    let mut fields = if labeled { 2 } else { 1 };
+    if let Some(operand) = line_of_words.words.get(fields) {
+        if card_column(line, operand.span.start) < COMMENT_COLUMN {
+            fields += 1;
+        }
+    }
+
+    if line_of_words.words.len() > fields {
+        let start = line_of_words.words[fields].span.start;
+        let column = line_of_words.words[fields].column;
+        line_of_words.words.truncate(fields);
+        line_of_words.comment = Some(Token {
+            kind: TokenKind::Comment,
+            text: line[start..].trim_end().to_owned(),
+            span: Span::new(start, line.trim_end().len()),
+            column,
+        });
+        line_of_words.code_end = start;
+    }
+    line_of_words.labeled = Some(labeled);
+    line_of_words
+}
+
+/// Whether the fields of a line read in card columns are where a card puts
+/// them, so reading it in words could only go wrong
+fn fits_card(text: &str, line: &Line) -> bool {
+    let column = |word: &Token| card_column(text, word.span.start);
+    let mut words = line.words.iter();
+    if line.labeled == Some(true) {
+        let label = words.next().unwrap();
+        if label.text.chars().count() >= MNEMONIC_COLUMN - 1 {
+            return false;
+        }
+    }
+    // A lone word could be a label or a mnemonic
+    let mnemonic = match words.next() {
+        Some(mnemonic) => mnemonic,
+        None => return false,
+    };
+    if column(mnemonic) < MNEMONIC_COLUMN || column(mnemonic) >= OPERAND_COLUMN - 1 {
+        return false;
+    }
+    if let Some(operand) = words.next() {
+        if column(operand) < OPERAND_COLUMN || column(operand) > OPERAND_COLUMN + 1 {
+            return false;
+        }
+    }
+    // A comment without a `.` starts in its own column
+    match line.comment {
+        Some(ref comment) if !comment.text.starts_with('.') => {
+            column(comment) >= COMMENT_COLUMN
+        }
+        _ => true,
+    }
+}
+
+/// The 1-based column of a byte offset on a card, a tab moves to the next
+/// tab stop
+fn card_column(line: &str, offset: usize) -> usize {
+    line[..offset].chars().fold(1, |column, c| {
+        if c == '\t' {
+            (column - 1) / TAB_WIDTH * TAB_WIDTH + TAB_WIDTH + 1
+        } else {
+            column + 1
+        }
+    })
+}
+
+/// The words and the comment of a line, spaces in parentheses don't end a
+/// word when grouped
+fn scan(line: &str, grouped: bool) -> Line {
+    let mut words: Vec<Token> = Vec::new();
+    let mut chars = line.char_indices().enumerate().peekable();
+
+    while let Some(&(column, (start, c))) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c == '.' {
+            let comment = Token {
+                kind: TokenKind::Comment,
+                text: line[start..].trim_end().to_owned(),
+                span: Span::new(start, line.trim_end().len()),
+                column: column + 1,
+            };
+            return Line {
+                words,
+                comment: Some(comment),
+                code_end: start,
+                labeled: None,
+            };
+        }
+
+        // A word, up to a space or a comment that isn't quoted
+        let mut quoted = false;
+        let mut depth = 0;
+        let mut end = start;
+        while let Some(&(_, (i, c))) = chars.peek() {
+            if !quoted && (c.is_whitespace() && depth == 0 || c == '.') {
+                break;
+            }
+            match c {
+                '\'' => quoted = !quoted,
+                '(' if grouped && !quoted => depth += 1,
+                ')' if grouped && !quoted && depth > 0 => depth -= 1,
+                _ => (),
+            }
+            end = i + c.len_utf8();
+            chars.next();
+        }
+
+        let span = Span::new(start, end);
+        match words.last_mut() {
+            Some(last) if last.text.ends_with(',') => {
+                last.text.push_str(&line[start..end]);
+                last.span = last.span.to(span);
+            }
+            _ => {
+                words.push(Token {
+                    kind: TokenKind::Word,
+                    text: line[start..end].to_owned(),
+                    span,
+                    column: column + 1,
+                })
+            }
+        }
+    }
+
+    Line {
+        words,
+        comment: None,
+        code_end: line.len(),
+        labeled: None,
+    }
+}
+
+/// The text in uppercase but for the quoted parts, ex. `c'Eof',x'f1'` is `C'Eof',X'F1'`
+pub fn uppercase_unquoted(text: &str) -> String {
+    fold_constants(text, true)
+}
+
+/// Only the `C'..'`/`X'..'` constants folded, ex. `buf,x'f1'` is `buf,X'F1'`
+pub fn uppercase_constants(text: &str) -> String {
+    fold_constants(text, false)
+}
+
+/// The hex digits of `X'..'` are always uppercased, the texts of `C'..'` never
+fn fold_constants(text: &str, symbols: bool) -> String {
+    let chars = text.chars().collect::<Vec<char>>();
+    let mut quoted = false;
+    let mut hex = false;
+    let mut folded = String::with_capacity(text.len());
+    for (i, &c) in chars.iter().enumerate() {
+        if c == '\'' {
+            hex = !quoted && i > 0 && chars[i - 1].eq_ignore_ascii_case(&'X');
+            quoted = !quoted;
+            folded.push(c);
+        } else if quoted {
+            folded.push(if hex { c.to_ascii_uppercase() } else { c });
+        } else if symbols || chars.get(i + 1) == Some(&'\'') {
+            folded.push(c.to_ascii_uppercase());
+        } else {
+            folded.push(c);
+        }
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str) -> Vec<String> {
+        tokenize(line).words.into_iter().map(|word| word.text).collect()
+    }
+
+    #[test]
+    fn quoted_texts() {
+        assert_eq!(words("MSG      BYTE    C'HELLO WORLD'"),
+                   vec!["MSG", "BYTE", "C'HELLO WORLD'"]);
+        assert_eq!(words(" BYTE C'A.B' . a period"), vec!["BYTE", "C'A.B'"]);
+        assert_eq!(words(" BYTE C'IT''S OK'"), vec!["BYTE", "C'IT''S OK'"]);
+        assert_eq!(words(" LDA =C' . '"), vec!["LDA", "=C' . '"]);
+    }
+
+    #[test]
+    fn comments() {
+        let line = tokenize("RDREC\tLDX\tZERO\t. CLEAR LOOP COUNTER  ");
+        let comment = line.comment.unwrap();
+        assert_eq!(comment.kind, TokenKind::Comment);
+        assert_eq!(comment.text, ". CLEAR LOOP COUNTER");
+        assert_eq!((comment.span, comment.column), (Span::new(15, 35), 16));
+        assert_eq!(line.code_end, 15);
+
+        assert!(tokenize("   . a whole line").is_empty());
+        assert!(tokenize(".").is_empty());
+        assert!(tokenize("").is_empty());
+        assert_eq!(words(" RSUB.no space"), vec!["RSUB"]);
+    }
+
+    #[test]
+    fn uppercase() {
+        assert_eq!(uppercase_unquoted("buffer,x"), "BUFFER,X");
+        assert_eq!(uppercase_unquoted("=c'Eof'"), "=C'Eof'");
+        assert_eq!(uppercase_unquoted("c'it''s'"), "C'it''s'");
+        assert_eq!(uppercase_unquoted("=x'ab'"), "=X'AB'");
+        assert_eq!(uppercase_constants("buf,x'0f'"), "buf,X'0F'");
+        assert_eq!(uppercase_constants("=c'Eof'"), "=C'Eof'");
+    }
+
+    #[test]
+    fn card_columns() {
+        let fixed = |line: &str| {
+            let line = tokenize_fixed(line);
+            let words = line.words.into_iter().map(|word| word.text).collect::<Vec<_>>();
+            (words, line.comment.map(|comment| comment.text), line.labeled)
+        };
+        assert_eq!(fixed("FIRST    STL     RETADR            17202D"),
+                   (vec!["FIRST".to_owned(), "STL".to_owned(), "RETADR".to_owned()],
+                    Some("17202D".to_owned()),
+                    Some(true)));
+        assert_eq!(fixed("         RSUB                      4F0000"),
+                   (vec!["RSUB".to_owned()], Some("4F0000".to_owned()), Some(false)));
+        assert_eq!(fixed("RSUB     RSUB"),
+                   (vec!["RSUB".to_owned(), "RSUB".to_owned()], None, Some(true)));
+        assert_eq!(fixed("         WHILE   (&I LE 5)  . until 5").0,
+                   vec!["WHILE", "(&I LE 5)"]);
+        assert_eq!(fixed("LOOP\tSTCH\tBUFFER, X\tSAVE IT").0,
+                   vec!["LOOP", "STCH", "BUFFER,X"]);
+    }
+
+    #[test]
+    fn format_detection() {
+        let auto = |line: &str| tokenize_as(line, SourceFormat::Auto).labeled;
+        assert_eq!(auto("CLOOP    +JSUB   RDREC             4B101036"), Some(true));
+        assert_eq!(auto("         COMP    #0"), Some(false));
+        // The mnemonic isn't in column 10 or the comment isn't in column 36
+        assert_eq!(auto("LOOP TD INPUT"), None);
+        assert_eq!(auto("    LDX    #0"), None);
+        assert_eq!(auto("         STCH    BUFFER X"), None);
+        assert_eq!(auto("RSUB"), None);
+        assert_eq!(words("         STCH    BUFFER X"), vec!["STCH", "BUFFER", "X"]);
+    }
+
+    #[test]
+    fn positions() {
+        let line = tokenize("LOOP  STCH  BUFFER, X");
+        let found = line.words
+            .iter()
+            .map(|word| (word.text.as_str(), word.span, word.column))
+            .collect::<Vec<_>>();
+        assert_eq!(found,
+                   vec![("LOOP", Span::new(0, 4), 1),
+                        ("STCH", Span::new(6, 10), 7),
+                        ("BUFFER,X", Span::new(12, 21), 13)]);
+        assert_eq!(line.code_end, 21);
+
+        // Columns count characters, spans count bytes
+        let word = &tokenize(" BYTE C'É' X").words[2];
+        assert_eq!((word.span, word.column), (Span::new(12, 13), 12));
+    }
+}
@@ -4,9 +4,9 @@
 #[macro_use]
 extern crate lazy_static;
 extern crate regex;
+extern crate term;
 #[macro_use]
 extern crate log;
-extern crate parking_lot;
 
 use std::fmt::UpperHex;
 use std::marker::Sized;
@@ -16,12 +16,27 @@ pub mod pass_one;
 pub mod basic_types;
 pub mod pass_two;
 pub mod filehandler;
+pub mod lexer;
+pub mod macros;
+pub mod conditional;
 pub mod htme;
 pub mod semantics_validator;
 pub mod operand_parsing;
+pub mod expression;
+pub mod diagnostic;
+pub mod session;
+pub mod listing;
+pub mod assembler;
+pub mod linker;
+pub mod simulator;
+pub mod disassembler;
+mod fuzz_tests;
 // Re-exports
 pub use htme::record_string::string_from_object_code;
 pub use htme::raw_program::RawProgram;
+pub use session::Session;
+pub use diagnostic::Diagnostic;
+pub use assembler::{assemble_str, Assembly, Options};
 
 // Re-export sub modules, to make imports neater
 pub use basic_types::instruction;
@@ -30,6 +45,7 @@ pub use basic_types::operands;
 pub use basic_types::flags;
 pub use basic_types::register;
 pub use basic_types::instruction_set;
+pub use basic_types::statement;
 pub use basic_types::unit_or_pair;
 pub use basic_types::literal_table;
 pub use basic_types::literal;
@@ -60,7 +76,7 @@ pub fn is_literal(op: &str) -> bool {
     return op.starts_with("=") && is_ascii_or_word_operand(op.trim_left_matches("="));
 }
 
-/// An ascii operand is on the form  (C|X)'...'
+/// An ascii operand is on the form  (C|X)'...', a quote in C'...' is doubled
 pub fn is_ascii_or_word_operand(op: &str) -> bool {
     return CHAR_OPERAND_STREAM.is_match(&op) || HEX_OPERAND_STREAM.is_match(&op);
 }
@@ -76,7 +92,7 @@ pub fn is_hex(op: &str) -> bool {
 }
 
 pub fn is_expression(op: &str) -> bool {
-    return EXPRESSION.is_match(&op);
+    expression::parse(op).is_ok()
 }
 
 /// Removes the container of a WORD/BYTE oeprand, the prefix, the '
@@ -88,15 +104,16 @@ fn remove_literal_container(byte_operand: &mut String) {
     byte_operand.pop();
 }
 
+/// The characters of a C'...' constant, ex. C'IT''S' -> IT'S
+fn char_constant(operand: &str) -> String {
+    operand[2..operand.len() - 1].replace("''", "'")
+}
+
 lazy_static!{
     // Regex reference: http://kbknapp.github.io/doapi-rs/docs/regex/index.html
-    static ref CHAR_OPERAND_STREAM:Regex = Regex::new(r"^(C|c)'[[:alnum:]]+'$").unwrap();
+    static ref CHAR_OPERAND_STREAM:Regex = Regex::new(r"^(C|c)'([ -&(-~]|'')+'$").unwrap();
     static ref HEX_OPERAND_STREAM:Regex = Regex::new(r"^(X|x)'[[:xdigit:]]+'$").unwrap();
     static ref DECIMAL_STREAM:Regex = Regex::new(r"^-?[[:digit:]]+$").unwrap();
     static ref HEX_STREAM:Regex = Regex::new(r"^[[:xdigit:]]+$").unwrap();
     static ref LABEL_STREAM:Regex = Regex::new(r"^[a-zA-Z_][a-zA-Z_0-9]*$").unwrap();
-    static ref COMMENT_REGEX:Regex = Regex::new(r"\.(\s|.)+").unwrap();
-    static ref COMMA_WHITESPACE_REGEX:Regex = Regex::new(r",\s+").unwrap();
-    static ref SPLIT_SOURCE_LINE_SPLIT_REGEX:Regex = Regex::new(r"(\s+|\n+|\t+)").unwrap();
-    pub static ref EXPRESSION:Regex = Regex::new(r"^-?([a-zA-Z_0-9]+)(?:(?:\+|-)([a-zA-Z_0-9]+))(?:(?:\+|-)([a-zA-Z_0-9]+))?(?:(?:\+|-)([a-zA-Z_0-9]+))?$").unwrap();
 }
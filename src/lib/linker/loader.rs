@@ -0,0 +1,384 @@
+/// Linking loader, combines the control sections of several object files
+/// into a single absolute program the way the textbook loader does
+///
+/// Pass one assigns every control section a load address, starting at
+/// PROGADDR, and builds the external symbol table (ESTAB) from the H and
+/// D records. Pass two copies the T records into memory and applies the
+/// M records using the addresses in ESTAB.
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use diagnostic::{Diagnostic, Code};
+use htme::record_string::{text_record, string_from_object_code, MAX_TEXT_BYTES};
+use htme::reader::{read_sections, ObjectSection};
+
+/// A control section as placed in memory
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadedSection {
+    pub name: String,
+    pub address: u32,
+    pub length: u32,
+    /// The EXTDEF symbols of the section with their load addresses
+    pub symbols: Vec<(String, u32)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LinkedProgram {
+    pub name: String,
+    /// PROGADDR, where the first section is loaded
+    pub start: u32,
+    pub entry: u32,
+    pub sections: Vec<LoadedSection>,
+    memory: Vec<u8>,
+    /// Bytes written by T records, reserved memory is left out of the output
+    loaded: Vec<bool>,
+}
+
+/// Where an external symbol comes from, used to point at the first
+/// definition of a duplicate symbol
+struct Definition {
+    address: u32,
+    file: String,
+    line: i32,
+}
+
+/// Links the given object files, each one is a (file name, records) pair
+pub fn link(objects: &[(String, String)], progaddr: u32) -> Result<LinkedProgram, Vec<Diagnostic>> {
+    let mut errs: Vec<Diagnostic> = Vec::new();
+    let mut files: Vec<(&str, ObjectSection)> = Vec::new();
+
+    for (file, text) in objects {
+        match read_sections(text) {
+            Ok(sections) => {
+                files.extend(sections.into_iter().map(|section| (file.as_str(), section)))
+            }
+            Err(e) => errs.extend(e.into_iter().map(|e| e.in_file(file))),
+        }
+    }
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+
+    // Pass one, assign addresses and build ESTAB
+    let mut estab: HashMap<String, Definition> = HashMap::new();
+    let mut sections: Vec<LoadedSection> = Vec::new();
+    let mut csaddr = progaddr;
+
+    for &(file, ref section) in &files {
+        let mut loaded = LoadedSection {
+            name: section.name.clone(),
+            address: csaddr,
+            length: section.length,
+            symbols: Vec::new(),
+        };
+
+        let mut names = vec![(section.name.clone(), csaddr)];
+        names.extend(section.defines
+            .iter()
+            .map(|&(ref name, address)| (name.clone(), relocate(address, section, csaddr))));
+
+        for (i, (name, address)) in names.into_iter().enumerate() {
+            if let Some(first) = estab.get(&name) {
+                errs.push(Diagnostic::error(Code::DuplicateExternal,
+                                            format!("external symbol `{}` is defined more than \
+                                                     once",
+                                                    name))
+                    .in_file(file)
+                    .at_line(section.line)
+                    .with_note(format!("first defined in `{}` at line {}",
+                                       first.file,
+                                       first.line)));
+                continue;
+            }
+            estab.insert(name.clone(),
+                         Definition {
+                             address,
+                             file: file.to_owned(),
+                             line: section.line,
+                         });
+            if i > 0 {
+                loaded.symbols.push((name, address));
+            }
+        }
+
+        sections.push(loaded);
+        csaddr = csaddr.wrapping_add(section.length);
+    }
+
+    // Pass two, load the text and apply the modifications
+    let size = (csaddr - progaddr) as usize;
+    let mut memory = vec![0u8; size];
+    let mut written = vec![false; size];
+    let mut entry = None;
+
+    for (&(file, ref section), loaded) in files.iter().zip(&sections) {
+        let err = |code: Code, message: String| {
+            Diagnostic::error(code, message).in_file(file).at_line(section.line)
+        };
+        let offset = |address: u32, len: usize| {
+            let at = relocate(address, section, loaded.address).wrapping_sub(progaddr) as usize;
+            let end = at.checked_add(len)?;
+            let section_end = (loaded.address - progaddr + loaded.length) as usize;
+            if address >= section.start && end <= section_end {
+                Some(at)
+            } else {
+                None
+            }
+        };
+
+        for refer in &section.refers {
+            if !estab.contains_key(refer) {
+                errs.push(err(Code::UndefinedExternal,
+                              format!("undefined external symbol `{}` referenced by `{}`",
+                                      refer,
+                                      section.name)));
+            }
+        }
+
+        for text in &section.texts {
+            match offset(text.start, text.bytes.len()) {
+                Some(at) => {
+                    memory[at..at + text.bytes.len()].copy_from_slice(&text.bytes);
+                    written[at..at + text.bytes.len()].iter_mut().for_each(|byte| *byte = true);
+                }
+                None => {
+                    errs.push(err(Code::InvalidRecord,
+                                  format!("text record at {:06X} is outside of section `{}`",
+                                          text.start,
+                                          section.name)))
+                }
+            }
+        }
+
+        for modification in &section.modifications {
+            // A section relocates its own addresses by how far it was moved
+            let value = if modification.symbol == section.name {
+                loaded.address.wrapping_sub(section.start)
+            } else {
+                match estab.get(&modification.symbol) {
+                    Some(definition) => definition.address,
+                    // Already reported for the R record
+                    None if section.refers.contains(&modification.symbol) => continue,
+                    None => {
+                        errs.push(err(Code::UndefinedExternal,
+                                      format!("undefined external symbol `{}` in modification \
+                                               of {:06X}",
+                                              modification.symbol,
+                                              modification.address)));
+                        continue;
+                    }
+                }
+            };
+
+            let len = (modification.half_bytes as usize).div_ceil(2);
+            match offset(modification.address, len) {
+                Some(at) if modification.half_bytes > 0 && modification.half_bytes <= 8 => {
+                    modify(&mut memory[at..at + len],
+                           modification.half_bytes,
+                           value,
+                           modification.negative)
+                }
+                _ => {
+                    errs.push(err(Code::InvalidRecord,
+                                  format!("modification of {:06X} is outside of section `{}`",
+                                          modification.address,
+                                          section.name)))
+                }
+            }
+        }
+
+        if entry.is_none() {
+            entry = section.entry.map(|address| relocate(address, section, loaded.address));
+        }
+    }
+
+    if !errs.is_empty() {
+        return Err(errs);
+    }
+
+    Ok(LinkedProgram {
+        name: sections.first().map_or(String::new(), |section| section.name.clone()),
+        start: progaddr,
+        entry: entry.unwrap_or(progaddr),
+        sections,
+        memory,
+        loaded: written,
+    })
+}
+
+/// The load address of an address assembled relative to the section start
+fn relocate(address: u32, section: &ObjectSection, load_address: u32) -> u32 {
+    address.wrapping_sub(section.start).wrapping_add(load_address)
+}
+
+/// Adds or subtracts a value from the rightmost half bytes of a field,
+/// an odd length leaves the high half of the first byte untouched
+fn modify(field: &mut [u8], half_bytes: u8, value: u32, negative: bool) {
+    let old = field.iter().fold(0u64, |old, &byte| old << 8 | byte as u64);
+    let mask = (1u64 << (half_bytes as u32 * 4)) - 1;
+    let value = value as u64;
+    let new = if negative {
+        (old & mask).wrapping_sub(value)
+    } else {
+        (old & mask).wrapping_add(value)
+    };
+    let new = old & !mask | new & mask;
+
+    for (i, byte) in field.iter_mut().rev().enumerate() {
+        *byte = (new >> (i * 8)) as u8;
+    }
+}
+
+impl LinkedProgram {
+    /// The address of a control section or an EXTDEF symbol
+    pub fn lookup(&self, name: &str) -> Option<u32> {
+        self.sections.iter().find_map(|section| {
+            if section.name == name {
+                Some(section.address)
+            } else {
+                section.symbols.iter().find(|sym| sym.0 == name).map(|sym| sym.1)
+            }
+        })
+    }
+
+    /// The linked program, the first byte is at the start address
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The external symbol table the way the textbook prints it
+    pub fn load_map(&self) -> String {
+        let mut out = format!("{:<8}  {:<8}  {:<7}  {}\n",
+                              "Section",
+                              "Symbol",
+                              "Address",
+                              "Length");
+        for section in &self.sections {
+            out.push_str(&format!("{:<8}  {:<8}  {:06X}   {:06X}\n",
+                                  section.name,
+                                  "",
+                                  section.address,
+                                  section.length));
+            for &(ref name, address) in &section.symbols {
+                out.push_str(&format!("{:<8}  {:<8}  {:06X}\n", "", name, address));
+            }
+        }
+        out
+    }
+
+    /// H/T/E records of the absolute program, ready for a simple loader
+    pub fn all_records(&self) -> String {
+        let mut records = vec![format!("H{:<6}{}{}",
+                                       self.name,
+                                       string_from_object_code(self.start, 3),
+                                       string_from_object_code(self.memory.len() as u32, 3))];
+
+        let mut at = 0;
+        while at < self.memory.len() {
+            if !self.loaded[at] {
+                at += 1;
+                continue;
+            }
+            let end = (at..self.memory.len())
+                .take(MAX_TEXT_BYTES)
+                .take_while(|&i| self.loaded[i])
+                .last()
+                .map_or(at, |last| last + 1);
+            let code = self.memory[at..end]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect::<String>();
+            records.push(text_record(self.start + at as u32, &code));
+            at = end;
+        }
+
+        records.push(String::from("E") + &string_from_object_code(self.entry, 3));
+        records.join("\n")
+    }
+
+    pub fn output_to_file(&self, path: &str) -> io::Result<()> {
+        // Same as the assembler, never leave a half written file behind
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, self.all_records())?;
+        fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::object_program_of;
+
+    /// The three sections of csect.asm, one per object file
+    fn objects() -> Vec<(String, String)> {
+        let records = object_program_of("src/tests/csect.asm");
+        let mut objects: Vec<(String, String)> = Vec::new();
+        for line in records.lines() {
+            if line.starts_with('H') {
+                objects.push((format!("{}.objcode", line[1..7].trim_end()), String::new()));
+            }
+            let object = objects.last_mut().unwrap();
+            object.1.push_str(line);
+            object.1.push('\n');
+        }
+        objects
+    }
+
+    fn word(program: &LinkedProgram, address: u32, len: usize) -> u32 {
+        let at = (address - program.start) as usize;
+        program.memory()[at..at + len].iter().fold(0, |word, &byte| word << 8 | byte as u32)
+    }
+
+    #[test]
+    fn build_estab() {
+        let program = link(&objects(), 0x4000).unwrap();
+
+        assert_eq!(program.lookup("COPY"), Some(0x4000));
+        assert_eq!(program.lookup("RDREC"), Some(0x5033));
+        assert_eq!(program.lookup("WRREC"), Some(0x505E));
+        assert_eq!(program.lookup("BUFFER"), Some(0x4033));
+        assert_eq!(program.lookup("BUFEND"), Some(0x5033));
+        assert_eq!(program.lookup("LENGTH"), Some(0x402D));
+        assert_eq!(program.entry, 0x4000);
+        assert_eq!(program.memory().len(), 0x107A);
+        assert!(program.load_map().contains("RDREC               005033   00002B\n"));
+    }
+
+    #[test]
+    fn apply_modifications() {
+        let program = link(&objects(), 0x4000).unwrap();
+
+        // +JSUB RDREC in COPY
+        assert_eq!(word(&program, 0x4003, 4), 0x4B105033);
+        // +STCH BUFFER,X in RDREC
+        assert_eq!(word(&program, 0x5033 + 0x17, 4), 0x57904033);
+        // MAXLEN WORD BUFEND-BUFFER
+        assert_eq!(word(&program, 0x5033 + 0x28, 3), 0x1000);
+    }
+
+    #[test]
+    fn absolute_records() {
+        let program = link(&objects(), 0x4000).unwrap();
+        let records = program.all_records();
+
+        assert!(records.starts_with("HCOPY  00400000107A\n"));
+        assert!(records.contains("\nT0040001E1720274B105033"));
+        assert!(records.ends_with("\nE004000"));
+        // The buffer is reserved memory, it isn't part of any text record
+        assert!(!records.contains("\nT004033"));
+    }
+
+    #[test]
+    fn undefined_and_duplicate_externals() {
+        let objects = objects();
+
+        let errs = link(&objects[..1], 0).unwrap_err();
+        assert!(errs.iter().all(|e| e.code == Code::UndefinedExternal));
+        assert!(errs.iter().any(|e| e.message.contains("`RDREC`")));
+
+        let twice = vec![objects[1].clone(), objects[1].clone(), objects[0].clone()];
+        let errs = link(&twice, 0).unwrap_err();
+        assert_eq!(errs[0].code, Code::DuplicateExternal);
+        assert_eq!(errs[0].file, "RDREC.objcode");
+    }
+}
@@ -0,0 +1,224 @@
+/// Textbook style assembly listing, every source line is printed as is
+/// next to its line number, location counter and object code, followed
+/// by the symbol table and the literal table
+///
+///  Line  Loc   Source                          Object code
+///     5  0000  FIRST   STL     RETADR          17202D
+///     6  0003          LDB     #LENGTH         69202D
+///     7                BASE    LENGTH
+///
+/// The statements generated by a macro invocation follow it, marked with `+`
+use htme::raw_program::RawProgram;
+use instruction::Instruction;
+use operands::Value;
+use session::Session;
+
+const TAB_WIDTH: usize = 8;
+
+pub fn listing(session: &Session, prog: &RawProgram, source: &[String]) -> String {
+    let source = source.iter().map(|line| expand_tabs(line)).collect::<Vec<String>>();
+    let expanded_width = prog.program
+        .iter()
+        .filter_map(|(_, instr)| instr.expanded.as_ref().map(|text| expand_tabs(text).len() + 1))
+        .max()
+        .unwrap_or(0);
+    let width = source.iter()
+        .map(|line| line.len())
+        .max()
+        .unwrap_or(0)
+        .max(expanded_width)
+        .max("Source".len());
+
+    let mut out = format!("{:>5}  {:<4}  {:<width$}  {}\n",
+                          "Line",
+                          "Loc",
+                          "Source",
+                          "Object code",
+                          width = width);
+
+    for (i, text) in source.iter().enumerate() {
+        let line = i as i32 + 1;
+        let statements = prog.program
+            .iter()
+            .filter(|(_, instr)| instr.src_line_num == line)
+            .collect::<Vec<_>>();
+
+        let main = statements.iter()
+            .find(|(_, instr)| !is_literal_pool(instr) && instr.expanded.is_none());
+        match main {
+            Some((objcode, instr)) => {
+                out.push_str(&format!("{:>5}  {:04X}  {:<width$}  {}",
+                                      line,
+                                      instr.locctr,
+                                      text,
+                                      objcode,
+                                      width = width))
+            }
+            None => out.push_str(&format!("{:>5}  {:<4}  {}", line, "", text)),
+        }
+        push_line_end(&mut out);
+
+        // The macro expansion of this line
+        for (objcode, instr) in statements.iter().filter(|s| s.1.expanded.is_some()) {
+            let text = format!("+{}", expand_tabs(instr.expanded.as_ref().unwrap()));
+            out.push_str(&format!("{:>5}  {:04X}  {:<width$}  {}",
+                                  "",
+                                  instr.locctr,
+                                  text,
+                                  objcode,
+                                  width = width));
+            push_line_end(&mut out);
+        }
+
+        // The literal pool placed by this line (LTORG or END)
+        for (objcode, instr) in statements.iter().filter(|s| is_literal_pool(&s.1)) {
+            let literal = match instr.first_value() {
+                Value::Bytes(ref bytes) => format!("*       ={}", bytes),
+                _ => "*".to_owned(),
+            };
+            out.push_str(&format!("{:>5}  {:04X}  {:<width$}  {}",
+                                  "",
+                                  instr.locctr,
+                                  literal,
+                                  objcode,
+                                  width = width));
+            push_line_end(&mut out);
+        }
+    }
+
+    out.push_str(&symbol_table(session));
+    out.push_str(&literal_table(session));
+    out
+}
+
+fn symbol_table(session: &Session) -> String {
+    let mut symbols = session.symbols
+        .get_all_symbols()
+        .into_iter()
+        .filter(|sym| !sym.get_name().starts_with('='))
+        .collect::<Vec<_>>();
+    symbols.sort_by_key(|sym| (sym.get_control_section(), sym.get_address(), sym.get_name()));
+
+    let mut out = format!("\nSymbol table\n{:<8}  {:<6}  {:<8}  {}\n",
+                          "Name",
+                          "Value",
+                          "Section",
+                          "Block");
+    for sym in symbols {
+        out.push_str(&format!("{:<8}  {:06X}  {:<8}  {}",
+                              sym.get_name(),
+                              sym.get_address(),
+                              sym.get_control_section(),
+                              sym.get_block()));
+        push_line_end(&mut out);
+    }
+    out
+}
+
+fn literal_table(session: &Session) -> String {
+    let mut out = format!("\nLiteral table\n{:<12}  {:<12}  {:<6}  {}\n",
+                          "Literal",
+                          "Value",
+                          "Length",
+                          "Address");
+    for lit in session.literals.get_all_literals() {
+        out.push_str(&format!("{:<12}  {:<12}  {:<6}  {:06X}\n",
+                              lit.external_name,
+                              lit.value,
+                              lit.length_in_bytes(),
+                              lit.address));
+    }
+    out
+}
+
+/// The BYTE declarations made by the assembler for literals
+fn is_literal_pool(instr: &Instruction) -> bool {
+    instr.label.starts_with("lit_") && instr.spans.mnemonic.is_none()
+}
+
+/// Ends the current line without leaving padding at its end
+fn push_line_end(out: &mut String) {
+    let trimmed = out.trim_end_matches(' ').len();
+    out.truncate(trimmed);
+    out.push('\n');
+}
+
+fn expand_tabs(text: &str) -> String {
+    let mut expanded = String::new();
+    for c in text.chars() {
+        if c == '\t' {
+            let spaces = TAB_WIDTH - expanded.chars().count() % TAB_WIDTH;
+            expanded.push_str(&" ".repeat(spaces));
+        } else {
+            expanded.push(c);
+        }
+    }
+    expanded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use filehandler::FileHandler;
+    use pass_one::pass_one::pass_one;
+    use pass_two::translator::pass_two;
+
+    fn assemble(path: &str) -> String {
+        let mut session = Session::new();
+        let mut file = FileHandler::open(path.to_owned()).unwrap();
+        let prog = file.parse_file().unwrap();
+        assert!(file.errs.is_empty());
+        let (_, mut prog) = pass_one(&mut session, prog).unwrap();
+        assert!(pass_two(&mut session, &mut prog).is_empty());
+        listing(&session, &prog, file.source_lines())
+    }
+
+    #[test]
+    fn every_source_line_is_listed() {
+        let listing = assemble("src/tests/test2.asm");
+        let lines = listing.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[0], " Line  Loc   Source                       Object code");
+        assert_eq!(lines[2], "    2  0000  FIRST STL RETADR             17202D");
+        assert_eq!(lines[18], "   18  0030  RETADR RESW 1");
+        assert_eq!(lines[21], "   21         . READ RECORD INTO BUFFER");
+        assert_eq!(lines[52], "   51  1077           END     FIRST");
+    }
+
+    #[test]
+    fn literals_follow_their_pool() {
+        let listing = assemble("src/tests/test2.asm");
+        let lines = listing.lines().collect::<Vec<&str>>();
+
+        assert_eq!(lines[37], "   37        LTORG");
+        assert_eq!(lines[38], "       105C  *       =X'F1'               F1");
+        assert!(listing.contains("\nSymbol table\n"));
+        assert!(listing.contains("\nLiteral table\n\
+                                  Literal       Value         Length  Address\n\
+                                  =X'F1'        F1            1       00105C\n"));
+    }
+
+    #[test]
+    fn macro_expansions_follow_their_invocation() {
+        let listing = assemble("src/tests/macros.asm");
+        let lines = listing.lines().collect::<Vec<&str>>();
+        let invocation = lines.iter().position(|line| line.starts_with("   29 ")).unwrap();
+
+        assert_eq!(lines[invocation + 1].split_whitespace().collect::<Vec<_>>(),
+                   vec!["0003", "+", "CLEAR", "X", "B410"]);
+        assert_eq!(lines[invocation + 5].split_whitespace().collect::<Vec<_>>(),
+                   vec!["000D", "+_AALOOP", "TD", "=X'F1'", "E32048"]);
+        assert!(lines[invocation + 14].starts_with("   30 "));
+    }
+
+    #[test]
+    fn macro_expansions_keep_comments() {
+        let listing = assemble("src/tests/macro_comments.asm");
+        let lines = listing.lines().collect::<Vec<&str>>();
+        let invocation = lines.iter().position(|line| line.starts_with("    7 ")).unwrap();
+
+        assert_eq!(lines[invocation + 1].split_whitespace().collect::<Vec<_>>(),
+                   vec!["0000", "+_AALOOP", "TD", "=X'F1'", ".", "wait", "for", "the", "device",
+                        "E32006"]);
+    }
+}
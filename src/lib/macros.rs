@@ -0,0 +1,363 @@
+/// Macro definitions and their expansion, a macro is defined by
+///
+///  RDBUFF   MACRO   &INDEV,&BUFADR,&RECLTH=LENGTH
+///           ...
+///           MEND
+///
+/// and invoked with positional or keyword arguments, ex. `RDBUFF F1,BUFFER`
+/// or `RDBUFF BUFADR=BUFFER,INDEV=F1`. Labels starting with `$` get a prefix
+/// that's unique to every expansion, so a macro with a loop can be invoked
+/// more than once. The body may define other macros, their parameters and
+/// `$` labels are left alone until they're expanded themselves. Other
+/// `&NAME`s are SET variables, they're replaced when the expanded statement
+/// is read.
+use std::collections::HashMap;
+use diagnostic::{Diagnostic, Code};
+use super::is_label;
+
+/// How deep macros can invoke each other, a recursion that never ends stops here
+pub const MAX_NESTING: usize = 64;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    /// The name without the `&`
+    pub name: String,
+    pub default: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Macro {
+    pub name: String,
+    pub params: Vec<Param>,
+    /// The statements of the body as written, without comments
+    pub body: Vec<String>,
+    /// Where the macro is defined
+    pub line: i32,
+}
+
+impl Macro {
+    /// A macro without a body, operands are the parameters of the prototype,
+    /// ex. `&INDEV,&BUFADR=BUFFER`
+    pub fn new(name: &str, operands: &str, line: i32) -> Result<Macro, Diagnostic> {
+        if !is_label(name) {
+            return Err(Diagnostic::error(Code::InvalidMacro,
+                                         format!("invalid macro name `{}`", name))
+                .with_help("macro names are written like labels".to_owned()));
+        }
+
+        let mut params: Vec<Param> = Vec::new();
+        for operand in split_arguments(operands) {
+            let (param, default) = match operand.find('=') {
+                Some(at) => (&operand[..at], Some(operand[at + 1..].to_owned())),
+                None => (operand.as_str(), None),
+            };
+            if !param.starts_with('&') || !is_label(&param[1..]) {
+                return Err(Diagnostic::error(Code::InvalidMacro,
+                                             format!("invalid macro parameter `{}`", operand))
+                    .with_help("parameters are written as `&NAME` or `&NAME=default`"
+                        .to_owned()));
+            }
+            let name = param[1..].to_owned();
+            if params.iter().any(|p| p.name == name) {
+                return Err(Diagnostic::error(Code::InvalidMacro,
+                                             format!("duplicate macro parameter `&{}`", name)));
+            }
+            params.push(Param { name, default });
+        }
+
+        Ok(Macro {
+            name: name.to_uppercase(),
+            params,
+            body: Vec::new(),
+            line,
+        })
+    }
+
+    /// The body with the arguments of an invocation in place of the
+    /// parameters, unique replaces the `$` of the labels
+    pub fn expand(&self, args: &str, unique: &str) -> Result<Vec<String>, Diagnostic> {
+        let values = self.bind(args)?;
+        let mut depth = 0;
+        let mut lines: Vec<String> = Vec::new();
+        for line in &self.body {
+            // Definitions made by the body are expanded when they're invoked
+            let words = line.split_whitespace().map(|w| w.to_uppercase()).collect::<Vec<_>>();
+            let mnemonic = |name: &str| {
+                words.iter().take(2).any(|w| w == name)
+            };
+            if mnemonic("MACRO") {
+                depth += 1;
+            }
+            if depth == 0 {
+                lines.push(self.substitute(line, &values, unique));
+            } else {
+                lines.push(line.clone());
+            }
+            if mnemonic("MEND") {
+                depth -= 1;
+            }
+        }
+        Ok(lines)
+    }
+
+    /// The value of every parameter for the given arguments
+    fn bind(&self, args: &str) -> Result<HashMap<String, String>, Diagnostic> {
+        let mut values: HashMap<String, String> = HashMap::new();
+        let mut position = 0;
+        let mut keywords = false;
+
+        for arg in split_arguments(args) {
+            let keyword = match arg.find('=') {
+                Some(at) if at > 0 => {
+                    let name = arg[..at].trim_start_matches('&');
+                    if is_label(name) { Some((name.to_owned(), &arg[at + 1..])) } else { None }
+                }
+                _ => None,
+            };
+
+            let (name, value) = match keyword {
+                Some((name, value)) => {
+                    if !self.params.iter().any(|p| p.name == name) {
+                        return Err(Diagnostic::error(Code::InvalidMacroCall,
+                                                     format!("`{}` has no parameter `&{}`",
+                                                             self.name,
+                                                             name))
+                            .with_help(self.describe_params()));
+                    }
+                    keywords = true;
+                    (name, value.to_owned())
+                }
+                None if keywords => {
+                    return Err(Diagnostic::error(Code::InvalidMacroCall,
+                                                 format!("positional argument `{}` after \
+                                                          keyword arguments",
+                                                         arg)));
+                }
+                None => {
+                    let param = match self.params.get(position) {
+                        Some(param) => param,
+                        None => {
+                            return Err(Diagnostic::error(Code::InvalidMacroCall,
+                                                         format!("too many arguments for `{}`",
+                                                                 self.name))
+                                .with_help(self.describe_params()));
+                        }
+                    };
+                    position += 1;
+                    (param.name.clone(), arg)
+                }
+            };
+
+            if values.insert(name.clone(), value).is_some() {
+                return Err(Diagnostic::error(Code::InvalidMacroCall,
+                                             format!("`&{}` is given more than once", name)));
+            }
+        }
+
+        // Parameters without an argument take their default, or nothing
+        for param in &self.params {
+            if !values.contains_key(&param.name) {
+                values.insert(param.name.clone(), param.default.clone().unwrap_or_default());
+            }
+        }
+        Ok(values)
+    }
+
+    fn substitute(&self, line: &str, values: &HashMap<String, String>, unique: &str) -> String {
+        let chars = line.chars().collect::<Vec<char>>();
+        let is_name = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        let mut out = String::new();
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            let name_follows = chars.get(i + 1).is_some_and(|&next| is_name(next));
+            let name_before = i > 0 && is_name(chars[i - 1]);
+
+            if c == '&' && name_follows {
+                let end = (i + 1..chars.len()).find(|&j| !is_name(chars[j])).unwrap_or(chars.len());
+                let name = chars[i + 1..end].iter().collect::<String>();
+                match values.get(&name) {
+                    Some(value) => out.push_str(value),
+                    None => {
+                        out.push('&');
+                        out.push_str(&name);
+                    }
+                }
+                i = end;
+                continue;
+            }
+
+            if c == '$' && name_follows && !name_before {
+                out.push_str(unique);
+            } else {
+                out.push(c);
+            }
+            i += 1;
+        }
+        out
+    }
+
+    fn describe_params(&self) -> String {
+        if self.params.is_empty() {
+            return format!("`{}` takes no arguments", self.name);
+        }
+        let params = self.params
+            .iter()
+            .map(|p| match p.default {
+                Some(ref default) => format!("&{}={}", p.name, default),
+                None => format!("&{}", p.name),
+            })
+            .collect::<Vec<String>>();
+        format!("`{}` takes {}", self.name, params.join(","))
+    }
+}
+
+/// The macros defined so far in a source file
+#[derive(Debug, Default)]
+pub struct MacroTable {
+    macros: HashMap<String, Macro>,
+    expansions: u32,
+}
+
+impl MacroTable {
+    pub fn new() -> MacroTable {
+        MacroTable::default()
+    }
+
+    /// A later definition of the same name replaces the earlier one
+    pub fn define(&mut self, mac: Macro) {
+        self.macros.insert(mac.name.clone(), mac);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Macro> {
+        self.macros.get(&name.to_uppercase())
+    }
+
+    /// The prefix of the `$` labels of the next expansion, `_AA`, `_AB` ...
+    pub fn next_unique(&mut self) -> String {
+        let mut n = self.expansions;
+        self.expansions += 1;
+
+        let mut letters = String::new();
+        while letters.len() < 2 || n > 0 {
+            letters.insert(0, (b'A' + (n % 26) as u8) as char);
+            n /= 26;
+        }
+        format!("_{}", letters)
+    }
+}
+
+/// Splits operands on commas that aren't in a C'...' constant, an empty
+/// text has no operands at all
+fn split_arguments(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+    let mut args: Vec<String> = vec![String::new()];
+    let mut quoted = false;
+    for c in text.chars() {
+        match c {
+            '\'' => quoted = !quoted,
+            ',' if !quoted => {
+                args.push(String::new());
+                continue;
+            }
+            _ => (),
+        }
+        args.last_mut().unwrap().push(c);
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rdbuff() -> Macro {
+        let mut mac = Macro::new("rdbuff", "&INDEV,&BUFADR,&RECLTH=LENGTH", 1).unwrap();
+        mac.body = vec!["         CLEAR   X".to_owned(),
+                        "$LOOP    TD      =X'&INDEV'".to_owned(),
+                        "         JEQ     $LOOP".to_owned(),
+                        "         STCH    &BUFADR,X".to_owned(),
+                        "         STX     &RECLTH".to_owned()];
+        mac
+    }
+
+    #[test]
+    fn prototypes() {
+        let mac = rdbuff();
+        assert_eq!(mac.name, "RDBUFF");
+        assert_eq!(mac.params[2],
+                   Param {
+                       name: "RECLTH".to_owned(),
+                       default: Some("LENGTH".to_owned()),
+                   });
+        assert!(Macro::new("M", "", 1).unwrap().params.is_empty());
+
+        assert_eq!(Macro::new("M", "X", 1).unwrap_err().code, Code::InvalidMacro);
+        assert_eq!(Macro::new("M", "&X,&X", 1).unwrap_err().code, Code::InvalidMacro);
+        assert_eq!(Macro::new("1M", "", 1).unwrap_err().code, Code::InvalidMacro);
+    }
+
+    #[test]
+    fn positional_and_keyword_arguments() {
+        let mac = rdbuff();
+        let lines = mac.expand("F1,BUFFER", "_AA").unwrap();
+        assert_eq!(lines[1], "_AALOOP    TD      =X'F1'");
+        assert_eq!(lines[2], "         JEQ     _AALOOP");
+        assert_eq!(lines[3], "         STCH    BUFFER,X");
+        assert_eq!(lines[4], "         STX     LENGTH");
+
+        let lines = mac.expand("05,RECLTH=SIZE,BUFADR=OUT", "_AB").unwrap();
+        assert_eq!(lines[1], "_ABLOOP    TD      =X'05'");
+        assert_eq!(lines[3], "         STCH    OUT,X");
+        assert_eq!(lines[4], "         STX     SIZE");
+
+        // Left out arguments are empty
+        assert_eq!(mac.expand("", "_AC").unwrap()[3], "         STCH    ,X");
+    }
+
+    #[test]
+    fn invalid_invocations() {
+        let mac = rdbuff();
+        let code = |args: &str| mac.expand(args, "_AA").unwrap_err().code;
+        assert_eq!(code("F1,BUFFER,LENGTH,EXTRA"), Code::InvalidMacroCall);
+        assert_eq!(code("DEVICE=F1"), Code::InvalidMacroCall);
+        assert_eq!(code("RECLTH=SIZE,F1"), Code::InvalidMacroCall);
+        assert_eq!(code("F1,INDEV=F2"), Code::InvalidMacroCall);
+
+        // Other names are SET variables
+        let mut variable = rdbuff();
+        variable.body.push("         LDA     &BUFFADR".to_owned());
+        assert_eq!(variable.expand("F1", "_AA").unwrap()[5], "         LDA     &BUFFADR");
+    }
+
+    #[test]
+    fn nested_definitions_are_kept() {
+        let mut mac = Macro::new("OUTER", "&DEV", 1).unwrap();
+        mac.body = vec!["INNER    MACRO   &X".to_owned(),
+                        "$L       TD      =X'&X'".to_owned(),
+                        "         MEND".to_owned(),
+                        "$L       TD      =X'&DEV'".to_owned()];
+        let lines = mac.expand("F1", "_AA").unwrap();
+        assert_eq!(lines[1], "$L       TD      =X'&X'");
+        assert_eq!(lines[3], "_AAL       TD      =X'F1'");
+    }
+
+    #[test]
+    fn unique_prefixes() {
+        let mut table = MacroTable::new();
+        assert_eq!(table.next_unique(), "_AA");
+        assert_eq!(table.next_unique(), "_AB");
+        table.expansions = 26 * 26;
+        assert_eq!(table.next_unique(), "_BAA");
+    }
+
+    #[test]
+    fn arguments_with_commas() {
+        assert_eq!(split_arguments("C'A,B',X"), vec!["C'A,B'", "X"]);
+        assert_eq!(split_arguments("F1,,LENGTH"), vec!["F1", "", "LENGTH"]);
+        assert!(split_arguments("").is_empty());
+    }
+}
@@ -6,20 +6,20 @@
 use operands::{OperandType, Value};
 use register::Register;
 use instruction::AsmOperand;
-use literal_table::insert_unresolved;
+use instruction_set::Directive;
+use diagnostic::{Diagnostic, Code};
 use super::*;
 use std::i32;
 
 
-pub fn parse_directive_operand(op: &str, instruction: &str) -> Result<AsmOperand, String> {
+pub fn parse_directive_operand(op: &str, directive: Directive) -> Result<AsmOperand, Diagnostic> {
     let mut errs: String = String::new();
-    let inst = instruction.to_uppercase();
 
     let result = parse_bytes(op)
         .or_else(|e| {
             errs = format!("{}", e);
             // RESW/B
-            if inst == "RESB" || inst == "RESW" || inst == "WORD" {
+            if let Directive::Resb | Directive::Resw | Directive::Word = directive {
                 parse_signed_int(op)
             } else {
                 Err("Not RESB/W or WORD".to_owned())
@@ -29,7 +29,7 @@ pub fn parse_directive_operand(op: &str, instruction: &str) -> Result<AsmOperand
         .or_else(|e| {
             // START / END
             errs = format!("{}\n{}", errs, e);
-            if inst == "START" || inst == "END" {
+            if let Directive::Start | Directive::End = directive {
                 parse_hex(op)
             } else {
                 Err("Not START/END".to_owned())
@@ -42,11 +42,13 @@ pub fn parse_directive_operand(op: &str, instruction: &str) -> Result<AsmOperand
         })
         .or_else(|e| {
             errs = format!("{}\n{}", errs, e);
-            if inst == "EQU"{
+            if let Directive::Word | Directive::Org = directive {
+                parse_expression(op)
+            } else if directive == Directive::Equ {
                 parse_signed_int(op)
                 .or_else(|e| {
                     errs = format!("{}\n{}", errs, e);
-                    parse_instruction_operand(op)
+                    parse_instruction_operand(op).map_err(|e| e.message)
                 })
                 .or_else(|e| {
                     errs = format!("{}\n{}", errs, e);
@@ -64,12 +66,36 @@ pub fn parse_directive_operand(op: &str, instruction: &str) -> Result<AsmOperand
         });
 
     match result {
-        Ok(r) => return Ok(r),
-        Err(_) => return Err(errs),
-    };
+        Ok(r) => Ok(r),
+        Err(_) => {
+            let name = directive.name();
+            debug!("Failed to parse {:?} for {}: {}", op, name, errs);
+            Err(Diagnostic::error(Code::InvalidOperand,
+                                  format!("invalid operand `{}` for `{}`", op, name))
+                .with_help(format!("`{}` expects {}",
+                                   name,
+                                   expected_directive_operand(directive))))
+        }
+    }
+}
+
+/// Describes the operand a directive accepts, used to help the user out
+fn expected_directive_operand(directive: Directive) -> &'static str {
+    match directive {
+        Directive::Resb | Directive::Resw => "a decimal number",
+        Directive::Word => "a decimal number, a C'...'/X'...' constant, a label or an expression",
+        Directive::Byte => "a C'...' or X'...' constant",
+        Directive::Start => "a hexadecimal address",
+        Directive::End => "a hexadecimal address or a label",
+        Directive::Base => "a label",
+        Directive::Equ => "a number, a label, an expression or `*`",
+        Directive::Org => "an address, ex. a label or `TABLE+3`",
+        Directive::Use => "a block name, or nothing for the default block",
+        _ => "no operands",
+    }
 }
 
-pub fn parse_instruction_operand(op: &str) -> Result<AsmOperand, String> {
+pub fn parse_instruction_operand(op: &str) -> Result<AsmOperand, Diagnostic> {
     let mut errs: String = String::new();
     let result = parse_register(op)
         .or_else(|e| {
@@ -81,22 +107,42 @@ pub fn parse_instruction_operand(op: &str) -> Result<AsmOperand, String> {
             parse_memory_operand(op)
         });
     match result {
-        Ok(r) => return Ok(r),
-        Err(_) => return Err(errs),
-    };
+        Ok(r) => Ok(r),
+        Err(_) => {
+            debug!("Failed to parse {:?}: {}", op, errs);
+            if op.starts_with('=') {
+                return Err(Diagnostic::error(Code::InvalidLiteral,
+                                             format!("invalid literal `{}`", op))
+                    .with_help("literals are written as =C'...' or =X'...'".to_owned()));
+            }
+            Err(Diagnostic::error(Code::InvalidOperand, format!("invalid operand `{}`", op))
+                .with_help("expected a register, a hexadecimal number, a label or an expression, \
+                            optionally prefixed by `#`, `@` or `=`"
+                    .to_owned()))
+        }
+    }
 }
 
 /// Occurs when: Instruction -> F3 / F4
 fn parse_memory_operand(op: &str) -> Result<AsmOperand, String> {
-    let prefix = &op[0..1];
-    let content = &op[1..];
+    let mut chars = op.chars();
+    let prefix = chars.next();
+    let content = chars.as_str();
     match prefix {
-        "#" => parse_label(content, OperandType::Immediate).or_else(|_| parse_signed_int(content)),
-        "@" => parse_label(content, OperandType::Indirect),
-        "=" => parse_literal(op),
+        Some('#') => {
+            parse_label(content, OperandType::Immediate)
+                .or_else(|_| parse_signed_int(content))
+                .or_else(|_| parse_memory_expression(content, OperandType::Immediate))
+        }
+        Some('@') => {
+            parse_label(content, OperandType::Indirect)
+                .or_else(|_| parse_memory_expression(content, OperandType::Indirect))
+        }
+        Some('=') => parse_literal(op),
         _ => {
             // Label
             parse_label(op, OperandType::Label)
+                .or_else(|_| parse_memory_expression(op, OperandType::Label))
         }
     }
 
@@ -104,7 +150,8 @@ fn parse_memory_operand(op: &str) -> Result<AsmOperand, String> {
 
 /// Occurs when: Instruction -> F3 / F2 / F1
 fn parse_register(op: &str) -> Result<AsmOperand, String> {
-    match op {
+    // Registers are named in any case, like the mnemonics
+    match op.to_ascii_uppercase().as_str() {
         "A" => Ok(AsmOperand::new(OperandType::Register, Value::Register(Register::A))),
         "X" => Ok(AsmOperand::new(OperandType::Register, Value::Register(Register::X))),
         "L" => Ok(AsmOperand::new(OperandType::Register, Value::Register(Register::L))),
@@ -171,7 +218,6 @@ fn parse_literal(op: &str) -> Result<AsmOperand, String> {
     if !is_literal(op) {
         return Err(format!("Invalid literal {}", op));
     }
-    insert_unresolved(&op.to_owned());
     Ok(create_operand(OperandType::Label, Value::Bytes(op.to_owned())))
 }
 
@@ -183,33 +229,18 @@ pub fn parse_ref_operands(ops: Vec<String>) -> AsmOperand {
     return AsmOperand::new(OperandType::VarArgs, Value::VarArgs(ops));
 }
 
-//parses expression operands
-fn parse_expression(op:&str)-> Result<AsmOperand, String> {
-    if is_expression(op) {
-        let labels = capture_expression(op);
-        println!("{:?}", labels);
-        return Ok(create_operand(OperandType::Expression, Value::Expression(labels)));
-    }
-    else{
-        return Err(format!("{} is not an expression.", op));
-    }
+/// Occurs when: Directive -> WORD / EQU, ex. BUFEND-BUFFER or (MAXLEN+2)/3
+fn parse_expression(op: &str) -> Result<AsmOperand, String> {
+    expression::parse(op)
+        .map(|expr| create_operand(OperandType::Expression, Value::Expression(expr)))
 }
 
-//returns expression to be computed and labels therein
-fn capture_expression(op:&str)->Vec<String>{
-    let matches = EXPRESSION.captures(op).unwrap();
-    let mut terms_vector = Vec::new();
-    for a_match in matches.iter() {
-        let term = a_match.map_or("", |m| m.as_str());
-        //skipping empty captures from repeated groups
-        if term != ""{
-            terms_vector.push(String::from(term))
-        }
-    }
-    return terms_vector
+/// Occurs when: Instruction -> F3 / F4, ex. TABLE+3 or #MAXLEN*2, the type
+/// keeps the addressing mode of the prefix
+fn parse_memory_expression(op: &str, t: OperandType) -> Result<AsmOperand, String> {
+    expression::parse(op).map(|expr| create_operand(t, Value::Expression(expr)))
 }
 
-
 fn parse_locctr_ref(op:&str)->Result<AsmOperand, String>{
     if op == "*"{
         return Ok(create_operand(OperandType::Bytes, Value::Bytes(op.to_owned())));
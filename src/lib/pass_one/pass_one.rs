@@ -4,80 +4,34 @@ use formats::Format;
 use unit_or_pair::*;
 use operands::*;
 use literal::Literal;
-use literal_table::{insert_literal, get_unresolved, get_literal};
+use literal_table::LiteralTable;
 use std::u32;
 use symbol::{Symbol, SymbolType};
-use symbol_tables::*;
+use session::Session;
+use statement::{Statement, constant_bytes, invalid_org_operand};
+use diagnostic::{Diagnostic, Code};
+use htme::control_section::ControlSection;
+use htme::reader::MEMORY_SIZE;
+use expression::{evaluate, Expr};
 use super::super::*;
-use basic_types::symbol_tables::define_local_symbol;
-extern crate meval;
 
-// FIXME: get instruction size shouldn't check for errors
+/// The bytes an instruction takes, RESB/RESW and BYTE sizes come from their operands
 fn get_instruction_size(inst: &Instruction) -> i32 {
     match inst.get_format() {
-        Format::One => return 1,
-        Format::Two => return 2,
-        Format::Three => return 3,
-        Format::Four => return 4,
-        Format::None => (),
-    }
-    match &*inst.mnemonic.to_uppercase() {
-
-        "BYTE" => {
-            let operands = unwrap_to_vec(&inst.operands);
-            if operands.len() != 1 {
-                panic!("BYTE expects only 1 operand");
-            }
-
-            let instr_len = match operands[0].val {
-                Value::Raw(_) => 1,
-                Value::SignedInt(_) => 1,
-                Value::Bytes(ref x) => {
-                    if x.starts_with("X") {
-                        let len: f32 = ((x.len() - 3) as f32 / 2.0) as f32;
-                        len.ceil() as i32
-                    } else if x.starts_with("C") {
-                        x.len() as i32 - 3
-                    } else if x.starts_with("=") {
-                        get_literal(x).unwrap().length_in_bytes()
-                    } else {
-                        panic!("Unexpected Error {:?}", *inst)
-                    }
-                }
-                _ => panic!("Unexpected Error {:?}", *inst),
-            };
-            return instr_len;
-        }
-        "WORD" => return 3,
-        "RESB" => {
-            let operands = unwrap_to_vec(&inst.operands);
-            if operands.len() != 1 {
-                panic!("RESB expects only 1 operand");
-            }
-            match operands[0].val {
-                Value::SignedInt(x) => return x,
-                _ => panic!("Unexpected Error {:?}", *inst),
-            }
-        }
-        "RESW" => {
-            let operands = unwrap_to_vec(&inst.operands);
-            if operands.len() != 1 {
-                panic!("RESW expects only 1 operand");
-            }
-            match operands[0].val {
-                Value::SignedInt(x) => return x * 3,
-                _ => panic!("Unexpected Error - find_instr_length {:?}", operands),
-            }
-        }
-        _ => (),
+        Format::One => 1,
+        Format::Two => 2,
+        Format::Three => 3,
+        Format::Four => 4,
+        Format::None => inst.statement.size(),
     }
-    return 0;
 }
 
-pub fn pass_one(prog_info: RawProgram) -> Result<(HashSet<Symbol>, RawProgram), String> {
+pub fn pass_one(session: &mut Session,
+                prog_info: RawProgram)
+                -> Result<(HashSet<Symbol>, RawProgram), Vec<Diagnostic>> {
 
     // TODO: replace the literal in an instruction operand with the literal label
-    // if let Value::Bytes(ref x) = instruction.get_first_operand().val {}
+    // if let Value::Bytes(ref x) = instruction.first_value() {}
 
     let prog = prog_info;
     let mut prog: RawProgram = prog;
@@ -93,26 +47,37 @@ pub fn pass_one(prog_info: RawProgram) -> Result<(HashSet<Symbol>, RawProgram),
     prog.program = Vec::new(); // To cancel the move effect
 
     // TODO: return here
-    let (errs, instructions) = process_instructions(temp_instructions, &mut prog);
+    let (errs, instructions) = process_instructions(session, temp_instructions, &mut prog);
     // Move the instructions back
     prog.program = instructions.into_iter()
         .map(|i| (String::new(), i))
         .collect::<Vec<(_, Instruction)>>();
 
     if errs.len() != 0 {
-        return Err(errs.join("\n "));
+        return Err(errs);
     }
 
-    Ok((get_all_symbols(), prog))
+    Ok((get_all_symbols(session), prog))
 }
 
-fn flush_literals(instructions: &mut Vec<Instruction>, start_loc: u32, csect: &str) -> i32 {
+/// Places the pending literals at start_loc, line is the LTORG or END
+/// line that caused the literal pool to be placed
+fn flush_literals(session: &mut Session,
+                  instructions: &mut Vec<Instruction>,
+                  start_loc: u32,
+                  csect: &str,
+                  block: &str,
+                  line: i32)
+                  -> i32 {
 
     let mut loc = start_loc;
-    for lit in get_unresolved() {
-        insert_literal(&lit, loc);
+    for lit in session.literals.get_unresolved() {
+        session.literals.insert_literal(&lit, loc);
         // literal declaration to be inserted in code
-        let lit_decl: Instruction = *create_from_literal(&lit, loc as i32);
+        let mut lit_decl: Instruction = *create_from_literal(&mut session.literals, &lit, loc as i32);
+        lit_decl.set_line_number(line);
+        lit_decl.csect = csect.to_owned();
+        lit_decl.block = block.to_owned();
         let lit_sz = get_instruction_size(&lit_decl) as u32;
         loc += lit_sz;
 
@@ -120,297 +85,622 @@ fn flush_literals(instructions: &mut Vec<Instruction>, start_loc: u32, csect: &s
         instructions.push(lit_decl);
 
         // Add literals to symbol table
-        define_local_symbol(&lit, lit_addr, csect).unwrap();
+        let mut sym = Symbol::new(&lit, lit_addr, csect).in_block(block);
+        sym.mark_relative();
+        session.symbols.define_symbol(sym).unwrap();
     }
     loc as i32
 }
 
-fn process_instructions(temp_instructions: Vec<Instruction>,
+/// A program block of USE, each block has its own location counter
+struct Block {
+    name: String,
+    /// Where the location counter of the block starts, the START address
+    /// for the default block and 0 for the others
+    start: i32,
+    loc: i32,
+    /// The highest address reached, ORG can move the location counter back
+    high: i32,
+}
+
+impl Block {
+    fn new(name: &str, start: i32) -> Block {
+        Block {
+            name: name.to_owned(),
+            start,
+            loc: start,
+            high: start,
+        }
+    }
+}
+
+/// Places the pending literals at the end of the current block and lays the
+/// blocks of the section out one after the other, in the order they first
+/// appear, returns the end of the section
+fn layout_blocks(session: &mut Session,
+                 instructions: &mut Vec<Instruction>,
+                 blocks: &mut [Block],
+                 current: usize,
+                 csect: &str,
+                 line: i32)
+                 -> i32 {
+    let pool = blocks[current].loc.max(blocks[current].high) as u32;
+    blocks[current].high = flush_literals(session,
+                                          instructions,
+                                          pool,
+                                          csect,
+                                          &blocks[current].name,
+                                          line);
+
+    let mut next = blocks[0].start;
+    for block in blocks.iter() {
+        let offset = next - block.start;
+        if offset != 0 {
+            for instr in instructions.iter_mut()
+                .filter(|instr| instr.csect == csect && instr.block == block.name) {
+                instr.locctr += offset;
+                if instr.spans.mnemonic.is_none() {
+                    // Literal pools are made by the assembler
+                    session.literals.move_literal(&instr.label, instr.locctr as u32);
+                }
+            }
+            session.symbols.move_block(csect, &block.name, offset);
+        }
+        next += block.high - block.start;
+    }
+    next
+}
+
+fn process_instructions(session: &mut Session,
+                        temp_instructions: Vec<Instruction>,
                         mut prog: &mut RawProgram)
-                        -> (Vec<String>, Vec<Instruction>) {
+                        -> (Vec<Diagnostic>, Vec<Instruction>) {
     let mut loc = 0;
-    let mut errs: Vec<String> = Vec::new();
+    let mut errs: Vec<Diagnostic> = Vec::new();
     let mut instructions: Vec<Instruction> = Vec::new();
     let mut csect: String = String::new();
 
     // Start must be the first instruction
-    match parse_start(&temp_instructions[0], &mut prog) {
-        Err(e) => errs.push(e),
-        Ok(start) => loc = start as i32,
+    match temp_instructions.first().map(|first| parse_start(session, first, prog)) {
+        None => {
+            errs.push(Diagnostic::error(Code::MissingStart,
+                                        "program doesn't have any instructions".to_owned())
+                .with_help("the program must start with `NAME START address`".to_owned()));
+            return (errs, instructions);
+        }
+        Some(Err(e)) => errs.push(e),
+        Some(Ok(start)) => loc = start,
     }
 
+    // ORG can move the location counter back, the block ends at the
+    // highest address reached
+    let mut high = loc;
+    let mut org_return: Option<i32> = None;
+    let mut blocks = vec![Block::new("", loc)];
+    let mut current = 0;
+
     // Skip the first instruction
     let temp_instructions = temp_instructions.into_iter().skip(1);
+    let mut end_line: Option<i32> = None;
     for instruction in temp_instructions {
         let mut instruction: Instruction = instruction;
-        let instruction_size: i32 = get_instruction_size(&instruction);
-
-        let mnemonic = instruction.mnemonic.to_uppercase();
+        if let Some(end_line) = end_line {
+            session.warnings.push(statement_after_end(&instruction, end_line));
+            break;
+        }
+        register_literals(session, &instruction);
 
-        if mnemonic != "EQU" {
+        if !matches!(instruction.statement, Statement::Equ(_)) {
             instruction.locctr = loc;
             instruction.csect = csect.clone();
         }
+        instruction.block = blocks[current].name.clone();
+
+        match instruction.statement {
+            Statement::Start(_) => {
+                errs.push(Diagnostic::error(Code::DuplicateStart,
+                                            "duplicate START directive".to_owned())
+                    .at_line(instruction.src_line_num)
+                    .with_span(instruction.spans.mnemonic))
+            }
+            Statement::Ltorg => {
+                loc = flush_literals(session,
+                                     &mut instructions,
+                                     loc as u32,
+                                     &csect,
+                                     &blocks[current].name,
+                                     instruction.src_line_num);
+            }
 
-        match mnemonic.as_ref() {
-            "START" => errs.push("Duplicate START instruction".to_owned()),
-            "LTORG" => {
-                loc = flush_literals(&mut instructions, loc as u32, &csect);
+            Statement::Use(ref name) => {
+                blocks[current].loc = loc;
+                blocks[current].high = high;
+                current = match blocks.iter().position(|block| block.name == *name) {
+                    Some(index) => index,
+                    None => {
+                        blocks.push(Block::new(name, 0));
+                        blocks.len() - 1
+                    }
+                };
+                loc = blocks[current].loc;
+                high = blocks[current].high;
+                org_return = None;
             }
 
-            "EQU" => {
-                if let Err(e) = parse_equ(&instruction, &csect, loc) {
-                    errs.push(format!("{} at line {}", e, instruction.src_line_num));
+            Statement::Equ(ref expr) => {
+                if let Err(e) = parse_equ(session, &instruction, expr, &csect, loc) {
+                    errs.push(e.at_line(instruction.src_line_num).or_span(instruction.spans.operands));
                 }
             }
 
-            "END" => {
-                match parse_end(&instruction, &mut prog, loc + instruction_size) {
+            Statement::Org(ref expr) => {
+                if !instruction.label.is_empty() {
+                    if let Err(e) = define_label(session, &instruction, loc, &csect) {
+                        errs.push(e.at_line(instruction.src_line_num)
+                            .with_span(instruction.spans.label));
+                    }
+                }
+                match parse_org(session, &instruction, expr, &csect, loc, org_return) {
+                    Ok(Some(addr)) => {
+                        // Nested ORGs return to where the first one was written
+                        org_return = org_return.or(Some(loc));
+                        loc = addr;
+                    }
+                    Ok(None) => loc = org_return.take().unwrap(),
+                    Err(e) => {
+                        errs.push(e.at_line(instruction.src_line_num)
+                            .or_span(instruction.spans.operands.or(instruction.spans.mnemonic)))
+                    }
+                }
+            }
+
+            Statement::End(_) => {
+                end_line = Some(instruction.src_line_num);
+                match parse_end(session, &instruction, prog, loc) {
                     Ok(_) => instructions.push(instruction.clone()),
-                    Err(e) => errs.push(e),
+                    Err(e) => {
+                        errs.push(e.at_line(instruction.src_line_num)
+                            .or_span(instruction.spans.operands))
+                    }
                 }
             }
+            Statement::Csect => {
+                let pool_line = instructions.last().map_or(0, |instr| instr.src_line_num);
+                blocks[current].loc = loc;
+                blocks[current].high = high;
+                let end = layout_blocks(session,
+                                        &mut instructions,
+                                        &mut blocks,
+                                        current,
+                                        &csect,
+                                        pool_line);
+                instruction.block = String::new();
+                loc = consume_instruction(session,
+                                          &instruction,
+                                          end,
+                                          &mut csect,
+                                          &mut errs,
+                                          &mut instructions,
+                                          &mut prog.sections);
+                blocks = vec![Block::new("", loc)];
+                current = 0;
+                high = loc;
+                org_return = None;
+            }
             _ => {
-                loc = consume_instruction(&instruction,
+                loc = consume_instruction(session,
+                                          &instruction,
                                           loc,
                                           &mut csect,
-                                          instruction_size,
                                           &mut errs,
-                                          &mut instructions)
+                                          &mut instructions,
+                                          &mut prog.sections)
             }
         };
+        if !(0..=MEMORY_SIZE as i32).contains(&loc) {
+            errs.push(Diagnostic::error(Code::AddressOutOfRange,
+                                        format!("the location counter {:#X} is out of the 1 MB \
+                                                 memory",
+                                                loc))
+                .at_line(instruction.src_line_num)
+                .with_span(instruction.spans.operands.or(instruction.spans.mnemonic)));
+            loc = loc.max(0).min(MEMORY_SIZE as i32);
+        }
+        high = high.max(loc);
     }
 
     // Flush remaining literals
-    flush_literals(&mut instructions, loc as u32, &csect);
+    let last_line = end_line.or(instructions.last().map(|instr| instr.src_line_num)).unwrap_or(0);
+    blocks[current].loc = loc;
+    blocks[current].high = high;
+    let end = layout_blocks(session, &mut instructions, &mut blocks, current, &csect, last_line);
+    if let Some(section) = prog.sections.last_mut() {
+        section.close(end as u32);
+    }
+    errs.extend(resolve_defines(session, &mut prog.sections));
 
     if prog.program_length == u32::MAX {
-        errs.push(format!("Couldn't find the END instruction"));
+        errs.push(Diagnostic::error(Code::MissingEnd, "missing END directive".to_owned())
+            .with_help("the program must end with `END [first instruction]`".to_owned()));
+    } else if let Some(section) = prog.sections.first() {
+        prog.program_length = section.length;
     }
     (errs, instructions)
 }
 
-fn consume_instruction(instruction: &Instruction,
+/// Fills the addresses of the EXTDEF symbols of every section, they're
+/// usually exported before being defined
+fn resolve_defines(session: &Session, sections: &mut [ControlSection]) -> Vec<Diagnostic> {
+    let mut errs: Vec<Diagnostic> = Vec::new();
+    for section in sections.iter_mut() {
+        for &mut (ref name, ref mut address) in section.defines.iter_mut() {
+            match session.symbols.get_symbol(name, &section.csect) {
+                Ok(ref sym) if sym.symbol_type == SymbolType::Local => {
+                    *address = sym.get_address() as u32;
+                }
+                _ => {
+                    errs.push(Diagnostic::error(Code::UndefinedSymbol,
+                                                format!("`{}` is exported by `{}` but isn't \
+                                                         defined in it",
+                                                        name,
+                                                        section.name))
+                        .with_help("EXTDEF can only export labels of its own control section"
+                            .to_owned()))
+                }
+            }
+        }
+    }
+    errs
+}
+
+/// Everything after END is ignored, the first ignored statement is reported
+fn statement_after_end(instruction: &Instruction, end_line: i32) -> Diagnostic {
+    Diagnostic::warning(Code::StatementAfterEnd,
+                        "statements after END are ignored".to_owned())
+        .at_line(instruction.src_line_num)
+        .with_span(instruction.spans.label.or(instruction.spans.mnemonic))
+        .with_note_at("the program ends here".to_owned(), end_line, None)
+}
+
+fn consume_instruction(session: &mut Session,
+                       instruction: &Instruction,
                        mut loc: i32,
                        mut csect: &mut String,
-                       instruction_size: i32,
-                       errs: &mut Vec<String>,
-                       instructions: &mut Vec<Instruction>)
+                       errs: &mut Vec<Diagnostic>,
+                       instructions: &mut Vec<Instruction>,
+                       sections: &mut Vec<ControlSection>)
                        -> i32 {
     // This function exists just to improve testability
 
-    if !instruction.label.is_empty() {
-        if let Err(e) = define_local_symbol(&instruction.label, loc, &csect) {
-            errs.push(format!("{} at line {}", e, instruction.src_line_num));
+    // The label of CSECT belongs to the new section, it's defined below
+    let is_csect = instruction.statement == Statement::Csect;
+    if !instruction.label.is_empty() && !is_csect {
+        if let Err(e) = define_label(session, instruction, loc, csect) {
+            errs.push(e.at_line(instruction.src_line_num).with_span(instruction.spans.label));
         }
     }
-    let mut result = Ok(());
-    match instruction.mnemonic.to_uppercase().as_str() {
-        "EXTREF" => {
+    let mut result: Result<(), Vec<Diagnostic>> = Ok(());
+    match instruction.statement {
+        Statement::Extref(ref names) => {
             // Call the master table
-            result = match instruction.get_first_operand().val {
-                Value::VarArgs(ops) => define_imported_symbols(&ops, csect),
-                _ => panic!("Invalid operands"),
+            if let Some(section) = sections.last_mut() {
+                names.iter().for_each(|name| section.add_refer(name));
             }
+            result = session.symbols
+                .define_imported_symbols(names, csect)
+                .and(check_external_names(names));
         }
-        "EXTDEF" => {
+        Statement::Extdef(ref names) => {
             // Call the master table
-            result = match instruction.get_first_operand().val {
-                Value::VarArgs(ops) => define_exported_symbols(&ops, csect),
-                _ => panic!("Invalid operands"),
-            };
+            if let Some(section) = sections.last_mut() {
+                names.iter().for_each(|name| section.add_define(name));
+            }
+            result = session.symbols
+                .define_exported_symbols(names, csect)
+                .and(check_external_names(names));
         }
-        "CSECT" => {
-            // TODO: add csect to master table
+        Statement::Csect => {
+            // loc is the end of the section, after its blocks and literals
+            if let Some(section) = sections.last_mut() {
+                section.close(loc as u32);
+            }
+            sections.push(ControlSection::new(&instruction.label, &instruction.label, 0));
+
             *csect = instruction.label.clone();
-            result = define_control_section(csect);
             loc = 0;
             // Control section name is the same as a program name
             // and can be used normally
-            result = result.and(define_local_symbol(csect, loc, csect));
+            result = session.symbols
+                .define_control_section(csect)
+                .and(define_label(session, instruction, loc, csect))
+                .and(check_section_name(instruction))
+                .map_err(|e| vec![e]);
         }
         _ => {
-            loc += instruction_size;
+            loc = loc.saturating_add(get_instruction_size(instruction));
             instructions.push(instruction.clone());
         }
     }
 
 
     if let Err(e) = result {
-        errs.push(e);
+        errs.extend(e.into_iter()
+            .map(|e| e.at_line(instruction.src_line_num).or_span(instruction.spans.operands)));
     }
 
     loc
 }
 
+/// H, D and R records have room for 6 characters per name
+const MAX_NAME: usize = 6;
+
+fn check_external_names(names: &[String]) -> Result<(), Vec<Diagnostic>> {
+    let errs = names.iter()
+        .filter(|name| name.len() > MAX_NAME)
+        .map(|name| {
+            Diagnostic::error(Code::InvalidOperand,
+                              format!("external symbol `{}` is longer than {} characters",
+                                      name,
+                                      MAX_NAME))
+                .with_help("the object program can't hold longer names, rename the symbol"
+                    .to_owned())
+        })
+        .collect::<Vec<Diagnostic>>();
+    if errs.is_empty() { Ok(()) } else { Err(errs) }
+}
+
+/// The name of START or CSECT goes to the H record
+fn check_section_name(instruction: &Instruction) -> Result<(), Diagnostic> {
+    if instruction.label.len() > MAX_NAME {
+        return Err(Diagnostic::error(Code::InvalidLabel,
+                                     format!("section name `{}` is longer than {} characters",
+                                             instruction.label,
+                                             MAX_NAME))
+            .at_line(instruction.src_line_num)
+            .with_span(instruction.spans.label)
+            .with_help("the object program can't hold longer names, rename the section"
+                .to_owned()));
+    }
+    Ok(())
+}
+
 /// Gets the address of the first executable isntruction
-fn parse_end(instruction: &Instruction,
+fn parse_end(session: &Session,
+             instruction: &Instruction,
              prog: &mut RawProgram,
              end_instr_addr: i32)
-             -> Result<(), String> {
-    // TODO: change read_start to read boundary START/END
-    // or replace with is action directive
-    // FIXME: check for instructions after END
-    let operands = unwrap_to_vec(&instruction.operands);
-    let end_loc: i32;
-    if operands.len() != 0 {
-        if let Value::Raw(op_end) = operands[0].val {
-            // Will panic on negative value
-            end_loc = op_end as i32;
-        } else if let Value::Label(ref lbl) = operands[0].val {
-            match get_symbol_for_end(&lbl) {
-                Ok(addr) => end_loc = addr,
-                Err(e) => return Err(e),
-            }
-        } else {
-            return Err(format!("Invalid END operands, found {:?}", operands));
-        }
-    } else {
+             -> Result<(), Diagnostic> {
+    let end_loc = match instruction.statement {
+        Statement::End(Some(Expr::Symbol(ref lbl))) => get_symbol_for_end(session, lbl)?,
+        Statement::End(Some(ref expr)) => evaluate(session, expr, "", end_instr_addr)?.value,
         // End operand isn't specified, default: program start address
-        end_loc = prog.first_instruction_address as i32;
-    }
+        _ => prog.starting_address as i32,
+    };
 
-    prog.starting_address = end_loc as u32;
-    prog.program_length = (end_instr_addr - end_loc) as u32;
+    prog.first_instruction_address = end_loc as u32;
+    prog.program_length = (end_instr_addr - prog.starting_address as i32) as u32;
     Ok(())
 }
 
 
-fn parse_equ(instruction: &Instruction, csect: &str, loc: i32) -> Result<(), String> {
-    //get symbol value from Raw val inside operand
-    if let Value::Raw(val) = instruction.get_first_operand().val {
-        return define_local_symbol(&instruction.label, val as i32, csect);
-    } else if let Value::Label(ref lbl) = instruction.get_first_operand().val {
-        return match get_symbol(&lbl, csect) {
-            Ok(sym) => {
-                if sym.symbol_type == SymbolType::Imported {
-                    return Err(format!("{{ {} }} is not a local variable in {{ {} }}",
-                                       sym.get_name(),
-                                       csect));
-                }
-                define_local_symbol(&instruction.label, sym.get_address(), csect)
-            }
-            Err(e) => Err(e),
-        };
-    } else if let Value::Expression(ref exp) = instruction.get_first_operand().val {
-        let expression: meval::Expr = exp[0].parse().unwrap();
-        let mut context = meval::Context::new();
-        for term in &exp[1..] {
-            match get_symbol(&term, csect) {
-                Ok(sym) => {
-                    if sym.symbol_type == SymbolType::Imported {
-                        return Err(format!("{{ {} }} is not a local variable in {{ {} }}",
-                                           sym.get_name(),
-                                           csect));
-                    }
-                    context.var(term.as_str(), sym.get_address() as f64);
-                }
-                Err(_) => continue,
-            }
-        }
-        return match expression.eval_with_context(context) {
-            Ok(val) => define_local_symbol(&instruction.label, val as i32, csect),
-            Err(e) => Err(e.to_string()),
-        };
-    } else if let Value::Bytes(val) = instruction.get_first_operand().val {
-        if val.starts_with("X'") && val.ends_with("'") {
-            let val = &mut val.to_owned();
-            remove_literal_container(val);
-            match i32::from_str_radix(&val, 16) {
-                Ok(decimal) => return define_local_symbol(&instruction.label, decimal, csect),
-                Err(e) => Err(e.to_string()),
-            }
+/// Defines the label of EQU as the value of its operand, it's relative to
+/// the program block of the operand's relative terms
+fn parse_equ(session: &mut Session,
+             instruction: &Instruction,
+             expr: &Expr,
+             csect: &str,
+             loc: i32)
+             -> Result<(), Diagnostic> {
+    let result = evaluate(session, expr, csect, loc)?;
+    if let Some((_, name)) = result.externals.first() {
+        return Err(Diagnostic::error(Code::ImportedSymbolMisuse,
+                                     format!("`{}` is not a local variable in `{}`",
+                                             name,
+                                             csect)));
+    }
+    let block = expression_block(session, expr, csect, &instruction.block)?
+        .unwrap_or_else(|| instruction.block.clone());
+    let block = if result.relative { Some(block.as_str()) } else { None };
+    define_label_as(session, instruction, result.value, csect, block)
+}
+
+/// The new location counter of an ORG, None for a bare ORG that returns to
+/// where the location counter was before the ORG that moved it
+fn parse_org(session: &Session,
+             instruction: &Instruction,
+             expr: &Option<Expr>,
+             csect: &str,
+             loc: i32,
+             org_return: Option<i32>)
+             -> Result<Option<i32>, Diagnostic> {
+    let expr = match *expr {
+        Some(ref expr) => expr,
+        None if org_return.is_some() => return Ok(None),
+        None => {
+            return Err(Diagnostic::error(Code::InvalidOperand,
+                                         "ORG without an operand has nothing to return to"
+                                             .to_owned())
+                .with_help("a bare ORG restores the location counter after `ORG address`"
+                    .to_owned()))
         }
-        else if val == String::from("*"){
-                return define_local_symbol(&instruction.label, loc, csect)
+    };
+
+    let result = evaluate(session, expr, csect, loc)?;
+    if let Some((_, name)) = result.externals.first() {
+        return Err(Diagnostic::error(Code::ImportedSymbolMisuse,
+                                     format!("`{}` is not a local variable in `{}`", name, csect)));
+    }
+    if !result.relative || result.value < 0 {
+        return Err(invalid_org_operand()
+            .with_note(format!("the operand is the value {}, not an address", result.value)));
+    }
+    match expression_block(session, expr, csect, &instruction.block)? {
+        Some(ref block) if *block != instruction.block => {
+            Err(invalid_org_operand().with_note(format!("the address is in the program block \
+                                                         `{}`, ORG can't leave its block",
+                                                        block)))
         }
-        else {
-            return Err(format!("Invalid EQU operands, found {:?}",
-                               unwrap_to_vec(&instruction.operands)));
+        _ => Ok(Some(result.value)),
+    }
+}
+
+/// The program block of the relative terms of an expression evaluated in
+/// pass one, the terms have to be in one block as the distance between two
+/// blocks isn't known before they're laid out
+fn expression_block(session: &Session,
+                    expr: &Expr,
+                    csect: &str,
+                    current: &str)
+                    -> Result<Option<String>, Diagnostic> {
+    let mut terms: Vec<(String, String)> = Vec::new();
+    if expr.uses_locctr() {
+        terms.push(("*".to_owned(), current.to_owned()));
+    }
+    for name in expr.symbols() {
+        if let Ok(sym) = session.symbols.get_symbol(name, csect) {
+            if sym.symbol_type == SymbolType::Local && sym.symbol.is_relative() {
+                terms.push((name.to_owned(), sym.symbol.get_block()));
+            }
         }
     }
-    //TODO: is there other cases?
-    else {
-        return Err(format!("Invalid EQU operands, found {:?}",
-                           unwrap_to_vec(&instruction.operands)));
+
+    if let Some(other) = terms.iter().find(|term| term.1 != terms[0].1) {
+        return Err(Diagnostic::error(Code::IllegalRelative,
+                                     format!("`{}` and `{}` are in different program blocks",
+                                             terms[0].0,
+                                             other.0))
+            .with_help("the blocks are laid out after pass one, use terms of a single block"
+                .to_owned()));
     }
+    Ok(terms.into_iter().next().map(|(_, block)| block))
 }
 
-
-fn parse_start(instruction: &Instruction, prog: &mut RawProgram) -> Result<i32, String> {
+fn parse_start(session: &mut Session,
+               instruction: &Instruction,
+               prog: &mut RawProgram)
+               -> Result<i32, Diagnostic> {
 
     // Duplicate start instruction
-    if instruction.mnemonic.to_uppercase() != "START" {
-        return Err(format!("Program must have its first instrution as START"));
-    } else if !prog.program_name.is_empty() {
-        return Err(format!("Invalid START instruction {:?} , old prog name: {}",
-                           instruction,
-                           prog.program_name));
+    let start_addr = match instruction.statement {
+        Statement::Start(address) => address,
+        _ => {
+            return Err(Diagnostic::error(Code::MissingStart,
+                                         "program must have START as its first instruction"
+                                             .to_owned())
+                .at_line(instruction.src_line_num)
+                .with_span(instruction.spans.mnemonic))
+        }
+    };
+    if !prog.program_name.is_empty() {
+        return Err(Diagnostic::error(Code::DuplicateStart,
+                                     format!("duplicate START directive, the program is \
+                                              already named `{}`",
+                                             prog.program_name))
+            .at_line(instruction.src_line_num)
+            .with_span(instruction.spans.mnemonic));
     } else if instruction.label.is_empty() {
-        return Err(format!("Program doesn't have a name specified in START"));
-    }
-
-    let start_addr: u32;
-    if let Value::Raw(adr) = instruction.get_first_operand().val {
-        start_addr = adr as u32;
-    } else {
-        start_addr = 0;
+        return Err(Diagnostic::error(Code::MissingProgramName,
+                                     "program doesn't have a name specified in START"
+                                         .to_owned())
+            .at_line(instruction.src_line_num)
+            .with_span(instruction.spans.mnemonic)
+            .with_help("write the name of the program as the label of START".to_owned()));
     }
+    check_section_name(instruction)?;
 
     prog.program_name = instruction.label.clone();
-    prog.first_instruction_address = start_addr;
+    prog.starting_address = start_addr;
+    prog.sections.push(ControlSection::new(&instruction.label, "", start_addr));
 
     // Program name goes to sym_tab
-    if let Err(e) = define_local_symbol(&instruction.label, start_addr as i32, &String::new()) {
+    if let Err(e) = define_label(session, instruction, start_addr as i32, "") {
         // Add prog name to symtab
-        return Err(format!("{}", e));
+        return Err(e.at_line(instruction.src_line_num).with_span(instruction.spans.label));
     }
 
     Ok(start_addr as i32)
 }
 
 
-fn create_from_literal(lit: &String, locctr: i32) -> Box<Instruction> {
+fn create_from_literal(literals: &mut LiteralTable, lit: &str, locctr: i32) -> Box<Instruction> {
 
-    insert_literal(lit, locctr as u32);
-    let literal: Literal = get_literal(lit).unwrap();
+    literals.insert_literal(lit, locctr as u32);
+    let literal: Literal = literals.get_literal(lit).unwrap();
 
     // Ad the literal definition, as normal byte/word
-    let operand = AsmOperand::new(OperandType::Bytes,
-                                  Value::Bytes(literal.external_name[1..].to_owned()));
+    let constant = &literal.external_name[1..];
+    let operand = AsmOperand::new(OperandType::Bytes, Value::Bytes(constant.to_owned()));
+    let bytes = constant_bytes(constant).unwrap_or_default();
 
     let mut lit_instr =
-        Instruction::new(literal.label, "BYTE".to_owned(), UnitOrPair::Unit(operand));
+        Instruction::new(literal.label, Statement::Byte(bytes), UnitOrPair::Unit(operand));
 
     lit_instr.locctr = literal.address as i32;
     Box::new(lit_instr)
 }
 
-pub fn get_symbol_for_end(symbol: &str) -> Result<i32, String> {
+pub fn get_symbol_for_end(session: &Session, symbol: &str) -> Result<i32, Diagnostic> {
     // Used with the END instruction only
-    match symbol_tables::get_symbol(symbol, &String::new()) {
+    match session.symbols.get_symbol(symbol, "") {
         Ok(sym) => {
             if sym.symbol_type == SymbolType::Imported {
-                return Err(format!("Can't use an Imported symbol with END"));
+                return Err(Diagnostic::error(Code::ImportedSymbolMisuse,
+                                             format!("END can't use the imported symbol `{}`",
+                                                     symbol)));
             }
             Ok(sym.symbol.get_address())
         }
-        Err(_) => Err(format!("Couldn't find symbol {{ {} }} for END instruction", symbol)),
+        Err(e) => Err(e),
     }
 
 }
 
-pub fn get_all_symbols() -> HashSet<Symbol> {
-    let mut result: HashSet<Symbol> = HashSet::new();
-    for val in symbol_tables::get_all_symbols() {
-        result.insert(val.clone());
-    }
+pub fn get_all_symbols(session: &Session) -> HashSet<Symbol> {
+    session.symbols.get_all_symbols()
+}
+
+pub fn get_csects_info(session: &Session) -> Vec<String> {
+    session.symbols.get_all_section_tables()
+}
+
+/// Defines the label of an instruction, remembering where it's in the source,
+/// labels are addresses so they're relative to their control section
+fn define_label(session: &mut Session,
+                instruction: &Instruction,
+                addr: i32,
+                csect: &str)
+                -> Result<(), Diagnostic> {
+    define_label_as(session, instruction, addr, csect, Some(&instruction.block))
+}
 
-    result
+/// Defines the label of an instruction, it's relative to the given program
+/// block or absolute without one, ex. EQU with a constant value
+fn define_label_as(session: &mut Session,
+                   instruction: &Instruction,
+                   addr: i32,
+                   csect: &str,
+                   block: Option<&str>)
+                   -> Result<(), Diagnostic> {
+    let mut sym = Symbol::new(&instruction.label, addr, csect)
+        .defined_at(instruction.src_line_num, instruction.spans.label);
+    if let Some(block) = block {
+        sym = sym.in_block(block);
+        sym.mark_relative();
+    }
+    session.symbols.define_symbol(sym)
 }
 
-pub fn get_csects_info() -> Vec<String> {
-    symbol_tables::get_all_section_tables()
+/// Literals are queued for the next literal pool (LTORG / end of program)
+/// when pass one first meets them
+fn register_literals(session: &mut Session, instruction: &Instruction) {
+    for operand in instruction.unwrap_operands() {
+        if let Value::Bytes(ref lit) = operand.val {
+            // A literal already placed by an LTORG is used from that pool
+            if lit.starts_with('=') && session.literals.get_literal(lit).is_none() {
+                session.literals.insert_unresolved(lit);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -418,11 +708,203 @@ mod tests {
     use super::*;
     #[test]
     fn test_literal_def() {
-        let instr: Instruction = *create_from_literal(&("=C'BOX'".to_owned()), 1025);
+        let mut literals = LiteralTable::new();
+        let instr: Instruction = *create_from_literal(&mut literals, "=C'BOX'", 1025);
         println!("{:?}", instr);
-        assert_eq!(instr.mnemonic, "BYTE");
+        assert_eq!(instr.statement, Statement::Byte(b"BOX".to_vec()));
         assert_eq!(instr.locctr, 1025);
-        assert_eq!(instr.get_first_operand().val,
+        assert_eq!(instr.first_value(),
                    Value::Bytes(("C'BOX'".to_owned())));
     }
+
+    #[test]
+    fn statements_after_end_are_ignored() {
+        use filehandler::FileHandler;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/after_end.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let (_, prog) = pass_one(&mut session, prog).unwrap();
+
+        assert!(prog.program.iter().all(|(_, instr)| instr.label != "LATE"));
+        assert_eq!(prog.program_length, 9);
+        assert_eq!(session.warnings.len(), 1);
+        assert_eq!(session.warnings[0].code, Code::StatementAfterEnd);
+        assert_eq!(session.warnings[0].line, 6);
+        assert_eq!(session.warnings[0].notes[0].line, Some(5));
+    }
+
+    #[test]
+    fn external_names_fit_in_records() {
+        use filehandler::FileHandler;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/long_extdef.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let errs = pass_one(&mut session, prog).unwrap_err();
+
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].code, Code::InvalidOperand);
+        assert_eq!(errs[0].line, 2);
+        assert_eq!(errs[0].message, "external symbol `BUFFEND` is longer than 6 characters");
+    }
+
+    #[test]
+    fn section_names_fit_in_records() {
+        use filehandler::FileHandler;
+
+        let source = "PROGRAM  START   0\n\
+                      FIRST    RSUB\n\
+                      SECTION2 CSECT\n\
+                      \x20        RSUB\n\
+                      \x20        END     FIRST\n";
+        let mut file = FileHandler::from_reader("<test>".to_owned(), source.as_bytes());
+        let prog = file.parse_file().unwrap();
+        let errs = pass_one(&mut Session::new(), prog).unwrap_err();
+
+        let found = errs.iter()
+            .map(|e| (e.code, e.line, e.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(found,
+                   vec![(Code::InvalidLabel, 1,
+                         "section name `PROGRAM` is longer than 6 characters"),
+                        (Code::InvalidLabel, 3,
+                         "section name `SECTION2` is longer than 6 characters")]);
+    }
+
+    #[test]
+    fn expressions_are_typed() {
+        use filehandler::FileHandler;
+        use pass_two::translator::pass_two;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/expressions.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let (_, mut prog) = pass_one(&mut session, prog).unwrap();
+        assert!(pass_two(&mut session, &mut prog).is_empty());
+        assert_eq!(prog.all_records().lines().nth(1).unwrap(),
+                   "T000000120320034F000000001E00000F00002D004143");
+
+        let symbol = |name: &str| {
+            let sym = session.symbols.get_symbol(name, "").unwrap();
+            (sym.get_address(), sym.symbol.is_relative())
+        };
+        assert_eq!(symbol("TABEND"), (0x30, true));
+        assert_eq!(symbol("SIZE"), (10, false));
+        assert_eq!(symbol("PREV"), (0x2D, true));
+        assert_eq!(symbol("SECOND"), (3, true));
+    }
+
+    #[test]
+    fn org_overlays_a_table() {
+        use filehandler::FileHandler;
+        use pass_two::translator::pass_two;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/org.asm".to_owned()).unwrap().parse_file().unwrap();
+        let (_, mut prog) = pass_one(&mut session, prog).unwrap();
+        assert!(pass_two(&mut session, &mut prog).is_empty());
+
+        let address = |name: &str| session.symbols.get_symbol(name, "").unwrap().get_address();
+        assert_eq!(address("SYMBOL"), address("STAB"));
+        assert_eq!(address("VALUE"), 0x0F);
+        assert_eq!(address("TOTAL"), 0x455);
+        assert_eq!(address("NAME"), 0x0A);
+        // The length comes from the highest address, not the last one
+        assert_eq!(prog.program_length, 0x458);
+
+        let records = prog.all_records();
+        let texts = records.lines().filter(|record| record.starts_with('T')).collect::<Vec<_>>();
+        assert_eq!(texts,
+                   vec!["T0000000905000003A0094F0000", "T00000A05414C504841", "T00045503000005"]);
+    }
+
+    #[test]
+    fn org_needs_an_address() {
+        use filehandler::FileHandler;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/org_errors.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let errs = pass_one(&mut session, prog).unwrap_err();
+
+        assert_eq!(errs.iter().map(|e| (e.code, e.line)).collect::<Vec<_>>(),
+                   vec![(Code::InvalidOperand, 2), (Code::InvalidOperand, 4)]);
+    }
+
+    #[test]
+    fn program_blocks() {
+        use filehandler::FileHandler;
+        use pass_two::translator::pass_two;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/blocks.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let (_, mut prog) = pass_one(&mut session, prog).unwrap();
+        assert!(pass_two(&mut session, &mut prog).is_empty());
+
+        // The default block, then CDATA and CBLKS in the order they're first used
+        let symbol = |name: &str| {
+            let sym = session.symbols.get_symbol(name, "").unwrap().symbol;
+            (sym.get_address(), sym.get_block())
+        };
+        assert_eq!(symbol("WRREC"), (0x4D, String::new()));
+        assert_eq!(symbol("RETADR"), (0x66, "CDATA".to_owned()));
+        assert_eq!(symbol("INPUT"), (0x6C, "CDATA".to_owned()));
+        assert_eq!(symbol("BUFFER"), (0x71, "CBLKS".to_owned()));
+        assert_eq!(symbol("BUFEND"), (0x1071, "CBLKS".to_owned()));
+        assert_eq!(session.symbols.get_symbol("MAXLEN", "").unwrap().get_address(), 4096);
+        assert_eq!(session.literals.get_literal("=C'EOF'").unwrap().address, 0x6D);
+        assert_eq!(prog.program_length, 0x1071);
+
+        assert_eq!(prog.all_records(),
+                   "HCOPY  000000001071\n\
+                    T0000001E1720634B20210320602900003320064B203B3F2FEE0320550F2056010003\n\
+                    T00001E1E0F20484B20293E203FB410B400B44075101000E32038332FFADB2032A004\n\
+                    T00003C1C33200857A02FB8503B2FEA13201F4F0000B410772017E3201B332FFA\n\
+                    T0000580E53A016DF2012B8503B2FEF4F0000\n\
+                    T00006C05F1454F4605\n\
+                    E000000");
+    }
+
+    #[test]
+    fn blocks_are_apart_in_pass_one() {
+        use filehandler::FileHandler;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/block_errors.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let errs = pass_one(&mut session, prog).unwrap_err();
+
+        assert_eq!(errs.iter().map(|e| (e.code, e.line)).collect::<Vec<_>>(),
+                   vec![(Code::IllegalRelative, 6), (Code::InvalidOperand, 7)]);
+    }
+
+    #[test]
+    fn relative_terms_do_not_add_up() {
+        use filehandler::FileHandler;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/relative_sum.asm".to_owned())
+            .unwrap()
+            .parse_file()
+            .unwrap();
+        let errs = pass_one(&mut session, prog).unwrap_err();
+
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].code, Code::IllegalRelative);
+        assert_eq!(errs[0].line, 4);
+    }
 }
@@ -8,3 +8,4 @@
 pub mod tests;
 pub mod translator;
 pub mod operand_translator;
+pub mod relocation;
@@ -2,55 +2,91 @@ use instruction::Instruction;
 use basic_types::formats::Format;
 use basic_types::operands::Value;
 use basic_types::register::Register;
-use symbol_tables::get_symbol;
-use base_table::get_base_at;
 use symbol::SymbolType;
 use regex::Regex;
-use literal_table::get_literal;
-use super::super::{to_hex_string, remove_literal_container};
-
-pub fn parse_operand(instruction: &mut Instruction, val: &Value) -> Result<String, String> {
-    match *val {
+use session::Session;
+use expression::{evaluate, Expr};
+use diagnostic::{Diagnostic, Code};
+use super::super::{to_hex_string, remove_literal_container, char_constant};
+
+pub fn parse_operand(session: &Session,
+                     instruction: &mut Instruction,
+                     val: &Value)
+                     -> Result<String, Diagnostic> {
+    let line = instruction.src_line_num;
+    let span = instruction.spans.operands;
+    let result = match *val {
         Value::None |
         Value::VarArgs(_) => Ok(String::new()),
         Value::Raw(x) => Ok(to_hex_string(x)),
-        Value::SignedInt(x) => parse_signed_int(x),
+        Value::SignedInt(x) => parse_signed_int(x, instruction.get_format()),
         Value::Register(ref x) => parse_register(*x),
-        Value::Label(ref lbl) => parse_label(instruction, lbl),
-        Value::Bytes(ref text) => parse_bytes(instruction, text),
-        Value::Expression(_) => Err(String::from("Expression should be resolved by now"))
+        Value::Label(ref lbl) => parse_label(session, instruction, lbl),
+        Value::Bytes(ref text) => parse_bytes(session, instruction, text),
+        Value::Expression(ref expr) => parse_expression(session, instruction, expr),
+    };
+    result.map_err(|e| e.at_line(line).or_span(span))
+}
+
+/// The value of a WORD, EXTREF symbols count as 0 and are filled in by the loader
+pub fn word_value(session: &Session,
+                  instruction: &Instruction,
+                  expr: &Expr)
+                  -> Result<String, Diagnostic> {
+    match evaluate(session, expr, &instruction.csect, instruction.locctr) {
+        Ok(result) => Ok(format!("{:06X}", result.value & 0xFFFFFF)),
+        Err(e) => Err(e.at_line(instruction.src_line_num).or_span(instruction.spans.operands)),
     }
 }
 
-fn parse_register(operand: Register) -> Result<String, String> {
+fn parse_register(operand: Register) -> Result<String, Diagnostic> {
     let reg_num = operand as u8;
     Ok(to_hex_string(reg_num as u32))
 }
 
-fn parse_signed_int(x: i32) -> Result<String, String> {
-    if x > 0x7FFFFF {
-        return Err("Value out of 23-bit range".to_string());
+fn parse_signed_int(x: i32, format: Format) -> Result<String, Diagnostic> {
+    // The address field of format 3/4 holds negative numbers in two's complement
+    let bits = match format {
+        Format::Three => 12,
+        Format::Four => 20,
+        _ => 23,
+    };
+    if x >= 1 << bits || (format != Format::None && x < -(1 << (bits - 1))) {
+        return Err(Diagnostic::error(Code::ValueOutOfRange,
+                                     format!("immediate value {} is out of the {}-bit range",
+                                             x,
+                                             bits)));
+    }
+    match format {
+        Format::Three | Format::Four => Ok(to_hex_string(x & ((1 << bits) - 1))),
+        _ => Ok(to_hex_string(x)),
     }
-    Ok(to_hex_string(x))
 }
 
-fn parse_label(instruction: &mut Instruction, lbl: &str) -> Result<String, String> {
+fn parse_label(session: &Session,
+               instruction: &mut Instruction,
+               lbl: &str)
+               -> Result<String, Diagnostic> {
     let sym_addr;
-    match get_symbol(&lbl.to_owned(), &instruction.csect) {
+    let relative;
+    match session.symbols.get_symbol(lbl, &instruction.csect) {
         Ok(sym) => {
             if sym.symbol_type == SymbolType::Imported &&
                instruction.get_format() == Format::Three {
-                return Err(format!("{{ {:?} }} : Imported symbols must be in format 4 \
-                                    instructions",
-                                   instruction));
+                return Err(Diagnostic::error(Code::ImportedSymbolMisuse,
+                                             format!("imported symbol `{}` can only be used by \
+                                                      format 4 instructions",
+                                                     lbl))
+                    .with_help(format!("use `+{}` instead", instruction.mnemonic())));
             }
 
-            sym_addr = sym.get_address()
+            sym_addr = sym.get_address();
+            relative = sym.symbol.is_relative();
         }
         Err(e) => return Err(e),
     }
 
-    match get_disp(instruction, sym_addr) {
+    match get_disp(session, instruction, sym_addr, relative) {
         Ok(addr) => return Ok(addr),
         Err(e) => {
             return Err(e);
@@ -58,16 +94,35 @@ fn parse_label(instruction: &mut Instruction, lbl: &str) -> Result<String, Strin
     };
 }
 
-fn parse_bytes(instruction: &mut Instruction, text: &str) -> Result<String, String> {
+/// An expression of a format 3/4 operand, ex. `TABLE+3` or `#MAXLEN*2`,
+/// EXTREF terms count as 0 and are filled in by the loader
+fn parse_expression(session: &Session,
+                    instruction: &mut Instruction,
+                    expr: &Expr)
+                    -> Result<String, Diagnostic> {
+    let result = evaluate(session, expr, &instruction.csect, instruction.locctr)?;
+
+    if let Some((_, name)) = result.externals.first() {
+        if instruction.get_format() == Format::Three {
+            return Err(Diagnostic::error(Code::ImportedSymbolMisuse,
+                                         format!("imported symbol `{}` can only be used by \
+                                                  format 4 instructions",
+                                                 name))
+                .with_help(format!("use `+{}` instead", instruction.mnemonic())));
+        }
+    }
+
+    get_disp(session, instruction, result.value, result.relative)
+}
+
+fn parse_bytes(session: &Session,
+               instruction: &mut Instruction,
+               text: &str)
+               -> Result<String, Diagnostic> {
     if text.starts_with("=") {
         // Return the address of the literal, not its value
-        let sym_addr = get_literal(text).unwrap().address as i32;
-        match get_disp(instruction, sym_addr) {
-            Ok(addr) => Ok(addr),
-            Err(e) => {
-                return Err(format!("{}", e.to_string()));
-            }
-        }
+        let sym_addr = session.literals.get_literal(text).unwrap().address as i32;
+        get_disp(session, instruction, sym_addr, true)
     } else {
         Ok(translate_literal(text))
     }
@@ -82,13 +137,10 @@ pub fn translate_literal(literal: &str) -> String {
         let captures = HEX_REGEX.captures(literal).unwrap();
         let mut operand_match: String = captures.get(0).unwrap().as_str().to_owned();
         remove_literal_container(&mut operand_match);
-        return operand_match;
+        operand_match
     } else if literal.starts_with('C') || literal.starts_with('c') {
         let captures = STR_REGEX.captures(literal).unwrap();
-        let mut operand_match: String = captures.get(0).unwrap().as_str().to_owned();
-        remove_literal_container(&mut operand_match);
-
-        return parse_str_operand(operand_match);
+        parse_str_operand(char_constant(captures.get(0).unwrap().as_str()))
     } else {
         panic!("Invalid literal to translate {}, expected C|X'...' ",
                literal);
@@ -104,18 +156,31 @@ fn parse_str_operand(operand_match: String) -> String {
         .join("")
 }
 
-fn get_disp(instruction: &mut Instruction, sym_addr: i32) -> Result<String, String> {
+/// The address field of a format 3/4 instruction, relative targets move with
+/// the section and are addressed from the PC or the base, absolute ones that
+/// fit in 12 bits are used as they are
+fn get_disp(session: &Session,
+            instruction: &mut Instruction,
+            sym_addr: i32,
+            relative: bool)
+            -> Result<String, Diagnostic> {
 
     if instruction.get_format() == Format::Four {
-        if sym_addr > 0xFFFFF {
-            return Err("Address is out of 20-bit range".to_owned());
+        if !(-0x80000..=0xFFFFF).contains(&sym_addr) {
+            return Err(Diagnostic::error(Code::AddressOutOfRange,
+                                         format!("address {:#X} is out of the 20-bit range",
+                                                 sym_addr)));
         }
-        return Ok(to_hex_string(sym_addr & 0xFFFF));
+        return Ok(to_hex_string(sym_addr & 0xFFFFF));
+    }
+
+    if !relative && (0..4096).contains(&sym_addr) {
+        return Ok(to_hex_string(sym_addr));
     }
 
     let final_disp: i32;
     let disp: i32 = sym_addr - (instruction.locctr + instruction.get_format() as i32);
-    let base = get_base_at(instruction.locctr as u32);
+    let base = session.bases.get_base_at(instruction.locctr as u32);
 
     // PC relative is invalid
     if -2048 <= disp && disp < 2048 {
@@ -133,33 +198,41 @@ fn get_disp(instruction: &mut Instruction, sym_addr: i32) -> Result<String, Stri
             final_disp = disp & 0xFFF;
 
         } else {
-            return Err(format!("Address is out of base relative range disp:{:#X} base:{:#X} \
-                                symbol addr:{:#X} , Instruction:{:?}",
-                               disp,
-                               base,
-                               sym_addr,
-                               instruction));
+            return Err(Diagnostic::error(Code::AddressOutOfRange,
+                                         format!("target address {:#X} is out of the base \
+                                                  relative range",
+                                                 sym_addr))
+                .with_note(format!("the base is {:#X}, the displacement would be {:#X}",
+                                   base,
+                                   disp))
+                .with_help(format!("use `+{}` to address it directly", instruction.mnemonic())));
         }
 
     } else {
-        return Err(format!("Address is out of PC relative range and no base is specified, \
-                            Displacement:{:#X} Target Address:{:#X} {} Loc:{:#X}",
-                           disp,
-                           sym_addr,
-                           instruction.mnemonic,
-                           instruction.locctr));
+        return Err(Diagnostic::error(Code::AddressOutOfRange,
+                                     format!("target address {:#X} is out of the PC relative \
+                                              range and no base is specified",
+                                             sym_addr))
+            .with_note(format!("the displacement from {:#X} would be {:#X}",
+                               instruction.locctr,
+                               disp))
+            .with_help(format!("use `+{}` or set a base with `BASE`", instruction.mnemonic())));
     }
 
-    panic_on_memory_limit(final_disp, instruction.locctr);
+    check_memory_limit(final_disp, instruction.locctr)?;
     return Ok(to_hex_string(final_disp));
 }
 
 
 
-fn panic_on_memory_limit(disp: i32, locctr: i32) {
-    if disp + locctr >= (1 << 20) {
-        panic!("Out of range address {}", disp + locctr)
+fn check_memory_limit(disp: i32, locctr: i32) -> Result<(), Diagnostic> {
+    let address = disp as i64 + locctr as i64;
+    if address >= (1 << 20) {
+        return Err(Diagnostic::error(Code::AddressOutOfRange,
+                                     format!("address {:#X} is out of the 1 MB memory",
+                                             address)));
     }
+    Ok(())
 }
 
 lazy_static!{
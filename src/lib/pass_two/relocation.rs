@@ -0,0 +1,160 @@
+/// Finds the fields of the object code a linking loader has to modify,
+/// addresses of the section's own labels are relocated by the section's
+/// load address and references to EXTREF symbols get the symbol's address
+use instruction::Instruction;
+use statement::Statement;
+use formats::Format;
+use operands::Value;
+use session::Session;
+use htme::modification::Modification;
+use expression::{evaluate, Expr};
+
+/// The modifications of a translated instruction, section is the name of
+/// the control section the instruction is in
+pub fn modifications(session: &Session, instr: &Instruction, section: &str) -> Vec<Modification> {
+    let address = instr.locctr as u32;
+    if let Statement::Word(ref expr) = instr.statement {
+        // The whole word is the value, 6 half bytes
+        return expression_modifications(session, instr, expr, address, 6, section);
+    }
+
+    let operand = match instr.unwrap_operands().into_iter().next() {
+        Some(operand) => operand.val,
+        None => return Vec::new(),
+    };
+
+    if instr.get_format() != Format::Four {
+        // Format 3 uses PC/base relative displacements, they don't move
+        return Vec::new();
+    }
+
+    // The 20-bit address field starts in the middle of the second byte
+    match operand {
+        Value::Label(lbl) => {
+            expression_modifications(session, instr, &Expr::Symbol(lbl), address + 1, 5, section)
+        }
+        Value::Expression(ref expr) => {
+            expression_modifications(session, instr, expr, address + 1, 5, section)
+        }
+        Value::Bytes(ref lit) if lit.starts_with('=') => {
+            vec![Modification::new(&instr.csect, address + 1, 5, section)]
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn expression_modifications(session: &Session,
+                            instr: &Instruction,
+                            expr: &Expr,
+                            address: u32,
+                            half_bytes: u8,
+                            section: &str)
+                            -> Vec<Modification> {
+    // Invalid expressions are reported by the translation of the instruction
+    let result = match evaluate(session, expr, &instr.csect, instr.locctr) {
+        Ok(result) => result,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut mods: Vec<Modification> = result.externals
+        .iter()
+        .map(|&(negative, ref symbol)| {
+            let modification = Modification::new(&instr.csect, address, half_bytes, symbol);
+            if negative { modification.negated() } else { modification }
+        })
+        .collect();
+
+    // A relative value moves with the section, absolute ones stay put
+    if result.relative {
+        mods.push(Modification::new(&instr.csect, address, half_bytes, section));
+    }
+
+    mods
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use unit_or_pair::UnitOrPair;
+    use instruction::AsmOperand;
+    use operands::OperandType;
+    use instruction_set::Mnemonic;
+    use symbol::Symbol;
+    use expression::parse;
+
+    fn session() -> Session {
+        let mut session = Session::new();
+        let mut buffer = Symbol::new("BUFFER", 0x33, "");
+        buffer.mark_relative();
+        session.symbols.define_symbol(buffer).unwrap();
+        session.symbols.define_local_symbol("BUFEND", 0x1033, "").unwrap();
+        session.symbols.define_local_symbol("MAXLEN", 4096, "").unwrap();
+        session.symbols.define_control_section("RDREC").unwrap();
+        session.symbols.define_imported_symbols(&["BUFFER".to_owned(), "BUFEND".to_owned()],
+                                      "RDREC")
+            .unwrap();
+        session
+    }
+
+    fn instruction(mnemonic: &str, val: Value, format: Format, csect: &str) -> Instruction {
+        let operands = UnitOrPair::Unit(AsmOperand::new(OperandType::Label, val));
+        let statement = Statement::parse(Mnemonic::parse(mnemonic).unwrap(), &operands).unwrap();
+        let mut instr = Instruction::new(String::new(), statement, operands);
+        instr.set_format(format);
+        instr.csect = csect.to_owned();
+        instr.locctr = 0x10;
+        instr
+    }
+
+    #[test]
+    fn format_four_relocation() {
+        let session = session();
+        let local = instruction("JSUB", Value::Label("BUFFER".to_owned()), Format::Four, "");
+        let absolute = instruction("LDT", Value::Label("MAXLEN".to_owned()), Format::Four, "");
+        let format_three = instruction("JSUB", Value::Label("BUFFER".to_owned()), Format::Three, "");
+
+        assert_eq!(modifications(&session, &local, "COPY"),
+                   vec![Modification::new("", 0x11, 5, "COPY")]);
+        assert!(modifications(&session, &absolute, "COPY").is_empty());
+        assert!(modifications(&session, &format_three, "COPY").is_empty());
+    }
+
+    #[test]
+    fn external_references() {
+        let session = session();
+        let external = instruction("STCH", Value::Label("BUFFER".to_owned()), Format::Four, "RDREC");
+        let word = instruction("WORD",
+                               Value::Expression(parse("BUFEND-BUFFER").unwrap()),
+                               Format::None,
+                               "RDREC");
+
+        assert_eq!(modifications(&session, &external, "RDREC")[0].record(),
+                   "M00001105+BUFFER");
+        let records = modifications(&session, &word, "RDREC")
+            .iter()
+            .map(|modification| modification.record())
+            .collect::<Vec<String>>();
+        assert_eq!(records, vec!["M00001006+BUFEND", "M00001006-BUFFER"]);
+    }
+
+    #[test]
+    fn relative_terms_cancel_out() {
+        let mut session = session();
+        let mut end = Symbol::new("END", 0x1033, "");
+        end.mark_relative();
+        session.symbols.define_symbol(end).unwrap();
+
+        let length = instruction("WORD",
+                                 Value::Expression(parse("END-BUFFER").unwrap()),
+                                 Format::None,
+                                 "");
+        let address = instruction("WORD",
+                                  Value::Expression(parse("END-BUFFER+BUFFER").unwrap()),
+                                  Format::None,
+                                  "");
+
+        assert!(modifications(&session, &length, "COPY").is_empty());
+        assert_eq!(modifications(&session, &address, "COPY"),
+                   vec![Modification::new("", 0x10, 6, "COPY")]);
+    }
+}
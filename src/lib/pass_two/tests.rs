@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod tests {
     use instruction::{Instruction, AsmOperand};
+    use instruction_set::Opcode;
+    use statement::Statement;
     use operands::{OperandType, Value};
     use formats::Format;
     use unit_or_pair::UnitOrPair;
@@ -9,7 +11,7 @@ mod tests {
     fn flag_resolution() {
         let mut instr: Instruction =
             Instruction::new(String::new(),
-                             "JSUB".to_owned(),
+                             Statement::Instruction(Opcode::Jsub),
                              UnitOrPair::Unit(AsmOperand::new(OperandType::Immediate,
                                                               Value::SignedInt(0x1036))));
 
@@ -1,58 +1,73 @@
 use super::super::string_from_object_code;
 use instruction::Instruction;
-use operands::Value;
-use instruction_set::{self, AssemblyDef, is_base_mode_directive, is_decodable_directive};
+use statement::Statement;
 use semantics_validator;
-use base_table::{set_base, end_base};
-use symbol_tables::get_symbol;
 use symbol::SymbolType;
-use pass_two::operand_translator::parse_operand;
+use session::Session;
+use diagnostic::{Diagnostic, Code};
+use pass_two::operand_translator::{parse_operand, word_value};
+use pass_two::relocation::modifications;
 use std::u32;
 
 
 use super::super::RawProgram;
 
 /// Returns the errors
-pub fn pass_two(prog: &mut RawProgram) -> Vec<String> {
+pub fn pass_two(session: &mut Session, prog: &mut RawProgram) -> Vec<Diagnostic> {
 
-    let mut errs: Vec<String> = Vec::new();
+    let mut errs: Vec<Diagnostic> = Vec::new();
+    let mut mods = Vec::new();
 
     for &mut (ref mut obj_code, ref mut instr) in prog.program.iter_mut() {
-        // TODO: add obj code
-        match translate(instr) {
-            Ok(obj) => *obj_code = obj,
-            Err(e) => errs.push(e),
+        match translate(session, instr) {
+            Ok(obj) => {
+                *obj_code = obj;
+                let section = if instr.csect.is_empty() {
+                    &prog.program_name
+                } else {
+                    &instr.csect
+                };
+                mods.extend(modifications(session, instr, section));
+            }
+            Err(e) => errs.extend(e),
         }
     }
 
+    prog.modifications = mods;
     errs
 }
 
-fn translate(instruction: &mut Instruction) -> Result<String, String> {
+fn translate(session: &mut Session,
+             instruction: &mut Instruction)
+             -> Result<String, Vec<Diagnostic>> {
 
-    let mut errs: Vec<String> = Vec::new();
+    let mut errs: Vec<Diagnostic> = Vec::new();
 
     if let Err(e) = semantics_validator::validate_semantics(instruction) {
-        errs.push(format!("Semantic Error(s): {} \n {:?} \n\n", e, instruction));
+        errs.extend(e);
     }
 
-    // Resolve operands first, in case of a directive, this function will return early
-    if is_base_mode_directive(&instruction.mnemonic).is_some() {
-        // Add the base entry
-        match resolve_base_directive(instruction) {
-            Ok(_) => return Ok(String::new()),
-            Err(e) => return Err(e),
+    // Directives are done here, they don't have an opcode
+    match instruction.statement {
+        Statement::Instruction(_) => {}
+        Statement::Base(_) | Statement::Nobase => {
+            // Add the base entry
+            return resolve_base_directive(session, instruction)
+                .map(|_| String::new())
+                .map_err(|e| vec![e]);
+        }
+        Statement::Word(ref expr) => {
+            return word_value(session, instruction, expr).map_err(|e| vec![e]);
         }
+        Statement::Byte(ref bytes) => {
+            return Ok(bytes.iter().map(|byte| format!("{:02X}", byte)).collect());
+        }
+        _ => return Ok(String::new()),
     }
 
-    let raw_operands: Result<String, String>;
-    raw_operands = resolve_incomplete_operands(instruction);
+    let raw_operands: Result<String, Vec<Diagnostic>> =
+        resolve_incomplete_operands(session, instruction);
 
-    if is_decodable_directive(&instruction.mnemonic) {
-        return raw_operands;
-    } else if is_directive(instruction) && !is_decodable_directive(&instruction.mnemonic) {       
-        return Ok(String::new());
-    }
     // Assemble the instruciton
     // Operand field in the hex code
     let raw_opcode: Result<u32, &str> = resolve_opcode(instruction);
@@ -63,40 +78,45 @@ fn translate(instruction: &mut Instruction) -> Result<String, String> {
     debug!("Raw flag value {:?}", raw_flags);
     debug!("Instruction opcode {:?}", raw_opcode);
 
-    let op_code = raw_opcode.map_err(|e| errs.push(e.to_owned()));
-    let operands = raw_operands.map_err(|e| errs.push(e));
-    let flags = raw_flags.map_err(|e| errs.push(e));
+    let line = instruction.src_line_num;
+    let span = instruction.spans.mnemonic;
+    let internal_error = |e: String| {
+        Diagnostic::error(Code::InvalidFormat, e).at_line(line).with_span(span)
+    };
+    let op_code = raw_opcode.map_err(|e| errs.push(internal_error(e.to_owned())));
+    let operands = raw_operands.map_err(|e| errs.extend(e));
+    let flags = raw_flags.map_err(|e| errs.push(internal_error(e)));
 
     if errs.len() > 0 {
-        return Err(errs.join("\n "));
+        return Err(errs);
     }
 
     // The operands are numeric if it's a normal instruction, not a directive
-    let operands: u32 = u32::from_str_radix(&operands.unwrap(), 16)
-        .map_err(|e| errs.push("Failed to parse operand ".to_owned() + &e.to_string()))
-        .unwrap_or(0);
+    let operands: u32 = u32::from_str_radix(&operands.unwrap(), 16).unwrap_or(0);
 
     let numeric_val = op_code.unwrap() + flags.unwrap();
     Ok(string_from_object_code(numeric_val + operands, (instruction.get_format()) as u8))
 }
 
 /// Returns the hex value of operands
-fn resolve_incomplete_operands(instruction: &mut Instruction) -> Result<String, String> {
+fn resolve_incomplete_operands(session: &Session,
+                               instruction: &mut Instruction)
+                               -> Result<String, Vec<Diagnostic>> {
     // Convert immediate and indirect operands to a basic forms -> Raw
     let mut raws: String = String::new();
-    let mut errs: Vec<String> = Vec::new();
+    let mut errs: Vec<Diagnostic> = Vec::new();
     let op_vec = instruction.unwrap_operands();
 
     for operand in &op_vec {
         // TODO: do the same with this as the operand_parser
-        match parse_operand(instruction, &operand.val) {
+        match parse_operand(session, instruction, &operand.val) {
             Ok(mut raw) => raws.push_str(&mut raw),
             Err(e) => errs.push(e),
         }
     }
 
     if errs.len() > 0 {
-        return Err(format!("Found error while parsing operands {}", errs.join("\n ")));
+        return Err(errs);
     }
 
     Ok(raws)
@@ -104,53 +124,59 @@ fn resolve_incomplete_operands(instruction: &mut Instruction) -> Result<String,
 
 /// Get the opcode value from the instruction set table
 fn resolve_opcode(instr: &Instruction) -> Result<u32, &str> {
-
-    let instruction_set_def: AssemblyDef;
-
-    match instruction_set::fetch_instruction(&instr.mnemonic) {
-        Ok(inst) => instruction_set_def = inst,
-        Err(err) => return Err(err),
-    };
-
-    let op_code = instruction_set_def.get_opcode_value(instr.get_format());
-    Ok(op_code as u32)
+    match instr.statement {
+        Statement::Instruction(opcode) => {
+            Ok(opcode.definition().get_opcode_value(instr.get_format()) as u32)
+        }
+        _ => Err("directives don't have an opcode"),
+    }
 }
 
-fn resolve_base_directive(instr: &Instruction) -> Result<(), String> {
-    let mnemonic = instr.mnemonic.to_uppercase();
+fn resolve_base_directive(session: &mut Session, instr: &Instruction) -> Result<(), Diagnostic> {
     let locctr = instr.locctr;
 
-    if mnemonic == "BASE" {
-        if let Value::Label(val) = instr.get_first_operand().val {
-
-            /// Returns the location of the symbol from the
-            /// symtab, the result is returned as i32 (it'll be envolved in subtraction)
-            ///  as it'll be subtracted from the locctr
-            match get_symbol(&val, &instr.csect) {
+    match instr.statement {
+        // The operand didn't parse, it's already reported
+        Statement::Base(ref val) if val.is_empty() => {}
+        Statement::Base(ref val) => {
+            // Returns the location of the symbol from the
+            // symtab, the result is returned as i32 (it'll be envolved in subtraction)
+            //  as it'll be subtracted from the locctr
+            match session.symbols.get_symbol(val, &instr.csect) {
                 Ok(sym) => {
                     if sym.symbol_type == SymbolType::Imported {
-                        return Err(format!("Base can't be an imported symbol {{ {:?} }}", instr));
+                        return Err(Diagnostic::error(Code::ImportedSymbolMisuse,
+                                                     format!("base can't be the imported \
+                                                              symbol `{}`",
+                                                             val))
+                            .at_line(instr.src_line_num)
+                            .with_span(instr.spans.operands));
                     }
-                    set_base(locctr, sym.get_address())
+                    session.bases.set_base(locctr, sym.get_address())
+                }
+                Err(e) => {
+                    return Err(e.at_line(instr.src_line_num).with_span(instr.spans.operands))
                 }
-                Err(e) => return Err(format!("Invalid base {} {}", val, e)),
             }
         }
-    } else if mnemonic == "NOBASE" {
-        end_base(locctr);
-    } else {
-        return Err(format!("Unknown instruction {:?}", instr));
+        Statement::Nobase => {
+            if let Err(e) = session.bases.end_base(locctr) {
+                return Err(Diagnostic::error(Code::InvalidLine, e.to_owned())
+                    .at_line(instr.src_line_num)
+                    .with_span(instr.spans.mnemonic)
+                    .with_help("a base is ended once, remove the second NOBASE".to_owned()));
+            }
+        }
+        _ => {
+            return Err(Diagnostic::error(Code::UnknownMnemonic,
+                                         format!("unknown base directive `{}`",
+                                                 instr.mnemonic()))
+                .at_line(instr.src_line_num))
+        }
     }
     Ok(())
 }
 
-fn is_directive(instr: &Instruction) -> bool {
-    if let Ok(_) = instruction_set::fetch_directive(&instr.mnemonic) {
-        return true;
-    }
-    return false;
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -159,10 +185,11 @@ mod tests {
     use operands::{Value, OperandType};
     use register::Register;
     use instruction::AsmOperand;
+    use instruction_set::{Mnemonic, Opcode};
 
     #[test]
     fn test_resolve_op_code() {
-        let mut inst = Instruction::new_simple("ldx".to_owned());
+        let mut inst = Instruction::new_simple(Statement::Instruction(Opcode::Ldx));
 
         inst.set_format(formats::Format::One);
         assert_eq!(resolve_opcode(&inst).unwrap(), 0x04);
@@ -178,11 +205,11 @@ mod tests {
     fn test_resolve_regs() {
         let mut inst =
             Instruction::new(String::new(),
-                             "add".to_owned(),
+                             Statement::Instruction(Opcode::Add),
                              UnitOrPair::Unit(AsmOperand::new(OperandType::Register,
                                                               Value::Register(Register::B))));
 
-        let opr: String = resolve_incomplete_operands(&mut inst).unwrap();
+        let opr: String = resolve_incomplete_operands(&Session::new(), &mut inst).unwrap();
         assert_eq!(opr, "3");
     }
 
@@ -208,10 +235,33 @@ mod tests {
                                                                      Format::Four)
                                     ];
 
-        assert_eq!(translate(&mut instrs[0]).unwrap(), "290000");
-        assert_eq!(translate(&mut instrs[1]).unwrap(), "B850");
-        assert_eq!(translate(&mut instrs[2]).unwrap(), "010003");
-        assert_eq!(translate(&mut instrs[3]).unwrap(), "75101000");
+        let mut session = Session::new();
+        assert_eq!(translate(&mut session, &mut instrs[0]).unwrap(), "290000");
+        assert_eq!(translate(&mut session, &mut instrs[1]).unwrap(), "B850");
+        assert_eq!(translate(&mut session, &mut instrs[2]).unwrap(), "010003");
+        assert_eq!(translate(&mut session, &mut instrs[3]).unwrap(), "75101000");
+    }
+
+    #[test]
+    fn expression_operands() {
+        use filehandler::FileHandler;
+        use pass_one::pass_one::pass_one;
+
+        let mut session = Session::new();
+        let prog = FileHandler::open("src/tests/operand_expressions.asm".to_owned()).unwrap()
+            .parse_file()
+            .unwrap();
+        let (_, mut prog) = pass_one(&mut session, prog).unwrap();
+        assert!(pass_two(&mut session, &mut prog).is_empty());
+
+        let records = prog.all_records();
+        let mut lines = records.lines().skip(1);
+        // LDA TABLE+3, STA TABLE-1,X, LDT #SIZE*2, +LDA TABLE+3, +LDT #SIZE*2, J @RETADR+3
+        assert_eq!(lines.next().unwrap(),
+                   "T0000001A17201703201A0500000FA01075001403100020751000143E2003");
+        // Only the relative format 4 operand is relocated
+        assert_eq!(lines.next().unwrap(), "M00001005+OPEXPR");
+        assert!(lines.next().unwrap().starts_with('E'));
     }
 
     fn create_instruction(mnemonic: &str,
@@ -219,7 +269,8 @@ mod tests {
                           format: Format)
                           -> Instruction {
 
-        let mut instr = Instruction::new(String::new(), mnemonic.to_owned(), operands);
+        let statement = Statement::parse(Mnemonic::parse(mnemonic).unwrap(), &operands).unwrap();
+        let mut instr = Instruction::new(String::new(), statement, operands);
         instr.set_format(format);
         instr
     }
@@ -4,37 +4,37 @@
 ///
 use instruction::Instruction;
 use formats::Format;
+use operands::OperandType;
 use instruction_set::*;
+use statement::Statement;
 use unit_or_pair::*;
+use diagnostic::{Diagnostic, Code};
 
-pub fn validate_semantics(instr: &mut Instruction) -> Result<(), String> {
-    let mut errs: Vec<String> = Vec::new();
+pub fn validate_semantics(instr: &mut Instruction) -> Result<(), Vec<Diagnostic>> {
+    let mut errs: Vec<Diagnostic> = Vec::new();
 
-    if let Ok(def) = fetch_directive(&instr.mnemonic) {
-        // Directives are matched while reading the source code
-        // FIXME: check operands of directives
-        if def.has_valid_operands(&instr.operands) {
-            return Ok(());
-        }
-        return Ok(());
-    }
-
-    let def: AssemblyDef;
-    match fetch_instruction(&instr.mnemonic) {
-        Ok(instr) => def = instr, 
-        Err(_) => return Err("Isn't an instruction or a directive".to_owned()),
-    }
+    let def: AssemblyDef = match instr.statement {
+        Statement::Instruction(opcode) => opcode.definition(),
+        // The operands of directives are checked while reading the source code
+        _ => return Ok(()),
+    };
 
     if def.has_valid_operands(&instr.operands) == false {
-        errs.push(format!("Operands for this instruction are invalid {{ {:?} }} , \nexpected {{ \
-                           {:?} }}",
-                          instr,
-                          def));
+        errs.push(Diagnostic::error(Code::InvalidOperands,
+                                    format!("invalid operands for `{}`", def.mnemonic))
+            .at_line(instr.src_line_num)
+            .with_span(instr.spans.operands.or(instr.spans.mnemonic))
+            .with_help(format!("`{}` expects {}", def.mnemonic, describe_operands(&def))));
     }
 
     // Check format correctness
     if def.match_format(&instr.get_format()) == false {
-        errs.push("Invalid instruction format".to_owned());
+        errs.push(Diagnostic::error(Code::InvalidFormat,
+                                    format!("`{}` can't be used in format {}",
+                                            def.mnemonic,
+                                            instr.get_format() as u8))
+            .at_line(instr.src_line_num)
+            .with_span(instr.spans.mnemonic));
     } else {
         // Format is matched correctly, adjust format 2 instructions with 1 register
         // check the docs of add_reg_a
@@ -48,8 +48,26 @@ pub fn validate_semantics(instr: &mut Instruction) -> Result<(), String> {
     }
 
     if errs.len() > 0 {
-        return Err(errs.join("\n"));
+        return Err(errs);
     }
 
     Ok(())
 }
+
+/// Human readable form of the operands an instruction set entry takes
+fn describe_operands(def: &AssemblyDef) -> String {
+    let operands = unwrap_to_vec(&def.operands);
+    if operands.is_empty() {
+        return "no operands".to_owned();
+    }
+    let names = operands.iter()
+        .map(|opr| {
+            match *opr {
+                OperandType::Register => "a register",
+                OperandType::Immediate if def.match_format(&Format::Two) => "a number",
+                _ => "a memory operand",
+            }
+        })
+        .collect::<Vec<&str>>();
+    names.join(" and ")
+}
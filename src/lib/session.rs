@@ -0,0 +1,47 @@
+/// An assembly session owns every table the passes fill in while assembling
+/// one program, assembling another program means creating another session
+use symbol_tables::MasterTable;
+use literal_table::LiteralTable;
+use base_table::BaseTable;
+use diagnostic::Diagnostic;
+
+#[derive(Debug, Default)]
+pub struct Session {
+    pub symbols: MasterTable,
+    pub literals: LiteralTable,
+    pub bases: BaseTable,
+    /// Errors stop the passes and are returned by them, warnings don't
+    /// so they're collected here
+    pub warnings: Vec<Diagnostic>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assembler::object_program_of;
+    use std::thread;
+
+    #[test]
+    fn sessions_dont_share_state() {
+        let first = object_program_of("src/tests/test2.asm");
+        let second = object_program_of("src/tests/test2.asm");
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn concurrent_sessions() {
+        let expected = object_program_of("src/tests/test2.asm");
+        let handles = (0..4)
+            .map(|_| thread::spawn(|| object_program_of("src/tests/test2.asm")))
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            assert_eq!(handle.join().unwrap(), expected);
+        }
+    }
+}
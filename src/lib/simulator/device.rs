@@ -0,0 +1,71 @@
+/// The I/O devices of the machine, programs address them by a one byte
+/// number with TD, RD and WD, ex. `TD =X'F1'`
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+
+pub enum Device {
+    /// Bytes kept in memory, what the program writes can be read back
+    Buffer {
+        input: VecDeque<u8>,
+        output: Vec<u8>,
+    },
+    /// A host file or stream the program reads from
+    Reader(Box<dyn Read>),
+    /// A host file or stream the program writes to
+    Writer(Box<dyn Write>),
+}
+
+impl Device {
+    pub fn buffer(input: &[u8]) -> Device {
+        Device::Buffer {
+            input: input.iter().cloned().collect(),
+            output: Vec::new(),
+        }
+    }
+
+    /// What the program wrote to an in memory device
+    pub fn output(&self) -> &[u8] {
+        match *self {
+            Device::Buffer { ref output, .. } => output,
+            _ => &[],
+        }
+    }
+
+    /// Reads the next byte, the end of the input reads as 0 like the
+    /// end of a record in the textbook programs
+    pub fn read(&mut self) -> io::Result<u8> {
+        match *self {
+            Device::Buffer { ref mut input, .. } => Ok(input.pop_front().unwrap_or(0)),
+            Device::Reader(ref mut reader) => {
+                let mut byte = [0u8];
+                match reader.read(&mut byte)? {
+                    0 => Ok(0),
+                    _ => Ok(byte[0]),
+                }
+            }
+            Device::Writer(_) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "it's an output device"))
+            }
+        }
+    }
+
+    pub fn write(&mut self, byte: u8) -> io::Result<()> {
+        match *self {
+            Device::Buffer { ref mut output, .. } => {
+                output.push(byte);
+                Ok(())
+            }
+            Device::Writer(ref mut writer) => writer.write_all(&[byte]),
+            Device::Reader(_) => {
+                Err(io::Error::new(io::ErrorKind::InvalidInput, "it's an input device"))
+            }
+        }
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Device::Writer(ref mut writer) => writer.flush(),
+            _ => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,707 @@
+/// SIC/XE machine, runs a loaded program one instruction at a time
+///
+/// The program starts with L holding RETURN_ADDRESS so the RSUB or
+/// `J @RETADR` that ends a textbook program stops the machine, a jump to
+/// the instruction itself (`HALT J HALT`) stops it as well.
+use std::collections::HashMap;
+use std::fmt;
+use instruction_set::fetch_by_opcode;
+use formats::Format;
+use unit_or_pair::UnitOrPair;
+use register::Register;
+use htme::reader::MEMORY_SIZE;
+use linker::loader::LinkedProgram;
+use simulator::device::Device;
+
+/// Returning to this address ends the program
+pub const RETURN_ADDRESS: u32 = 0xFFFFFF;
+
+const WORD_MASK: u32 = 0xFFFFFF;
+/// Condition codes kept in SW
+const CC_EQUAL: u32 = 0x00;
+const CC_LESS: u32 = 0x40;
+const CC_GREATER: u32 = 0x80;
+const CC_MASK: u32 = 0xC0;
+
+/// Register numbers of format 2 instructions that aren't in `Register`
+const PC: u8 = 8;
+const SW: u8 = 9;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Fault {
+    InvalidOpcode { address: u32, opcode: u8 },
+    AddressOutOfRange { address: u32, pc: u32 },
+    InvalidRegister { register: u8, pc: u32 },
+    DivisionByZero { pc: u32 },
+    UnconnectedDevice { device: u8, pc: u32 },
+    Io { device: u8, message: String },
+    StepLimit(u64),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::InvalidOpcode { address, opcode } => {
+                write!(f, "invalid opcode {:02X} at {:06X}", opcode, address)
+            }
+            Fault::AddressOutOfRange { address, pc } => {
+                write!(f, "address {:06X} is out of memory at {:06X}", address, pc)
+            }
+            Fault::InvalidRegister { register, pc } => {
+                write!(f, "invalid register {} at {:06X}", register, pc)
+            }
+            Fault::DivisionByZero { pc } => write!(f, "division by zero at {:06X}", pc),
+            Fault::UnconnectedDevice { device, pc } => {
+                write!(f, "device {:02X} isn't connected, used at {:06X}", device, pc)
+            }
+            Fault::Io { device, ref message } => write!(f, "device {:02X}: {}", device, message),
+            Fault::StepLimit(steps) => write!(f, "the program didn't stop after {} steps", steps),
+        }
+    }
+}
+
+/// Why the program stopped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// Returned to RETURN_ADDRESS
+    Returned,
+    /// Jumped to the instruction itself
+    Halted,
+}
+
+/// The operand of a format 3/4 instruction once the addressing mode is applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operand {
+    Immediate(u32),
+    Memory(u32),
+}
+
+pub struct Machine {
+    memory: Vec<u8>,
+    /// A, X, L, B, S and T by register number, 24 bits each
+    registers: [u32; 6],
+    pub f: f64,
+    pub pc: u32,
+    pub sw: u32,
+    devices: HashMap<u8, Device>,
+    /// Instructions executed so far
+    pub steps: u64,
+}
+
+impl Default for Machine {
+    fn default() -> Machine {
+        Machine::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Machine {
+        Machine {
+            memory: vec![0; MEMORY_SIZE as usize],
+            registers: [0; 6],
+            f: 0.0,
+            pc: 0,
+            sw: 0,
+            devices: HashMap::new(),
+            steps: 0,
+        }
+    }
+
+    pub fn connect(&mut self, number: u8, device: Device) {
+        self.devices.insert(number, device);
+    }
+
+    pub fn device(&self, number: u8) -> Option<&Device> {
+        self.devices.get(&number)
+    }
+
+    /// Copies a linked program to memory and points PC at its entry
+    pub fn load(&mut self, program: &LinkedProgram) -> Result<(), Fault> {
+        let start = program.start as usize;
+        let end = start + program.memory().len();
+        if end > self.memory.len() {
+            return Err(Fault::AddressOutOfRange {
+                address: end as u32,
+                pc: program.entry,
+            });
+        }
+        self.memory[start..end].copy_from_slice(program.memory());
+        self.pc = program.entry;
+        self.set_register(Register::L, RETURN_ADDRESS);
+        Ok(())
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    /// The value of a register, F is converted to an integer the way FIX does
+    pub fn register(&self, register: Register) -> u32 {
+        match register {
+            Register::F => (self.f as i32) as u32 & WORD_MASK,
+            _ => self.registers[register as usize],
+        }
+    }
+
+    pub fn set_register(&mut self, register: Register, value: u32) {
+        match register {
+            Register::F => self.f = signed(value) as f64,
+            _ => self.registers[register as usize] = value & WORD_MASK,
+        }
+    }
+
+    /// Runs until the program stops, at most max_steps instructions
+    pub fn run(&mut self, max_steps: u64) -> Result<Stop, Fault> {
+        let result = self.run_steps(max_steps);
+        for (&number, device) in self.devices.iter_mut() {
+            device.flush().map_err(|e| {
+                    Fault::Io {
+                        device: number,
+                        message: e.to_string(),
+                    }
+                })?;
+        }
+        result
+    }
+
+    fn run_steps(&mut self, max_steps: u64) -> Result<Stop, Fault> {
+        for _ in 0..max_steps {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+        Err(Fault::StepLimit(max_steps))
+    }
+
+    /// Executes a single instruction, returns why the program stopped if it did
+    pub fn step(&mut self) -> Result<Option<Stop>, Fault> {
+        let pc = self.pc;
+        let opcode = self.byte(pc)?;
+        let def = fetch_by_opcode(opcode).ok_or(Fault::InvalidOpcode {
+                address: pc,
+                opcode,
+            })?;
+        self.steps += 1;
+
+        match def.format {
+            UnitOrPair::Unit(Format::One) => {
+                self.pc = pc + 1;
+                self.format_one(&def.mnemonic);
+            }
+            UnitOrPair::Unit(Format::Two) => {
+                let registers = self.byte(pc + 1)?;
+                self.pc = pc + 2;
+                self.format_two(&def.mnemonic, registers >> 4, registers & 0xF, pc)?;
+            }
+            _ => {
+                let operand = self.operand(pc, opcode)?;
+                self.format_three(&def.mnemonic, operand, pc)?;
+            }
+        }
+
+        if self.pc == RETURN_ADDRESS {
+            Ok(Some(Stop::Returned))
+        } else if self.pc == pc {
+            Ok(Some(Stop::Halted))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn format_one(&mut self, mnemonic: &str) {
+        match mnemonic {
+            "FIX" => self.registers[Register::A as usize] = (self.f as i32) as u32 & WORD_MASK,
+            "FLOAT" => self.f = signed(self.registers[Register::A as usize]) as f64,
+            // Floats are always normalized, the I/O channels aren't simulated
+            _ => {}
+        }
+    }
+
+    fn format_two(&mut self, mnemonic: &str, r1: u8, r2: u8, pc: u32) -> Result<(), Fault> {
+        // SVC takes a number, the shifts take a count instead of a second register
+        let first = match mnemonic {
+            "SVC" => 0,
+            _ => signed(self.get(r1, pc)?),
+        };
+        let second = match mnemonic {
+            "ADDR" | "SUBR" | "MULR" | "DIVR" | "COMPR" | "RMO" => signed(self.get(r2, pc)?),
+            _ => 0,
+        };
+
+        match mnemonic {
+            "ADDR" => self.set(r2, second.wrapping_add(first) as u32, pc)?,
+            "SUBR" => self.set(r2, second.wrapping_sub(first) as u32, pc)?,
+            "MULR" => self.set(r2, second.wrapping_mul(first) as u32, pc)?,
+            "DIVR" => {
+                if first == 0 {
+                    return Err(Fault::DivisionByZero { pc });
+                }
+                self.set(r2, second.wrapping_div(first) as u32, pc)?
+            }
+            "COMPR" => self.compare(first, second),
+            "CLEAR" => self.set(r1, 0, pc)?,
+            "RMO" => self.set(r2, first as u32, pc)?,
+            "TIXR" => {
+                let x = signed(self.registers[Register::X as usize]).wrapping_add(1);
+                self.registers[Register::X as usize] = x as u32 & WORD_MASK;
+                self.compare(x, first);
+            }
+            // The second half of the byte holds the shift count minus one
+            "SHIFTL" => {
+                let value = first as u32 & WORD_MASK;
+                let count = (r2 as u32 + 1) % 24;
+                let shifted = (value << count | value >> ((24 - count) % 24)) & WORD_MASK;
+                self.set(r1, shifted, pc)?
+            }
+            "SHIFTR" => self.set(r1, (first >> (r2 as u32 + 1).min(23)) as u32, pc)?,
+            // There's no supervisor to call
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn format_three(&mut self, mnemonic: &str, operand: Operand, pc: u32) -> Result<(), Fault> {
+        let target = match operand {
+            Operand::Immediate(value) |
+            Operand::Memory(value) => value,
+        };
+
+        match mnemonic {
+            "LDA" | "LDX" | "LDL" | "LDB" | "LDS" | "LDT" => {
+                let value = self.word_operand(operand)?;
+                self.registers[load_register(mnemonic) as usize] = value;
+            }
+            "LDCH" => {
+                let value = self.byte_operand(operand)?;
+                let a = &mut self.registers[Register::A as usize];
+                *a = *a & 0xFFFF00 | value as u32;
+            }
+            "LDF" => self.f = self.float_operand(operand)?,
+            "STA" | "STX" | "STL" | "STB" | "STS" | "STT" => {
+                let value = self.registers[load_register(mnemonic) as usize];
+                self.write_word(target, value)?
+            }
+            "STCH" => {
+                let value = self.registers[Register::A as usize] as u8;
+                self.write_byte(target, value)?
+            }
+            "STF" => {
+                let bits = float_to_bits(self.f);
+                for i in 0..6 {
+                    self.write_byte(target + i, (bits >> (40 - 8 * i)) as u8)?;
+                }
+            }
+            "STSW" => {
+                let value = self.sw;
+                self.write_word(target, value)?
+            }
+            "ADD" | "SUB" | "MUL" | "DIV" | "AND" | "OR" | "COMP" => {
+                let a = signed(self.registers[Register::A as usize]);
+                let value = signed(self.word_operand(operand)?);
+                let result = match mnemonic {
+                    "ADD" => a.wrapping_add(value),
+                    "SUB" => a.wrapping_sub(value),
+                    "MUL" => a.wrapping_mul(value),
+                    "DIV" if value == 0 => return Err(Fault::DivisionByZero { pc }),
+                    "DIV" => a.wrapping_div(value),
+                    "AND" => a & value,
+                    "OR" => a | value,
+                    _ => {
+                        self.compare(a, value);
+                        return Ok(());
+                    }
+                };
+                self.registers[Register::A as usize] = result as u32 & WORD_MASK;
+            }
+            "ADDF" | "SUBF" | "MULF" | "DIVF" | "COMPF" => {
+                let value = self.float_operand(operand)?;
+                self.f = match mnemonic {
+                    "ADDF" => self.f + value,
+                    "SUBF" => self.f - value,
+                    "MULF" => self.f * value,
+                    "DIVF" if value == 0.0 => return Err(Fault::DivisionByZero { pc }),
+                    "DIVF" => self.f / value,
+                    _ => {
+                        self.sw = self.sw & !CC_MASK | condition(self.f.partial_cmp(&value));
+                        return Ok(());
+                    }
+                };
+            }
+            "TIX" => {
+                let x = signed(self.registers[Register::X as usize]).wrapping_add(1);
+                self.registers[Register::X as usize] = x as u32 & WORD_MASK;
+                let value = signed(self.word_operand(operand)?);
+                self.compare(x, value);
+            }
+            "J" => self.pc = target,
+            "JEQ" if self.sw & CC_MASK == CC_EQUAL => self.pc = target,
+            "JLT" if self.sw & CC_MASK == CC_LESS => self.pc = target,
+            "JGT" if self.sw & CC_MASK == CC_GREATER => self.pc = target,
+            "JSUB" => {
+                self.registers[Register::L as usize] = self.pc;
+                self.pc = target;
+            }
+            "RSUB" => self.pc = self.registers[Register::L as usize],
+            "TD" => {
+                let number = self.byte_operand(operand)?;
+                if !self.devices.contains_key(&number) {
+                    return Err(Fault::UnconnectedDevice {
+                        device: number,
+                        pc,
+                    });
+                }
+                // Connected devices are always ready
+                self.sw = self.sw & !CC_MASK | CC_LESS;
+            }
+            "RD" => {
+                let number = self.byte_operand(operand)?;
+                let byte = self.device_mut(number, pc)?
+                    .read()
+                    .map_err(|e| {
+                        Fault::Io {
+                            device: number,
+                            message: e.to_string(),
+                        }
+                    })?;
+                let a = &mut self.registers[Register::A as usize];
+                *a = *a & 0xFFFF00 | byte as u32;
+            }
+            "WD" => {
+                let number = self.byte_operand(operand)?;
+                let byte = self.registers[Register::A as usize] as u8;
+                self.device_mut(number, pc)?
+                    .write(byte)
+                    .map_err(|e| {
+                        Fault::Io {
+                            device: number,
+                            message: e.to_string(),
+                        }
+                    })?;
+            }
+            // Conditional jumps that aren't taken, the privileged
+            // instructions (LPS, STI, SSK) have nothing to act on
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Decodes the address of a format 3/4 instruction and moves PC past it
+    fn operand(&mut self, pc: u32, opcode: u8) -> Result<Operand, Fault> {
+        let first = self.byte(pc + 1)? as u32;
+        let second = self.byte(pc + 2)? as u32;
+        let indexed = first & 0x80 != 0;
+        let index = if indexed { self.registers[Register::X as usize] } else { 0 };
+
+        // Standard SIC, a 15-bit address
+        if opcode & 0x03 == 0 {
+            self.pc = pc + 3;
+            return Ok(Operand::Memory(((first & 0x7F) << 8 | second) + index));
+        }
+
+        let base_relative = first & 0x40 != 0;
+        let pc_relative = first & 0x20 != 0;
+        let extended = first & 0x10 != 0;
+
+        let address = if extended {
+            let third = self.byte(pc + 3)? as u32;
+            self.pc = pc + 4;
+            (first & 0x0F) << 16 | second << 8 | third
+        } else {
+            self.pc = pc + 3;
+            let disp = (first & 0x0F) << 8 | second;
+            if pc_relative {
+                // 12-bit two's complement displacement
+                let disp = ((disp << 20) as i32 >> 20) as u32;
+                self.pc.wrapping_add(disp)
+            } else if base_relative {
+                self.registers[Register::B as usize] + disp
+            } else {
+                disp
+            }
+        };
+        let address = address.wrapping_add(index) & WORD_MASK;
+
+        match opcode & 0x03 {
+            1 => Ok(Operand::Immediate(address)),
+            2 => Ok(Operand::Memory(self.word(address)?)),
+            _ => Ok(Operand::Memory(address)),
+        }
+    }
+
+    fn word_operand(&self, operand: Operand) -> Result<u32, Fault> {
+        match operand {
+            Operand::Immediate(value) => Ok(value),
+            Operand::Memory(address) => self.word(address),
+        }
+    }
+
+    fn byte_operand(&self, operand: Operand) -> Result<u8, Fault> {
+        match operand {
+            Operand::Immediate(value) => Ok(value as u8),
+            Operand::Memory(address) => self.byte(address),
+        }
+    }
+
+    fn float_operand(&self, operand: Operand) -> Result<f64, Fault> {
+        match operand {
+            Operand::Immediate(value) => Ok(signed(value) as f64),
+            Operand::Memory(address) => {
+                let mut bits = 0u64;
+                for i in 0..6 {
+                    bits = bits << 8 | self.byte(address + i)? as u64;
+                }
+                Ok(float_from_bits(bits))
+            }
+        }
+    }
+
+    fn byte(&self, address: u32) -> Result<u8, Fault> {
+        self.memory.get(address as usize).cloned().ok_or(Fault::AddressOutOfRange {
+            address,
+            pc: self.pc,
+        })
+    }
+
+    fn word(&self, address: u32) -> Result<u32, Fault> {
+        Ok((self.byte(address)? as u32) << 16 | (self.byte(address + 1)? as u32) << 8 |
+           self.byte(address + 2)? as u32)
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) -> Result<(), Fault> {
+        let pc = self.pc;
+        match self.memory.get_mut(address as usize) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(Fault::AddressOutOfRange { address, pc }),
+        }
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) -> Result<(), Fault> {
+        self.write_byte(address, (value >> 16) as u8)?;
+        self.write_byte(address + 1, (value >> 8) as u8)?;
+        self.write_byte(address + 2, value as u8)
+    }
+
+    /// A register by its number in a format 2 instruction
+    fn get(&self, register: u8, pc: u32) -> Result<u32, Fault> {
+        match register {
+            0..=5 => Ok(self.registers[register as usize]),
+            6 => Ok((self.f as i32) as u32 & WORD_MASK),
+            PC => Ok(self.pc),
+            SW => Ok(self.sw),
+            _ => Err(Fault::InvalidRegister { register, pc }),
+        }
+    }
+
+    fn set(&mut self, register: u8, value: u32, pc: u32) -> Result<(), Fault> {
+        let value = value & WORD_MASK;
+        match register {
+            0..=5 => self.registers[register as usize] = value,
+            6 => self.f = signed(value) as f64,
+            PC => self.pc = value,
+            SW => self.sw = value,
+            _ => return Err(Fault::InvalidRegister { register, pc }),
+        }
+        Ok(())
+    }
+
+    fn compare(&mut self, first: i32, second: i32) {
+        self.sw = self.sw & !CC_MASK | condition(first.partial_cmp(&second));
+    }
+
+    fn device_mut(&mut self, number: u8, pc: u32) -> Result<&mut Device, Fault> {
+        self.devices.get_mut(&number).ok_or(Fault::UnconnectedDevice {
+            device: number,
+            pc,
+        })
+    }
+
+    /// The registers in the order the textbook lists them
+    pub fn dump(&self) -> String {
+        format!("A={:06X} X={:06X} L={:06X} B={:06X} S={:06X} T={:06X} F={:012X} \
+                 PC={:06X} SW={:06X}",
+                self.registers[Register::A as usize],
+                self.registers[Register::X as usize],
+                self.registers[Register::L as usize],
+                self.registers[Register::B as usize],
+                self.registers[Register::S as usize],
+                self.registers[Register::T as usize],
+                float_to_bits(self.f),
+                self.pc,
+                self.sw)
+    }
+}
+
+/// The register a load or store instruction works on
+fn load_register(mnemonic: &str) -> Register {
+    match &mnemonic[2..] {
+        "X" => Register::X,
+        "L" => Register::L,
+        "B" => Register::B,
+        "S" => Register::S,
+        "T" => Register::T,
+        _ => Register::A,
+    }
+}
+
+fn condition(ordering: Option<::std::cmp::Ordering>) -> u32 {
+    match ordering {
+        Some(::std::cmp::Ordering::Less) => CC_LESS,
+        Some(::std::cmp::Ordering::Greater) => CC_GREATER,
+        _ => CC_EQUAL,
+    }
+}
+
+/// Sign extends a 24-bit word
+fn signed(word: u32) -> i32 {
+    ((word << 8) as i32) >> 8
+}
+
+/// SIC/XE floats are 48 bits, a sign bit, an 11-bit exponent biased by
+/// 1024 and a 36-bit fraction with the binary point before its first bit
+fn float_from_bits(bits: u64) -> f64 {
+    let fraction = bits & ((1 << 36) - 1);
+    if fraction == 0 {
+        return 0.0;
+    }
+    let exponent = ((bits >> 36) & 0x7FF) as i32 - 1024;
+    let value = fraction as f64 / (1u64 << 36) as f64 * 2f64.powi(exponent);
+    if bits >> 47 & 1 == 1 { -value } else { value }
+}
+
+fn float_to_bits(value: f64) -> u64 {
+    if value == 0.0 || !value.is_finite() {
+        return 0;
+    }
+    let sign = if value < 0.0 { 1u64 << 47 } else { 0 };
+    let mut fraction = value.abs();
+    let mut exponent = 0i32;
+    while fraction >= 1.0 {
+        fraction /= 2.0;
+        exponent += 1;
+    }
+    while fraction < 0.5 {
+        fraction *= 2.0;
+        exponent -= 1;
+    }
+    let exponent = (exponent + 1024).clamp(0, 0x7FF) as u64;
+    sign | exponent << 36 | (fraction * (1u64 << 36) as f64) as u64 & ((1 << 36) - 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assembler::object_program_of;
+    use linker::loader::link;
+
+    /// Loads raw object code at address 0
+    fn boot(code: &[u8]) -> Machine {
+        let mut machine = Machine::new();
+        machine.memory[..code.len()].copy_from_slice(code);
+        machine.set_register(Register::L, RETURN_ADDRESS);
+        machine
+    }
+
+    /// Copies the input from device F1 to device 05, see test2.asm
+    fn copy_records(path: &str, progaddr: u32) {
+        let objects = vec![(path.to_owned(), object_program_of(path))];
+        let program = link(&objects, progaddr).unwrap();
+
+        let mut machine = Machine::new();
+        machine.load(&program).unwrap();
+        machine.connect(0xF1, Device::buffer(b"HELLO"));
+        machine.connect(0x05, Device::buffer(b""));
+
+        assert_eq!(machine.run(100_000), Ok(Stop::Returned));
+        assert_eq!(machine.device(0x05).unwrap().output(), b"HELLOEOF");
+    }
+
+    #[test]
+    fn run_copy_program() {
+        copy_records("src/tests/test2.asm", 0);
+        // Relocated by the M records
+        copy_records("src/tests/test2.asm", 0x3000);
+    }
+
+    #[test]
+    fn run_linked_control_sections() {
+        copy_records("src/tests/csect.asm", 0x4000);
+    }
+
+    #[test]
+    fn addressing_modes() {
+        // LDA #5, +LDX #0x10 , LDA 3,X (base 0), LDA @0x20
+        let mut code = vec![0x01, 0x00, 0x05, 0x05, 0x10, 0x00, 0x10, 0x03, 0x80, 0x03, 0x02,
+                            0x00, 0x20];
+        code.resize(0x30, 0);
+        code[0x13..0x16].copy_from_slice(&[0x00, 0x00, 0x2A]);
+        code[0x20..0x23].copy_from_slice(&[0x00, 0x00, 0x13]);
+        let mut machine = boot(&code);
+
+        machine.step().unwrap();
+        assert_eq!(machine.register(Register::A), 5);
+        machine.step().unwrap();
+        assert_eq!(machine.register(Register::X), 0x10);
+        machine.step().unwrap();
+        assert_eq!(machine.register(Register::A), 0x2A);
+        machine.step().unwrap();
+        assert_eq!(machine.register(Register::A), 0x2A);
+        assert_eq!(machine.pc, 13);
+    }
+
+    #[test]
+    fn registers_and_conditions() {
+        // LDA #3, CLEAR S, SUBR A,S, COMPR S,A, JLT HALT, an invalid opcode, HALT J HALT
+        let code = [0x01, 0x00, 0x03, 0xB4, 0x40, 0x94, 0x04, 0xA0, 0x40, 0x3B, 0x20, 0x03,
+                    0xFF, 0x00, 0x00, 0x3F, 0x2F, 0xFD];
+        let mut machine = boot(&code);
+
+        assert_eq!(machine.run(10), Ok(Stop::Halted));
+        assert_eq!(machine.register(Register::S), 0xFFFFFD);
+        assert_eq!(machine.sw & CC_MASK, CC_LESS);
+        assert_eq!(machine.pc, 15);
+    }
+
+    #[test]
+    fn shifts() {
+        // LDA #0x800001, SHIFTL A,4, SHIFTR A,8 (counts are stored minus one)
+        let mut machine = boot(&[0x01, 0x00, 0x00, 0xA4, 0x03, 0xA8, 0x07]);
+        machine.step().unwrap();
+        machine.set_register(Register::A, 0x800001);
+        machine.step().unwrap();
+        assert_eq!(machine.register(Register::A), 0x000018);
+        machine.set_register(Register::A, 0x800000);
+        machine.step().unwrap();
+        assert_eq!(machine.register(Register::A), 0xFF8000);
+    }
+
+    #[test]
+    fn faults() {
+        let mut machine = boot(&[0xFF]);
+        assert_eq!(machine.step(),
+                   Err(Fault::InvalidOpcode {
+                       address: 0,
+                       opcode: 0xFF,
+                   }));
+
+        // TD #5
+        let mut machine = boot(&[0xE1, 0x00, 0x05]);
+        assert_eq!(machine.step(),
+                   Err(Fault::UnconnectedDevice { device: 5, pc: 0 }));
+
+        // J 3, J 0
+        let mut machine = boot(&[0x3F, 0x20, 0x00, 0x3F, 0x2F, 0xFA]);
+        assert_eq!(machine.run(50), Err(Fault::StepLimit(50)));
+    }
+
+    #[test]
+    fn float_format() {
+        for &value in &[1.0, -2.5, 0.1, 1e10] {
+            let round_trip = float_from_bits(float_to_bits(value));
+            assert!((round_trip - value).abs() <= value.abs() * 1e-10);
+        }
+        assert_eq!(float_to_bits(0.5), 0x400800000000);
+    }
+}
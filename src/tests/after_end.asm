@@ -0,0 +1,7 @@
+PROG    START   0
+FIRST   LDA     ZERO
+        J       FIRST
+ZERO    WORD    0
+        END     FIRST
+LATE    LDA     ZERO
+        RSUB
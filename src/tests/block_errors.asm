@@ -0,0 +1,8 @@
+BLKERR   START   0
+FIRST    RSUB
+         USE     CDATA
+TABLE    RESW    10
+         USE
+DIST     EQU     TABLE-FIRST
+         ORG     TABLE
+         END     FIRST
@@ -0,0 +1,13 @@
+CARDS    START   0
+. The object code of an old listing is next to the statements
+LOAD     MACRO   &REG,&VALUE
+         LD&REG  #&VALUE           01
+         MEND
+&I       SET     1
+         WHILE   (&I LE 2)
+T&I      LOAD    A,&I
+&I       SET     &I+1
+         ENDW
+RSUB     LOAD    X,3               050003
+         RSUB                      4F0000
+         END     RSUB
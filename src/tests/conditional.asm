@@ -0,0 +1,21 @@
+CONDS    START   0
+. Builds a table of squares and a device loop, DEV and DEBUG come from -D
+         IF      (&DEV EQ '')
+&DEV     SET     'F1'
+         ENDIF
+&SIZE    SET     4
+FIRST    LDX     #0
+LOOP     TD      =X'&DEV'
+         JEQ     LOOP
+         IF      (&DEBUG EQ 1)
+         LDA     #&SIZE
+         ELSE
+         LDA     #0
+         ENDIF
+         RSUB
+&I       SET     1
+         WHILE   (&I LE &SIZE)
+SQ&I     WORD    &I*&I
+&I       SET     &I+1
+         ENDW
+         END     FIRST
@@ -0,0 +1,19 @@
+CNDERR   START   0
+         IF      (&A EQ)
+         ENDIF
+         ELSE
+         ENDIF
+         IF      (1 EQ 1)
+         ELSE
+         ELSE
+         ENDIF
+         LDA     &NOPE
+X        SET     1
+&N       SET     0
+         WHILE   (&N GE 0)
+&N       SET     &N+1
+         ENDW
+         ENDW
+         WHILE
+         IF      (2 EQ 2)
+         END     CONDERR
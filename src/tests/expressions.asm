@@ -0,0 +1,13 @@
+EXPR     START   0
+FIRST    LDA     LENGTH
+         RSUB
+LENGTH   WORD    TABEND-TABLE
+HALF     WORD    (TABEND-TABLE)/2
+LAST     WORD    TABEND-3
+CHARS    WORD    C'AB'+1
+TABLE    RESW    10
+TABEND   EQU     *
+SIZE     EQU     (TABEND-TABLE)/3
+PREV     EQU     *-3
+SECOND   EQU     FIRST+3*1
+         END     FIRST
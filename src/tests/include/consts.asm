@@ -0,0 +1,2 @@
+. Constants of the buffers
+MAXLEN   EQU     4096
@@ -0,0 +1 @@
+         INCLUDE 'cycle_b.asm'
@@ -0,0 +1,2 @@
+. Includes the file that included it
+         INCLUDE 'cycle_a.asm'
@@ -0,0 +1,6 @@
+. Device routines shared by the programs
+WAIT     MACRO   &DEV
+$LOOP    TD      =X'&DEV'
+         JEQ     $LOOP
+         MEND
+         INCLUDE 'consts.asm'
@@ -0,0 +1 @@
+HALF     EQU     MAXLEN/2
@@ -0,0 +1,9 @@
+INCERR   START   0
+         INCLUDE 'include/cycle_a.asm'
+         INCLUDE 'missing.asm'
+         INCLUDE
+CONSTS   MACRO
+         INCLUDE 'include/consts.asm'
+         MEND
+         CONSTS
+         END     INCERR
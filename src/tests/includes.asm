@@ -0,0 +1,8 @@
+INCTST   START   0
+         INCLUDE 'include/devices.asm'
+FIRST    WAIT    F1
+         +LDT    #MAXLEN
+         COPY    'sizes.asm'
+         LDA     #HALF
+         RSUB
+         END     FIRST
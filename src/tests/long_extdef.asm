@@ -0,0 +1,5 @@
+PROG     START   0
+         EXTDEF  BUFFEND
+FIRST    RSUB
+BUFFEND  RESB    1
+         END     FIRST
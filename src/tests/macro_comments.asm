@@ -0,0 +1,9 @@
+COMNTS   START   0
+. Waits for a device, the comments of the body survive the expansion
+WAITDEV  MACRO   &DEV
+$LOOP    TD      =X'&DEV'    . wait for the device
+         JEQ     $LOOP       . not ready yet
+         MEND
+FIRST    WAITDEV F1
+         RSUB
+         END     FIRST
@@ -0,0 +1,15 @@
+MACERR   START   0
+LOAD     MACRO   &REG,&VALUE
+         LD&REG  #&VALUE
+         MEND
+         LOAD    A,1,2
+         LOAD    Q,5
+         MEND
+         MACRO   &X
+ENDLESS  MACRO
+         ENDLESS
+         MEND
+         ENDLESS
+UNENDED  MACRO   &A
+         LDA     &A
+         END     LOAD
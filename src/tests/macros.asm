@@ -0,0 +1,37 @@
+MACROS   START   0
+. Reads a record from a device into a buffer
+RDBUFF   MACRO   &INDEV,&BUFADR,&RECLTH
+         CLEAR   X
+         CLEAR   A
+         CLEAR   S
+         +LDT    #4096
+$LOOP    TD      =X'&INDEV'
+         JEQ     $LOOP
+         RD      =X'&INDEV'
+         COMPR   A,S
+         JEQ     $EXIT
+         STCH    &BUFADR,X
+         TIXR    T
+         JLT     $LOOP
+$EXIT    STX     &RECLTH
+         MEND
+. Writes a buffer to a device, the device is a keyword parameter
+WRBUFF   MACRO   &OUTDEV=05,&BUFADR,&RECLTH
+$LOOP    TD      =X'&OUTDEV'
+         JEQ     $LOOP
+         MEND
+. Copies a record, invoking the other macros
+COPYREC  MACRO   &FROM,&TO
+         RDBUFF  &FROM,BUFFER,LENGTH
+         WRBUFF  OUTDEV=&TO,BUFADR=BUFFER,RECLTH=LENGTH
+         MEND
+FIRST    STL     RETADR
+         RDBUFF  F1,BUFFER,LENGTH
+         COPYREC F3,06
+CLOOP    WRBUFF  BUFADR=BUFFER,RECLTH=LENGTH
+         J       CLOOP
+         LTORG
+RETADR   RESW    1
+LENGTH   RESW    1
+BUFFER   RESB    4096
+         END     FIRST
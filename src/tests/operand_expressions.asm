@@ -0,0 +1,14 @@
+OPEXPR   START   0
+FIRST    STL     RETADR
+         LDA     TABLE+3
+         LDX     #0
+         STA     TABLE-1,X
+         LDT     #SIZE*2
+         +LDA    TABLE+3
+         +LDT    #SIZE*2
+         J       @RETADR+3
+RETADR   RESW    1
+TABLE    RESW    10
+TABEND   EQU     *
+SIZE     EQU     (TABEND-TABLE)/3
+         END     FIRST
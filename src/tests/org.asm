@@ -0,0 +1,15 @@
+ORGTAB   START   0
+FIRST    LDX     #0
+         LDA     VALUE,X
+         RSUB
+STAB     RESB    1100
+         ORG     STAB
+SYMBOL   RESB    6
+VALUE    RESW    1
+FLAGS    RESB    2
+         ORG
+TOTAL    WORD    5
+         ORG     SYMBOL+1
+NAME     BYTE    C'ALPHA'
+         ORG
+         END     FIRST
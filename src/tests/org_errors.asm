@@ -0,0 +1,6 @@
+BADORG   START   0
+         ORG
+SIZE     EQU     2*5
+         ORG     SIZE
+FIRST    RSUB
+         END     FIRST
@@ -0,0 +1,5 @@
+SUM      START   0
+FIRST    RSUB
+LAST     RSUB
+BAD      EQU     FIRST+LAST
+         END     FIRST
@@ -0,0 +1,9 @@
+STRS     START   0
+. Quoted texts with spaces, periods and quotes
+FIRST    LDA     =C'A B'         . a literal with a space
+         LDCH    DOT
+         RSUB
+GREET    BYTE    C'HELLO WORLD'
+DOT      BYTE    C'A.B'          . a period isn't a comment
+QUOTE    BYTE    C'IT''S'
+         END     FIRST